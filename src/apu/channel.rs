@@ -0,0 +1,132 @@
+// The parts the sound channels have in common
+
+/// Turns a channel off once it's played for long enough. Clocked at 256 Hz
+/// by the frame sequencer.
+pub struct LengthCounter {
+    pub enabled: bool,
+    counter: u16,
+    max: u16, // 64, or 256 for the wave channel
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> LengthCounter {
+        LengthCounter { enabled: false, counter: 0, max }
+    }
+
+    /// Load the length from NRx1. Higher values play for less time.
+    pub fn load(&mut self, length: u8) {
+        self.counter = self.max - length as u16;
+    }
+
+    /// A triggered channel with no length left gets the whole length
+    pub fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// Returns whether the channel should be turned off
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+}
+
+/// Fades a channel's volume up or down. Clocked at 64 Hz by the frame
+/// sequencer.
+pub struct Envelope {
+    register: u8, // NRx2
+    pub volume: u8,
+    timer: u8,
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope::new()
+    }
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope { register: 0x00, volume: 0, timer: 0 }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.register = val;
+    }
+
+    /// Whether NRx2 turns the channel's DAC on: it's off when the top five
+    /// bits are all 0
+    pub fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    fn period(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    pub fn clock(&mut self) {
+        if self.period() == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            if self.register & 0x08 != 0 {
+                if self.volume < 15 { self.volume += 1 }
+            } else if self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_the_length_counter_turns_channels_off() {
+    let mut length = LengthCounter::new(64);
+    length.load(62);
+    length.enabled = true;
+    assert!(!length.clock());
+    assert!(length.clock());
+    assert!(!length.clock());
+    // Triggering with nothing left reloads the whole length
+    length.trigger();
+    for _ in 0..63 {
+        assert!(!length.clock());
+    }
+    assert!(length.clock());
+}
+
+#[test]
+fn test_the_envelope_fades() {
+    let mut envelope = Envelope::new();
+    envelope.write(0x22); // Volume 2, going down every other clock
+    envelope.trigger();
+    envelope.clock();
+    assert_eq!(envelope.volume, 2);
+    envelope.clock();
+    assert_eq!(envelope.volume, 1);
+    envelope.clock();
+    envelope.clock();
+    envelope.clock();
+    envelope.clock();
+    assert_eq!(envelope.volume, 0);
+    envelope.write(0xF9); // Volume 15, going up every clock
+    envelope.trigger();
+    envelope.clock();
+    assert_eq!(envelope.volume, 15);
+}
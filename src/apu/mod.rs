@@ -0,0 +1,321 @@
+// The Audio Processing Unit
+// --- ----- ---------- ----
+// Four channels: two square waves (the first with a frequency sweep), a
+// wave channel playing 4-bit samples out of wave RAM, and noise. Their
+// lengths, envelopes and sweep are clocked by the frame sequencer, which
+// steps at 512 Hz off DIV. Each channel's DAC turns its 0 ..= 15 output into
+// a voltage, and NR51 and NR50 mix those into the left and right speakers.
+
+pub mod channel;
+pub mod square;
+pub mod wave;
+pub mod noise;
+pub mod wav;
+
+use self::noise::Noise;
+use self::square::Square;
+use self::wave::Wave;
+
+/// The CPU runs at 4194304 Hz
+const CYCLES_PER_SECOND : u32 = 4_194_304;
+
+// Relative to 0xFF10
+const NR50 : u16 = 0x14;
+const NR51 : u16 = 0x15;
+const NR52 : u16 = 0x16;
+
+pub struct Apu {
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    nr50: u8, // Master volume
+    nr51: u8, // Which channels go to which speaker
+    powered: bool, // NR52 bit 7
+    frame_step: u8, // 0 ..= 7
+    last_div: u8,
+    // Samples are only kept once someone's asked for them
+    sample_rate: Option<u32>,
+    sample_counter: u32,
+    samples: Vec<f32>,
+    // Each channel on its own, before panning, if anyone wants them
+    channel_samples: Option<Vec<f32>>,
+}
+
+impl Default for Apu {
+    fn default() -> Apu {
+        Apu::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        Apu {
+            square1: Square::with_sweep(),
+            square2: Square::new(),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            nr50: 0x00,
+            nr51: 0x00,
+            powered: false,
+            frame_step: 0,
+            last_div: 0,
+            sample_rate: None,
+            sample_counter: 0,
+            samples: Vec::new(),
+            channel_samples: None,
+        }
+    }
+
+    /// Produce `rate` stereo samples a second, for `take_samples`.
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.sample_rate = Some(rate);
+        self.sample_counter = 0;
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+
+    /// Stop producing samples, throwing away any that haven't been taken.
+    pub fn stop_sampling(&mut self) {
+        self.sample_rate = None;
+        self.samples.clear();
+    }
+
+    /// Everything played since the last call, as interleaved left and right
+    /// samples from -1.0 to 1.0.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        ::std::mem::take(&mut self.samples)
+    }
+
+    /// Keep each channel's output as well, alongside every stereo sample,
+    /// for `take_channel_samples`.
+    pub fn set_channel_capture(&mut self, enabled: bool) {
+        self.channel_samples = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// The channels' outputs since the last call, four to a sample (in
+    /// channel order), from -1.0 to 1.0. Empty unless channel capture is on.
+    pub fn take_channel_samples(&mut self) -> Vec<f32> {
+        self.channel_samples.as_mut().map_or_else(Vec::new, ::std::mem::take)
+    }
+
+    /// Let `cycles` T-cycles pass.
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            if self.powered {
+                self.square1.tick();
+                self.square2.tick();
+                self.wave.tick();
+                self.noise.tick();
+            }
+            if let Some(rate) = self.sample_rate {
+                self.sample_counter += rate;
+                if self.sample_counter >= CYCLES_PER_SECOND {
+                    self.sample_counter -= CYCLES_PER_SECOND;
+                    let (left, right) = self.mix();
+                    self.samples.push(left);
+                    self.samples.push(right);
+                    if self.channel_samples.is_some() {
+                        let outputs = self.channel_outputs();
+                        if let Some(ref mut samples) = self.channel_samples {
+                            samples.extend_from_slice(&outputs);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tell the APU what DIV is now. Bit 4 going from 1 to 0 steps the
+    /// frame sequencer.
+    pub fn div_changed(&mut self, div: u8) {
+        let falling = self.last_div & 0x10 != 0 && div & 0x10 == 0;
+        self.last_div = div;
+        if falling && self.powered {
+            self.step_frame_sequencer();
+        }
+    }
+
+    fn step_frame_sequencer(&mut self) {
+        // Length at 256 Hz, sweep at 128 Hz, envelopes at 64 Hz
+        if self.frame_step & 1 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    /// Each channel's DAC output, from -1.0 to 1.0. A DAC that's off
+    /// outputs nothing at all.
+    pub fn channel_outputs(&self) -> [f32; 4] {
+        fn dac(enabled: bool, output: u8) -> f32 {
+            if enabled { output as f32 / 7.5 - 1.0 } else { 0.0 }
+        }
+        [
+            dac(self.square1.dac_enabled(), self.square1.output()),
+            dac(self.square2.dac_enabled(), self.square2.output()),
+            dac(self.wave.dac_enabled(), self.wave.output()),
+            dac(self.noise.dac_enabled(), self.noise.output()),
+        ]
+    }
+
+    /// The left and right samples right now
+    fn mix(&self) -> (f32, f32) {
+        if !self.powered {
+            return (0.0, 0.0);
+        }
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (channel, output) in self.channel_outputs().iter().enumerate() {
+            if self.nr51 & (0x10 << channel) != 0 {
+                left += output;
+            }
+            if self.nr51 & (0x01 << channel) != 0 {
+                right += output;
+            }
+        }
+        let left_volume = ((self.nr50 >> 4) & 0x07) as f32 + 1.0;
+        let right_volume = (self.nr50 & 0x07) as f32 + 1.0;
+        (left / 4.0 * left_volume / 8.0, right / 4.0 * right_volume / 8.0)
+    }
+
+    fn power_off(&mut self) {
+        // Everything but wave RAM is cleared
+        let ram = self.wave.ram;
+        self.square1 = Square::with_sweep();
+        self.square2 = Square::new();
+        self.wave = Wave::new();
+        self.wave.ram = ram;
+        self.noise = Noise::new();
+        self.nr50 = 0x00;
+        self.nr51 = 0x00;
+        self.powered = false;
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        if let ::io::WAVE_START ..= ::io::WAVE_END = address {
+            return self.wave.ram[(address - ::io::WAVE_START) as usize];
+        }
+        match address - ::io::NR10 {
+            register @ 0x00 ..= 0x04 => self.square1.read_register(register),
+            register @ 0x05 ..= 0x09 => self.square2.read_register(register - 0x05),
+            register @ 0x0A ..= 0x0E => self.wave.read_register(register - 0x0A),
+            register @ 0x0F ..= 0x13 => self.noise.read_register(register - 0x0F),
+            NR50 => self.nr50,
+            NR51 => self.nr51,
+            NR52 => {
+                (self.powered as u8) << 7
+                    | (self.noise.enabled as u8) << 3
+                    | (self.wave.enabled as u8) << 2
+                    | (self.square2.enabled as u8) << 1
+                    | self.square1.enabled as u8
+            },
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, val: u8) {
+        if let ::io::WAVE_START ..= ::io::WAVE_END = address {
+            self.wave.ram[(address - ::io::WAVE_START) as usize] = val;
+            return;
+        }
+        let register = address - ::io::NR10;
+        if register == NR52 {
+            if val & 0x80 == 0 {
+                self.power_off();
+            } else if !self.powered {
+                self.powered = true;
+                self.frame_step = 0;
+            }
+            return;
+        }
+        // Switched off, only NR52 and wave RAM can be written
+        if !self.powered {
+            return;
+        }
+        match register {
+            0x00 ..= 0x04 => self.square1.write_register(register, val),
+            0x05 ..= 0x09 => self.square2.write_register(register - 0x05, val),
+            0x0A ..= 0x0E => self.wave.write_register(register - 0x0A, val),
+            0x0F ..= 0x13 => self.noise.write_register(register - 0x0F, val),
+            NR50 => self.nr50 = val,
+            NR51 => self.nr51 = val,
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+fn playing_square2() -> Apu {
+    let mut apu = Apu::new();
+    apu.write_register(::io::NR52, 0x80);
+    apu.write_register(0xFF24, 0x77); // NR50: full volume
+    apu.write_register(0xFF25, 0x22); // NR51: channel 2 on both sides
+    apu.write_register(0xFF16, 0x80); // 50% duty
+    apu.write_register(0xFF17, 0xF0); // Volume 15
+    apu.write_register(0xFF19, 0x87); // Trigger
+    apu
+}
+
+#[test]
+fn test_nr52_shows_which_channels_are_on() {
+    let mut apu = playing_square2();
+    assert_eq!(apu.read_register(::io::NR52), 0x82);
+    apu.write_register(::io::NR52, 0x00);
+    assert_eq!(apu.read_register(::io::NR52), 0x00);
+    assert_eq!(apu.read_register(0xFF17), 0x00);
+    // Nothing but NR52 and wave RAM can be written until it's back on
+    apu.write_register(0xFF17, 0xF0);
+    apu.write_register(::io::WAVE_START, 0x12);
+    assert_eq!(apu.read_register(0xFF17), 0x00);
+    assert_eq!(apu.read_register(::io::WAVE_START), 0x12);
+}
+
+#[test]
+fn test_the_frame_sequencer_clocks_lengths_off_div() {
+    let mut apu = playing_square2();
+    apu.write_register(0xFF16, 0xBF); // 63: one length clock left
+    apu.write_register(0xFF19, 0xC7); // Trigger, with the length enabled
+    apu.div_changed(0x10);
+    assert_eq!(apu.read_register(::io::NR52), 0x82);
+    apu.div_changed(0x20);
+    assert_eq!(apu.read_register(::io::NR52), 0x80);
+}
+
+#[test]
+fn test_samples_come_at_the_chosen_rate() {
+    let mut apu = playing_square2();
+    apu.set_sample_rate(32768);
+    apu.tick(CYCLES_PER_SECOND / 128);
+    let samples = apu.take_samples();
+    assert_eq!(samples.len(), 256 * 2);
+    assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+    // Both sides get the square wave, at its high and low
+    assert!(samples.iter().any(|&sample| sample > 0.0));
+    assert!(samples.iter().any(|&sample| sample < 0.0));
+    assert_eq!(samples[0], samples[1]);
+    assert!(apu.take_samples().is_empty());
+}
+
+#[test]
+fn test_nr51_pans_channels() {
+    let mut apu = playing_square2();
+    apu.write_register(0xFF25, 0x20); // Left only
+    apu.set_sample_rate(44100);
+    apu.tick(CYCLES_PER_SECOND / 100);
+    let samples = apu.take_samples();
+    assert!(samples.chunks(2).all(|pair| pair[1] == 0.0));
+    assert!(samples.chunks(2).any(|pair| pair[0] != 0.0));
+}
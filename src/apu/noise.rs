@@ -0,0 +1,122 @@
+use apu::channel::{Envelope, LengthCounter};
+
+/// Channel 4: pseudo-random noise, from a linear feedback shift register
+pub struct Noise {
+    pub enabled: bool,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    register: u8, // NR43
+    timer: u32,
+    lfsr: u16, // 15 bits
+}
+
+impl Default for Noise {
+    fn default() -> Noise {
+        Noise::new()
+    }
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            register: 0x00,
+            timer: 0,
+            lfsr: 0x7FFF,
+        }
+    }
+
+    fn period(&self) -> u32 {
+        let divisor = match self.register & 0x07 {
+            0 => 8,
+            code => code as u32 * 16,
+        };
+        divisor << (self.register >> 4)
+    }
+
+    /// Let one T-cycle pass
+    pub fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            // In 7-bit mode, the feedback goes into bit 6 as well, for a
+            // much shorter (and more tuneful) sequence
+            if self.register & 0x08 != 0 {
+                self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+            }
+        }
+    }
+
+    /// The channel's output, from 0 to 15
+    pub fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 1 == 0 { self.envelope.volume } else { 0 }
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Read NR40 ..= NR44 (`register` is 0 ..= 4). There is no NR40.
+    pub fn read_register(&self, register: u16) -> u8 {
+        match register {
+            2 => self.envelope.read(),
+            3 => self.register,
+            4 => (self.length.enabled as u8) << 6,
+            _ => 0x00,
+        }
+    }
+
+    /// Write NR40 ..= NR44 (`register` is 0 ..= 4)
+    pub fn write_register(&mut self, register: u16, val: u8) {
+        match register {
+            1 => self.length.load(val & 0x3F),
+            2 => {
+                self.envelope.write(val);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            },
+            3 => self.register = val,
+            4 => {
+                self.length.enabled = val & 0x40 != 0;
+                if val & 0x80 != 0 {
+                    self.enabled = self.dac_enabled();
+                    self.length.trigger();
+                    self.envelope.trigger();
+                    self.timer = self.period();
+                    self.lfsr = 0x7FFF;
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+#[test]
+fn test_the_noise_channel_repeats_every_127_steps_in_7_bit_mode() {
+    let mut noise = Noise::new();
+    noise.write_register(2, 0xF0);
+    noise.write_register(3, 0x08); // 7 bits, every 8 cycles
+    noise.write_register(4, 0x80);
+    let mut output = Vec::new();
+    for _ in 0..254 {
+        output.push(noise.output());
+        for _ in 0..8 {
+            noise.tick();
+        }
+    }
+    assert!(output.contains(&15) && output.contains(&0));
+    assert_eq!(output[..127], output[127..]);
+}
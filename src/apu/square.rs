@@ -0,0 +1,247 @@
+use apu::channel::{Envelope, LengthCounter};
+
+/// The four duty cycles, as eight-step waveforms
+const DUTY : [u8; 4] = [
+    0b0000_0001, // 12.5%
+    0b1000_0001, // 25%
+    0b1000_0111, // 50%
+    0b0111_1110, // 75%
+];
+
+/// Channel 1's frequency sweep
+struct Sweep {
+    register: u8, // NR10
+    enabled: bool,
+    shadow: u16,  // The frequency being swept
+    timer: u8,
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0x07
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    /// The next frequency, and whether it's still in range
+    fn next(&self) -> (u16, bool) {
+        let delta = self.shadow >> self.shift();
+        let frequency = if self.register & 0x08 != 0 {
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+        (frequency, frequency <= 0x7FF)
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8
+        self.timer = if self.period() == 0 { 8 } else { self.period() };
+    }
+}
+
+/// Channels 1 and 2: square waves. Only channel 1 has a sweep.
+pub struct Square {
+    pub enabled: bool,
+    sweep: Option<Sweep>,
+    duty: u8,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    frequency: u16, // 11 bits
+    timer: u32,
+    step: u8, // Where we are in the duty cycle
+}
+
+impl Default for Square {
+    fn default() -> Square {
+        Square::new()
+    }
+}
+
+impl Square {
+    /// Channel 1
+    pub fn with_sweep() -> Square {
+        Square {
+            sweep: Some(Sweep { register: 0x00, enabled: false, shadow: 0, timer: 0 }),
+            ..Square::new()
+        }
+    }
+
+    /// Channel 2
+    pub fn new() -> Square {
+        Square {
+            enabled: false,
+            sweep: None,
+            duty: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            frequency: 0,
+            timer: 0,
+            step: 0,
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    /// Let one T-cycle pass
+    pub fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.step = (self.step + 1) % 8;
+        }
+    }
+
+    /// The channel's output, from 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let high = DUTY[self.duty as usize] >> (7 - self.step) & 1 != 0;
+        if high { self.envelope.volume } else { 0 }
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let disable = match self.sweep {
+            Some(ref mut sweep) => {
+                if sweep.timer > 0 {
+                    sweep.timer -= 1;
+                }
+                if sweep.timer != 0 {
+                    return;
+                }
+                sweep.reload_timer();
+                if !sweep.enabled || sweep.period() == 0 {
+                    return;
+                }
+                let (frequency, in_range) = sweep.next();
+                if in_range && sweep.shift() != 0 {
+                    sweep.shadow = frequency;
+                    self.frequency = frequency;
+                }
+                // The next step is checked straight away, too
+                !in_range || !sweep.next().1
+            },
+            None => false,
+        };
+        if disable {
+            self.enabled = false;
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        let frequency = self.frequency;
+        let mut overflow = false;
+        if let Some(ref mut sweep) = self.sweep {
+            sweep.shadow = frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+            if sweep.shift() != 0 {
+                overflow = !sweep.next().1;
+            }
+        }
+        if overflow {
+            self.enabled = false;
+        }
+    }
+
+    /// Read NRx0 ..= NRx4 (`register` is 0 ..= 4)
+    pub fn read_register(&self, register: u16) -> u8 {
+        match register {
+            0 => self.sweep.as_ref().map_or(0xFF, |sweep| sweep.register),
+            1 => self.duty << 6,
+            2 => self.envelope.read(),
+            // The frequency can't be read back
+            3 => 0x00,
+            _ => (self.length.enabled as u8) << 6,
+        }
+    }
+
+    /// Write NRx0 ..= NRx4 (`register` is 0 ..= 4)
+    pub fn write_register(&mut self, register: u16, val: u8) {
+        match register {
+            0 => if let Some(ref mut sweep) = self.sweep { sweep.register = val & 0x7F },
+            1 => {
+                self.duty = val >> 6;
+                self.length.load(val & 0x3F);
+            },
+            2 => {
+                self.envelope.write(val);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            },
+            3 => self.frequency = (self.frequency & 0x700) | val as u16,
+            _ => {
+                self.frequency = (self.frequency & 0xFF) | ((val & 0x07) as u16) << 8;
+                self.length.enabled = val & 0x40 != 0;
+                if val & 0x80 != 0 {
+                    self.trigger();
+                }
+            },
+        }
+    }
+}
+
+#[test]
+fn test_square_waves_follow_the_duty_cycle() {
+    let mut square = Square::new();
+    square.write_register(1, 0x80); // 50%
+    square.write_register(2, 0xF0);
+    square.write_register(3, 0xFF);
+    square.write_register(4, 0x87); // Frequency 0x7FF: 4 cycles a step
+    let mut wave = Vec::new();
+    for _ in 0..8 {
+        wave.push(square.output());
+        for _ in 0..4 {
+            square.tick();
+        }
+    }
+    assert_eq!(wave, vec![15, 0, 0, 0, 0, 15, 15, 15]);
+}
+
+#[test]
+fn test_the_sweep_changes_channel_1s_frequency() {
+    let mut square = Square::with_sweep();
+    square.write_register(0, 0x12); // Up by a quarter, every sweep clock
+    square.write_register(2, 0xF0);
+    square.write_register(3, 0x00);
+    square.write_register(4, 0x84); // 0x400
+    square.clock_sweep();
+    assert_eq!(square.frequency, 0x500);
+    square.clock_sweep();
+    assert_eq!(square.frequency, 0x640);
+    assert!(square.enabled);
+    // The step after this one, 0x9C4, is out of range
+    square.clock_sweep();
+    assert_eq!(square.frequency, 0x7D0);
+    assert!(!square.enabled);
+}
+
+#[test]
+fn test_triggering_without_a_dac_does_nothing() {
+    let mut square = Square::new();
+    square.write_register(2, 0x00);
+    square.write_register(4, 0x80);
+    assert!(!square.enabled);
+}
@@ -0,0 +1,219 @@
+// Capturing Audio
+// --------- -----
+// For machines with no sound card: the APU's output written to 16-bit PCM
+// WAV files, optionally with a file per channel, and hashed so tests can
+// check a ROM still sounds the way it did.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use apu::Apu;
+
+/// The size of the RIFF header, up to the start of the samples
+const HEADER_SIZE : u32 = 44;
+
+/// -1.0 ..= 1.0 to a signed 16-bit sample
+fn to_pcm(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Writes 16-bit PCM samples to a WAV file. The header's sizes are filled in
+/// by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, channels: u16, sample_rate: u32)
+                                  -> io::Result<WavWriter<BufWriter<File>>> {
+        WavWriter::new(BufWriter::new(File::create(path)?), channels, sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, channels: u16, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        let block_align = channels * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?; // Bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { writer, data_size: 0 })
+    }
+
+    /// Append samples from -1.0 to 1.0, interleaved if there's more than one
+    /// channel.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            self.writer.write_all(&to_pcm(sample).to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    /// Fill in the header and hand back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Records the mixed stereo output to `<name>.wav`, and each channel to
+/// `<name>-1.wav` ..= `<name>-4.wav` if stems are asked for.
+pub struct AudioCapture {
+    mix: WavWriter<BufWriter<File>>,
+    stems: Vec<WavWriter<BufWriter<File>>>,
+}
+
+impl AudioCapture {
+    /// Start recording. This sets the APU's sample rate, and turns on
+    /// channel capture for the stems.
+    pub fn create<P: AsRef<Path>>(path: P, apu: &mut Apu, sample_rate: u32, stems: bool)
+                                  -> io::Result<AudioCapture> {
+        let path = path.as_ref();
+        let mix = WavWriter::create(path, 2, sample_rate)?;
+        let mut stem_writers = Vec::new();
+        if stems {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            for channel in 1..=4 {
+                let stem_path = path.with_file_name(format!("{}-{}.wav", stem, channel));
+                stem_writers.push(WavWriter::create(stem_path, 1, sample_rate)?);
+            }
+        }
+        apu.set_sample_rate(sample_rate);
+        apu.set_channel_capture(stems);
+        Ok(AudioCapture { mix, stems: stem_writers })
+    }
+
+    /// Write out everything the APU has played since the last call. Call it
+    /// every frame or so, to keep the APU's buffers small.
+    pub fn capture(&mut self, apu: &mut Apu) -> io::Result<()> {
+        self.mix.write_samples(&apu.take_samples())?;
+        if !self.stems.is_empty() {
+            let channels = apu.take_channel_samples();
+            for (channel, stem) in self.stems.iter_mut().enumerate() {
+                let samples: Vec<f32> = channels.iter().skip(channel).step_by(4).cloned().collect();
+                stem.write_samples(&samples)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in the files' headers. Until this is called, they claim to be
+    /// empty.
+    pub fn finish(self) -> io::Result<()> {
+        self.mix.finish()?;
+        for stem in self.stems {
+            stem.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// A hash of some samples, as they'd be written to a WAV file. Stable from
+/// one build (and Rust version) to the next: 64-bit FNV-1a.
+pub fn hash_samples(samples: &[f32]) -> u64 {
+    let mut hash : u64 = 0xCBF2_9CE4_8422_2325;
+    for &sample in samples {
+        for &byte in &to_pcm(sample).to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+    hash
+}
+
+/// Run `cpu` for `seconds` and hash what it played at `sample_rate`, for
+/// checking a ROM's sound hasn't changed. The APU goes back to the sample
+/// rate it had before, but anything it hadn't handed over is lost.
+pub fn audio_hash(cpu: &mut ::cpu::Z80, seconds: u32, sample_rate: u32) -> u64 {
+    let wanted = seconds as usize * sample_rate as usize * 2;
+    let previous_rate = cpu.apu_mut().sample_rate();
+    cpu.apu_mut().stop_sampling();
+    cpu.apu_mut().set_sample_rate(sample_rate);
+    let mut samples = Vec::with_capacity(wanted);
+    while samples.len() < wanted {
+        cpu.run_frame();
+        samples.extend(cpu.apu_mut().take_samples());
+    }
+    samples.truncate(wanted);
+    match previous_rate {
+        Some(rate) => cpu.apu_mut().set_sample_rate(rate),
+        None => cpu.apu_mut().stop_sampling(),
+    }
+    hash_samples(&samples)
+}
+
+#[test]
+fn test_wav_headers_have_the_right_sizes() {
+    let mut wav = WavWriter::new(io::Cursor::new(Vec::new()), 2, 44100).unwrap();
+    wav.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+    let bytes = wav.finish().unwrap().into_inner();
+    assert_eq!(bytes.len(), 44 + 8);
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(bytes[4..8], (36u32 + 8).to_le_bytes());
+    assert_eq!(bytes[22..24], 2u16.to_le_bytes());
+    assert_eq!(bytes[28..32], (44100u32 * 4).to_le_bytes());
+    assert_eq!(bytes[40..44], 8u32.to_le_bytes());
+    // Out of range samples are clipped
+    assert_eq!(bytes[44..], [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+}
+
+#[test]
+fn test_capturing_stems() {
+    let path = ::std::env::temp_dir().join(format!("gb-{}-capture.wav", ::std::process::id()));
+    let mut apu = Apu::new();
+    let mut capture = AudioCapture::create(&path, &mut apu, 8192, true).unwrap();
+    apu.write_register(::io::NR52, 0x80);
+    apu.tick(4_194_304 / 8);
+    capture.capture(&mut apu).unwrap();
+    capture.finish().unwrap();
+    let stem_path = path.with_file_name(format!("gb-{}-capture-4.wav", ::std::process::id()));
+    assert_eq!(::std::fs::metadata(&path).unwrap().len(), 44 + 1024 * 4);
+    assert_eq!(::std::fs::metadata(&stem_path).unwrap().len(), 44 + 1024 * 2);
+    ::std::fs::remove_file(&path).unwrap();
+    for channel in 1..=4 {
+        ::std::fs::remove_file(path.with_file_name(
+            format!("gb-{}-capture-{}.wav", ::std::process::id(), channel))).unwrap();
+    }
+}
+
+#[test]
+fn test_audio_hashes_change_with_the_sound() {
+    let silence = hash_samples(&[0.0; 16]);
+    assert_eq!(silence, hash_samples(&[0.0; 16]));
+    assert!(silence != hash_samples(&[0.5; 16]));
+    // Nothing's playing without a cartridge, so every second sounds the same
+    let mut cpu = ::cpu::Z80::new();
+    cpu.skip_boot(::boot::Model::Dmg);
+    let first = audio_hash(&mut cpu, 1, 1024);
+    assert_eq!(audio_hash(&mut cpu, 1, 1024), first);
+}
+
+#[test]
+fn test_audio_hashes_leave_the_apu_as_it_was() {
+    let mut cpu = ::cpu::Z80::new();
+    cpu.skip_boot(::boot::Model::Dmg);
+    let expected = audio_hash(&mut cpu, 1, 1024);
+    assert_eq!(cpu.apu_mut().sample_rate(), None);
+    assert!(cpu.apu_mut().take_samples().is_empty());
+    // Samples nobody took don't end up in the hash
+    cpu.apu_mut().set_sample_rate(8192);
+    cpu.apu_mut().samples.extend_from_slice(&[1.0; 64]);
+    assert_eq!(audio_hash(&mut cpu, 1, 1024), expected);
+    assert_eq!(cpu.apu_mut().sample_rate(), Some(8192));
+}
@@ -0,0 +1,126 @@
+use apu::channel::LengthCounter;
+
+/// Channel 3: plays back the 32 4-bit samples in wave RAM
+pub struct Wave {
+    pub enabled: bool,
+    dac_enabled: bool, // NR30
+    pub length: LengthCounter,
+    volume: u8, // NR32: mute, 100%, 50% or 25%
+    frequency: u16,
+    timer: u32,
+    position: u8, // Which of the 32 samples is playing
+    pub ram: [u8; 16], // 0xFF30 ..= 0xFF3F, two samples a byte
+}
+
+impl Default for Wave {
+    fn default() -> Wave {
+        Wave::new()
+    }
+}
+
+impl Wave {
+    pub fn new() -> Wave {
+        Wave {
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            volume: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            ram: [0; 16],
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    /// Let one T-cycle pass
+    pub fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+        }
+    }
+
+    /// The channel's output, from 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.volume == 0 {
+            return 0;
+        }
+        // The high nibble plays first
+        let byte = self.ram[self.position as usize / 2];
+        let sample = if self.position & 1 == 0 { byte >> 4 } else { byte & 0x0F };
+        sample >> (self.volume - 1)
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Read NR30 ..= NR34 (`register` is 0 ..= 4)
+    pub fn read_register(&self, register: u16) -> u8 {
+        match register {
+            0 => if self.dac_enabled { 0x80 } else { 0x00 },
+            1 => 0x00,
+            2 => self.volume << 5,
+            3 => 0x00,
+            _ => (self.length.enabled as u8) << 6,
+        }
+    }
+
+    /// Write NR30 ..= NR34 (`register` is 0 ..= 4)
+    pub fn write_register(&mut self, register: u16, val: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = val & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            },
+            1 => self.length.load(val),
+            2 => self.volume = (val >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | val as u16,
+            _ => {
+                self.frequency = (self.frequency & 0xFF) | ((val & 0x07) as u16) << 8;
+                self.length.enabled = val & 0x40 != 0;
+                if val & 0x80 != 0 {
+                    self.enabled = self.dac_enabled;
+                    self.length.trigger();
+                    self.timer = self.period();
+                    self.position = 0;
+                }
+            },
+        }
+    }
+}
+
+#[test]
+fn test_the_wave_channel_plays_wave_ram() {
+    let mut wave = Wave::new();
+    wave.ram[0] = 0xF8;
+    wave.ram[1] = 0x40;
+    wave.write_register(0, 0x80);
+    wave.write_register(2, 0x20); // 100%
+    wave.write_register(3, 0xFF);
+    wave.write_register(4, 0x87); // Frequency 0x7FF: 2 cycles a sample
+    // Triggering skips the first sample
+    wave.tick();
+    wave.tick();
+    assert_eq!(wave.output(), 0x08);
+    wave.tick();
+    wave.tick();
+    assert_eq!(wave.output(), 0x04);
+    wave.write_register(2, 0x60); // 25%
+    assert_eq!(wave.output(), 0x01);
+}
@@ -0,0 +1,94 @@
+// Booting up
+// ---------
+// When it's switched on, the Game Boy runs a small ROM of its own, mapped
+// over the start of the cartridge. It scrolls the logo down, checks the
+// cartridge header, and then unmaps itself by writing to 0xFF50, leaving
+// the registers in a known state for the game at 0x0100.
+
+/// Which Game Boy we're pretending to be. Games can tell them apart by the
+/// value the boot ROM leaves in A.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    Dmg, // The original Game Boy
+    Mgb, // The Game Boy Pocket
+    Cgb, // The Game Boy Color
+}
+
+/// The DMG and MGB boot ROMs cover 0x0000 ..= 0x00FF
+pub const DMG_BOOT_ROM_SIZE : usize = 0x0100;
+/// The CGB's also covers 0x0200 ..= 0x08FF, leaving a gap for the header
+pub const CGB_BOOT_ROM_SIZE : usize = 0x0900;
+
+/// A, F, B, C, D, E, H and L once the boot ROM is done
+pub fn registers(model: Model) -> [u8; 8] {
+    match model {
+        Model::Dmg => [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+        Model::Mgb => [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+        Model::Cgb => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+    }
+}
+
+/// The IO registers every boot ROM leaves set. DIV, LY and DMA are left out,
+/// since writing to them does something else entirely, and so are
+/// NR14 ..= NR44, which would restart the sound channels.
+const IO_REGISTERS : &[(u16, u8)] = &[
+    (0xFF00, 0xCF), // P1
+    (0xFF02, 0x7E), // SC
+    (0xFF07, 0xF8), // TAC
+    (0xFF26, 0xF1), // NR52, first so the rest of the APU is switched on
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF40, 0x91), // LCDC
+    (0xFF41, 0x85), // STAT
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF47, 0xFC), // BGP
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+    (0xFF0F, 0xE1), // IF
+    (0xFFFF, 0x00), // IE
+];
+
+/// The IO registers the boot ROM leaves set, in the order to write them.
+pub fn io_registers(model: Model) -> Vec<(u16, u8)> {
+    let mut registers = IO_REGISTERS.to_vec();
+    if model == Model::Cgb {
+        registers.extend_from_slice(&[
+            (0xFF4D, 0x7E), // KEY1: normal speed
+            (0xFF4F, 0xFE), // VBK: bank 0
+            (0xFF70, 0xF8), // SVBK: bank 1
+            (0xFF68, 0x80), // BCPS: colour 0, counting up
+        ]);
+        // Every background palette all white
+        registers.extend(::std::iter::repeat_n((0xFF69, 0xFF), 64));
+    }
+    registers
+}
+
+#[test]
+fn test_the_cgb_boot_rom_leaves_more_registers_set() {
+    let dmg = io_registers(Model::Dmg);
+    assert_eq!(io_registers(Model::Mgb), dmg);
+    let cgb = io_registers(Model::Cgb);
+    assert_eq!(cgb[..dmg.len()], dmg[..]);
+    let cgb_only = &cgb[dmg.len()..];
+    assert!(cgb_only.contains(&(0xFF4D, 0x7E)));
+    assert!(cgb_only.contains(&(0xFF4F, 0xFE)));
+    assert!(cgb_only.contains(&(0xFF70, 0xF8)));
+    assert_eq!(cgb_only.iter().filter(|&&register| register == (0xFF69, 0xFF)).count(), 64);
+}
@@ -0,0 +1,599 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use mbc::{MemoryBankController, RomOnly};
+use mbc::mbc1::Mbc1;
+use mbc::mbc2::{self, Mbc2};
+use mbc::mbc3::{Mbc3, Rtc, RtcClock};
+use mbc::mbc5::Mbc5;
+
+// Where things live in the cartridge header
+const TITLE_START     : usize = 0x0134;
+const TITLE_END       : usize = 0x0144;
+const NEW_LICENSEE    : usize = 0x0144;
+const CGB_FLAG        : usize = 0x0143;
+const CARTRIDGE_TYPE  : usize = 0x0147;
+const ROM_SIZE        : usize = 0x0148;
+const RAM_SIZE        : usize = 0x0149;
+const OLD_LICENSEE    : usize = 0x014B;
+const HEADER_CHECKSUM : usize = 0x014D;
+const GLOBAL_CHECKSUM : usize = 0x014E;
+const HEADER_END      : usize = 0x0150;
+
+/// The smallest cartridge there is: two 16 KiB banks
+const MIN_ROM_SIZE : usize = 0x8000;
+
+/// How often battery-backed RAM is written out, if it's changed: about
+/// once a second of emulated time
+const AUTOSAVE_CYCLES : u32 = 4_194_304;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    /// The image is smaller than its header (or its header says it should be)
+    Truncated { expected: usize, actual: usize },
+    /// The header checksum at 0x014D doesn't match the header
+    BadHeaderChecksum { expected: u8, actual: u8 },
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    /// A cartridge type (0x0147) with a controller we don't emulate
+    UnsupportedCartridgeType(u8),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CartridgeError::Io(ref e) =>
+                write!(f, "couldn't read cartridge: {}", e),
+            CartridgeError::Truncated { expected, actual } =>
+                write!(f, "cartridge is truncated: expected {} bytes, found {}",
+                       expected, actual),
+            CartridgeError::BadHeaderChecksum { expected, actual } =>
+                write!(f, "bad header checksum: expected {:02X}, found {:02X}",
+                       expected, actual),
+            CartridgeError::UnknownRomSize(code) =>
+                write!(f, "unknown ROM size code {:02X}", code),
+            CartridgeError::UnknownRamSize(code) =>
+                write!(f, "unknown RAM size code {:02X}", code),
+            CartridgeError::UnsupportedCartridgeType(code) =>
+                write!(f, "unsupported cartridge type {:02X}", code),
+        }
+    }
+}
+
+impl error::Error for CartridgeError {}
+
+impl From<io::Error> for CartridgeError {
+    fn from(e: io::Error) -> CartridgeError {
+        CartridgeError::Io(e)
+    }
+}
+
+/// Whether a game uses the Game Boy Color's extra features
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgbFlag {
+    DmgOnly,
+    CgbEnhanced, // 0x80: works on both
+    CgbOnly,     // 0xC0
+}
+
+/// Who published the game. Older games use a single byte at 0x014B;
+/// newer ones set that to 0x33 and use two ASCII characters at 0x0144.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Licensee {
+    Old(u8),
+    New([u8; 2]),
+}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub title: String,
+    pub cgb_flag: CgbFlag,
+    pub cartridge_type: u8,
+    pub rom_size: usize, // In bytes
+    pub ram_size: usize, // In bytes
+    pub licensee: Licensee,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl Header {
+    fn parse(rom: &[u8]) -> Result<Header, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::Truncated {
+                expected: HEADER_END, actual: rom.len()
+            });
+        }
+
+        let expected = rom[HEADER_CHECKSUM];
+        let actual = header_checksum(rom);
+        if expected != actual {
+            return Err(CartridgeError::BadHeaderChecksum { expected, actual });
+        }
+
+        let cgb_flag = match rom[CGB_FLAG] {
+            0x80 => CgbFlag::CgbEnhanced,
+            0xC0 => CgbFlag::CgbOnly,
+            _    => CgbFlag::DmgOnly,
+        };
+        // On color games, the last byte of the title is the CGB flag
+        let title_end = if cgb_flag == CgbFlag::DmgOnly { TITLE_END } else { CGB_FLAG };
+        let title = rom[TITLE_START..title_end].iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect();
+
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00 ..= 0x08 => MIN_ROM_SIZE << code,
+            0x52 => 72 * 0x4000,
+            0x53 => 80 * 0x4000,
+            0x54 => 96 * 0x4000,
+            code => return Err(CartridgeError::UnknownRomSize(code)),
+        };
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0,
+            0x01 => 0x0800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(CartridgeError::UnknownRamSize(code)),
+        };
+
+        let licensee = match rom[OLD_LICENSEE] {
+            0x33 => Licensee::New([rom[NEW_LICENSEE], rom[NEW_LICENSEE + 1]]),
+            code => Licensee::Old(code),
+        };
+
+        Ok(Header {
+            title,
+            cgb_flag,
+            cartridge_type: rom[CARTRIDGE_TYPE],
+            rom_size,
+            ram_size,
+            licensee,
+            header_checksum: expected,
+            // The global checksum is big-endian, unlike everything else
+            global_checksum: (rom[GLOBAL_CHECKSUM] as u16) << 8
+                | rom[GLOBAL_CHECKSUM + 1] as u16,
+        })
+    }
+
+    /// Whether the cartridge has a battery, to keep its RAM (and clock)
+    /// going while the Game Boy is switched off
+    pub fn has_battery(&self) -> bool {
+        matches!(self.cartridge_type,
+                 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF)
+    }
+}
+
+/// The checksum the boot ROM verifies: it won't start a game without it.
+fn header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE_START..HEADER_CHECKSUM].iter()
+        .fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1))
+}
+
+/// The sum of every byte in the cartridge, except the checksum itself.
+fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter().enumerate()
+        .filter(|&(i, _)| i != GLOBAL_CHECKSUM && i != GLOBAL_CHECKSUM + 1)
+        .fold(0u16, |sum, (_, &b)| sum.wrapping_add(b as u16))
+}
+
+pub struct Cartridge {
+    pub header: Header,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mbc: Box<dyn MemoryBankController>,
+    // Where battery-backed RAM is kept between runs
+    save_path: Option<PathBuf>,
+    // Whether RAM has been written since it was last saved
+    dirty: bool,
+    cycles_since_save: u32,
+}
+
+/// Pick the memory bank controller for a cartridge type (0x0147)
+fn memory_bank_controller(cartridge_type: u8)
+                          -> Result<Box<dyn MemoryBankController>, CartridgeError> {
+    Ok(match cartridge_type {
+        // ROM only, ROM+RAM and ROM+RAM+BATTERY
+        0x00 | 0x08 | 0x09 => Box::new(RomOnly),
+        0x01 ..= 0x03      => Box::new(Mbc1::new()),
+        0x05 | 0x06        => Box::new(Mbc2::new()),
+        0x0F | 0x10        => Box::new(Mbc3::with_rtc(RtcClock::Emulated)),
+        0x11 ..= 0x13      => Box::new(Mbc3::new()),
+        0x19 ..= 0x1B      => Box::new(Mbc5::new()),
+        0x1C ..= 0x1E      => Box::new(Mbc5::with_rumble()),
+        // MMM01, MBC6, MBC7, the Pocket Camera, HuC1 and friends
+        code => return Err(CartridgeError::UnsupportedCartridgeType(code)),
+    })
+}
+
+impl Cartridge {
+    /// Load a cartridge from a .gb file. If it has a battery, its RAM is
+    /// kept in a .sav file next to it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Cartridge, CartridgeError> {
+        let mut rom = Vec::new();
+        File::open(path.as_ref())?.read_to_end(&mut rom)?;
+        let mut cartridge = Cartridge::from_bytes(&rom)?;
+        if cartridge.header.has_battery() {
+            cartridge.set_save_path(path.as_ref().with_extension("sav"))?;
+        }
+        Ok(cartridge)
+    }
+
+    /// Load a cartridge from a ROM image already in memory. Cartridges
+    /// whose controller isn't supported are rejected here, so the MMU
+    /// never has to run one.
+    pub fn from_bytes(rom: &[u8]) -> Result<Cartridge, CartridgeError> {
+        let header = Header::parse(rom)?;
+        if rom.len() < header.rom_size {
+            return Err(CartridgeError::Truncated {
+                expected: header.rom_size, actual: rom.len()
+            });
+        }
+        let mbc = memory_bank_controller(header.cartridge_type)?;
+        // The MBC2's RAM is part of the controller, so the header says
+        // there isn't any
+        let ram_size = match header.cartridge_type {
+            0x05 | 0x06 => mbc2::RAM_SIZE,
+            _           => header.ram_size,
+        };
+        Ok(Cartridge {
+            mbc,
+            ram: vec![0; ram_size],
+            rom: rom.to_vec(),
+            header,
+            save_path: None,
+            dirty: false,
+            cycles_since_save: 0,
+        })
+    }
+
+    /// A blank 32 KiB cartridge, for when nothing is plugged in.
+    pub fn empty() -> Cartridge {
+        Cartridge {
+            header: Header {
+                title: String::new(),
+                cgb_flag: CgbFlag::DmgOnly,
+                cartridge_type: 0x00,
+                rom_size: MIN_ROM_SIZE,
+                ram_size: 0,
+                licensee: Licensee::Old(0x00),
+                header_checksum: 0x00,
+                global_checksum: 0x0000,
+            },
+            rom: vec![0; MIN_ROM_SIZE],
+            ram: Vec::new(),
+            mbc: Box::new(RomOnly),
+            save_path: None,
+            dirty: false,
+            cycles_since_save: 0,
+        }
+    }
+
+    /// Whether the global checksum matches. Real hardware never checks
+    /// this, and plenty of games get it wrong, so it isn't an error.
+    pub fn global_checksum_is_valid(&self) -> bool {
+        global_checksum(&self.rom) == self.header.global_checksum
+    }
+
+    /// Read from the cartridge's ROM, at 0x0000 ..= 0x7FFF
+    pub fn read_rom(&self, address: u16) -> u8 {
+        self.mbc.read_rom(&self.rom, address)
+    }
+
+    /// Write to the cartridge's ROM area. ROM can't be written, but the
+    /// memory bank controller listens for these.
+    pub fn write_rom(&mut self, address: u16, val: u8) {
+        self.mbc.write_rom(address, val);
+    }
+
+    /// Let `cycles` T-cycles pass, for cartridges with a clock in them
+    /// and to autosave battery-backed RAM.
+    pub fn tick(&mut self, cycles: u32) {
+        self.mbc.tick(cycles);
+        if self.dirty {
+            self.cycles_since_save = self.cycles_since_save.saturating_add(cycles);
+            if self.cycles_since_save >= AUTOSAVE_CYCLES {
+                // There's no one to tell if this fails; it'll be tried
+                // again in another second, and when the cartridge is dropped
+                self.cycles_since_save = 0;
+                let _ = self.save();
+            }
+        }
+    }
+
+    /// Keep the cartridge's RAM in `path`, in the raw format other
+    /// emulators use, loading whatever is already there. A missing file is
+    /// fine: it'll be created the first time the RAM is saved.
+    pub fn set_save_path<P: Into<PathBuf>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.into();
+        match fs::read(&path) {
+            Ok(save) => self.load_save(&save),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+        self.save_path = Some(path);
+        Ok(())
+    }
+
+    pub fn save_path(&self) -> Option<&Path> {
+        self.save_path.as_deref()
+    }
+
+    /// Restore RAM, and then the clock state after it if there is one.
+    fn load_save(&mut self, save: &[u8]) {
+        let ram_size = self.ram.len().min(save.len());
+        self.ram[..ram_size].copy_from_slice(&save[..ram_size]);
+        if let Some(rtc) = self.mbc.rtc_mut() {
+            rtc.load_footer(&save[ram_size..]);
+        }
+    }
+
+    /// Write RAM (and the clock) out to the save file, if there is one.
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = match self.save_path {
+            Some(ref path) => path.clone(),
+            None => return Ok(()),
+        };
+        let mut save = self.ram.clone();
+        if let Some(rtc) = self.mbc.rtc_mut() {
+            save.extend_from_slice(&rtc.to_footer());
+        }
+        fs::write(path, save)?;
+        self.dirty = false;
+        self.cycles_since_save = 0;
+        Ok(())
+    }
+
+    /// The cartridge's real-time clock, if it has one. Set its `clock` to
+    /// `RtcClock::Host` to have it follow the wall clock instead of the
+    /// emulator.
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.mbc.rtc_mut()
+    }
+
+    /// Have `callback` called with the rumble motor's state whenever it
+    /// changes, on cartridges that have one.
+    pub fn set_rumble_callback<F: FnMut(bool) + 'static>(&mut self, callback: F) {
+        self.mbc.set_rumble_callback(Box::new(callback));
+    }
+
+    /// Read from the cartridge's RAM, at 0xA000 ..= 0xBFFF
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.mbc.read_ram(&self.ram, address)
+    }
+
+    /// Write to the cartridge's RAM, at 0xA000 ..= 0xBFFF
+    pub fn write_ram(&mut self, address: u16, val: u8) {
+        if self.mbc.write_ram(&mut self.ram, address, val) {
+            self.dirty = true;
+        }
+    }
+}
+
+impl Drop for Cartridge {
+    fn drop(&mut self) {
+        // Don't lose anything written since the last autosave
+        if self.dirty || self.mbc.rtc_mut().is_some() {
+            let _ = self.save();
+        }
+    }
+}
+
+/// Build a ROM image with a valid header, for testing.
+#[cfg(test)]
+pub fn test_rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; MIN_ROM_SIZE << rom_size];
+    rom[TITLE_START..TITLE_START + 4].copy_from_slice(b"TEST");
+    rom[CARTRIDGE_TYPE] = cartridge_type;
+    rom[ROM_SIZE] = rom_size;
+    rom[RAM_SIZE] = ram_size;
+    rom[HEADER_CHECKSUM] = header_checksum(&rom);
+    let checksum = global_checksum(&rom);
+    rom[GLOBAL_CHECKSUM] = (checksum >> 8) as u8;
+    rom[GLOBAL_CHECKSUM + 1] = checksum as u8;
+    rom
+}
+
+#[test]
+fn test_parsing_the_header() {
+    let mut rom = test_rom(0x00, 0x01, 0x02);
+    rom[OLD_LICENSEE] = 0x01;
+    rom[HEADER_CHECKSUM] = header_checksum(&rom);
+    let cartridge = Cartridge::from_bytes(&rom).unwrap();
+    assert_eq!(cartridge.header.title, "TEST");
+    assert_eq!(cartridge.header.cgb_flag, CgbFlag::DmgOnly);
+    assert_eq!(cartridge.header.cartridge_type, 0x00);
+    assert_eq!(cartridge.header.rom_size, 64 * 1024);
+    assert_eq!(cartridge.header.ram_size, 8 * 1024);
+    assert_eq!(cartridge.header.licensee, Licensee::Old(0x01));
+}
+
+#[test]
+fn test_parsing_a_color_header() {
+    let mut rom = test_rom(0x00, 0x00, 0x00);
+    rom[TITLE_START..TITLE_END].copy_from_slice(b"ABCDEFGHIJKLMNO\xC0");
+    rom[OLD_LICENSEE] = 0x33;
+    rom[NEW_LICENSEE] = b'0';
+    rom[NEW_LICENSEE + 1] = b'1';
+    rom[HEADER_CHECKSUM] = header_checksum(&rom);
+    let cartridge = Cartridge::from_bytes(&rom).unwrap();
+    assert_eq!(cartridge.header.title, "ABCDEFGHIJKLMNO");
+    assert_eq!(cartridge.header.cgb_flag, CgbFlag::CgbOnly);
+    assert_eq!(cartridge.header.licensee, Licensee::New(*b"01"));
+}
+
+#[test]
+fn test_checking_the_global_checksum() {
+    let mut rom = test_rom(0x00, 0x00, 0x00);
+    let cartridge = Cartridge::from_bytes(&rom).unwrap();
+    assert!(cartridge.global_checksum_is_valid());
+    // A bad global checksum still loads
+    rom[0x4000] = 0x01;
+    let cartridge = Cartridge::from_bytes(&rom).unwrap();
+    assert!(!cartridge.global_checksum_is_valid());
+}
+
+#[test]
+fn test_rejecting_a_bad_header_checksum() {
+    let mut rom = test_rom(0x00, 0x00, 0x00);
+    rom[HEADER_CHECKSUM] = rom[HEADER_CHECKSUM].wrapping_add(1);
+    match Cartridge::from_bytes(&rom) {
+        Err(CartridgeError::BadHeaderChecksum { .. }) => (),
+        other => panic!("expected a bad checksum, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_rejecting_truncated_images() {
+    let rom = test_rom(0x00, 0x02, 0x00);
+    match Cartridge::from_bytes(&rom[..0x100]) {
+        Err(CartridgeError::Truncated { expected: 0x150, actual: 0x100 }) => (),
+        other => panic!("expected a truncated header, got {:?}", other.err()),
+    }
+    match Cartridge::from_bytes(&rom[..0x8000]) {
+        Err(CartridgeError::Truncated { expected: 0x20000, actual: 0x8000 }) => (),
+        other => panic!("expected a truncated image, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_rejecting_unknown_sizes() {
+    let mut rom = test_rom(0x00, 0x00, 0x00);
+    rom[RAM_SIZE] = 0x09;
+    rom[HEADER_CHECKSUM] = header_checksum(&rom);
+    match Cartridge::from_bytes(&rom) {
+        Err(CartridgeError::UnknownRamSize(0x09)) => (),
+        other => panic!("expected an unknown RAM size, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_rejecting_unsupported_cartridge_types() {
+    let rom = test_rom(0xFC, 0x00, 0x00);
+    match Cartridge::from_bytes(&rom) {
+        Err(CartridgeError::UnsupportedCartridgeType(0xFC)) => (),
+        other => panic!("expected an unsupported type, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_mbc2_cartridges_have_built_in_ram() {
+    let rom = test_rom(0x06, 0x01, 0x00);
+    let mut cartridge = Cartridge::from_bytes(&rom).unwrap();
+    cartridge.write_rom(0x0000, 0x0A);
+    cartridge.write_ram(0xA1FF, 0x07);
+    assert_eq!(cartridge.read_ram(0xA1FF), 0xF7);
+}
+
+#[cfg(test)]
+fn temp_path(name: &str) -> PathBuf {
+    ::std::env::temp_dir().join(format!("gb-{}-{}", ::std::process::id(), name))
+}
+
+#[test]
+fn test_battery_backed_ram_is_saved() {
+    let rom_path = temp_path("battery.gb");
+    let save_path = rom_path.with_extension("sav");
+    fs::write(&rom_path, test_rom(0x03, 0x00, 0x02)).unwrap();
+    {
+        let mut cartridge = Cartridge::open(&rom_path).unwrap();
+        assert_eq!(cartridge.save_path(), Some(save_path.as_path()));
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA123, 0x42);
+        // Nothing is written until a second has gone by
+        cartridge.tick(AUTOSAVE_CYCLES - 1);
+        assert!(!save_path.exists());
+        cartridge.tick(1);
+        assert_eq!(fs::read(&save_path).unwrap()[0x123], 0x42);
+        cartridge.write_ram(0xA124, 0x43);
+    }
+    // ...and what's left is saved when the cartridge is dropped
+    let mut cartridge = Cartridge::open(&rom_path).unwrap();
+    cartridge.write_rom(0x0000, 0x0A);
+    assert_eq!(cartridge.read_ram(0xA123), 0x42);
+    assert_eq!(cartridge.read_ram(0xA124), 0x43);
+    drop(cartridge);
+    fs::remove_file(&rom_path).unwrap();
+    fs::remove_file(&save_path).unwrap();
+}
+
+#[test]
+fn test_ignored_ram_writes_dont_need_saving() {
+    let mut cartridge = Cartridge::from_bytes(&test_rom(0x03, 0x00, 0x02)).unwrap();
+    // RAM is disabled until the game turns it on
+    cartridge.write_ram(0xA000, 0x42);
+    assert!(!cartridge.dirty);
+    cartridge.write_rom(0x0000, 0x0A);
+    cartridge.write_ram(0xA000, 0x42);
+    assert!(cartridge.dirty);
+    // ...and a battery with no RAM has nothing to save
+    let mut cartridge = Cartridge::from_bytes(&test_rom(0x03, 0x00, 0x00)).unwrap();
+    cartridge.write_rom(0x0000, 0x0A);
+    cartridge.write_ram(0xA000, 0x42);
+    assert!(!cartridge.dirty);
+}
+
+#[test]
+fn test_failed_autosaves_wait_before_trying_again() {
+    let save_path = temp_path("missing-directory").join("game.sav");
+    let mut cartridge = Cartridge::from_bytes(&test_rom(0x03, 0x00, 0x02)).unwrap();
+    cartridge.set_save_path(&save_path).unwrap();
+    cartridge.write_rom(0x0000, 0x0A);
+    cartridge.write_ram(0xA000, 0x42);
+    cartridge.tick(AUTOSAVE_CYCLES);
+    assert!(!save_path.exists());
+    // Still unsaved, but it's another second until the next try
+    assert!(cartridge.dirty);
+    assert_eq!(cartridge.cycles_since_save, 0);
+    cartridge.tick(u32::MAX);
+    cartridge.tick(u32::MAX);
+    assert_eq!(cartridge.cycles_since_save, 0);
+}
+
+#[test]
+fn test_saves_include_the_clock() {
+    let save_path = temp_path("clock.sav");
+    let mut cartridge = Cartridge::from_bytes(&test_rom(0x10, 0x00, 0x02)).unwrap();
+    cartridge.set_save_path(&save_path).unwrap();
+    cartridge.save().unwrap();
+    assert_eq!(fs::read(&save_path).unwrap().len(), 0x2000 + ::mbc::mbc3::RTC_FOOTER_SIZE);
+    drop(cartridge);
+    fs::remove_file(&save_path).unwrap();
+}
+
+#[test]
+fn test_cartridges_without_a_battery_arent_saved() {
+    let rom_path = temp_path("no-battery.gb");
+    fs::write(&rom_path, test_rom(0x02, 0x00, 0x02)).unwrap();
+    let cartridge = Cartridge::open(&rom_path).unwrap();
+    assert_eq!(cartridge.save_path(), None);
+    fs::remove_file(&rom_path).unwrap();
+}
+
+#[test]
+fn test_opening_a_missing_file() {
+    match Cartridge::open("/this/cartridge/does/not/exist.gb") {
+        Err(CartridgeError::Io(_)) => (),
+        other => panic!("expected an IO error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_mbc1_cartridges_switch_banks() {
+    let mut rom = test_rom(0x03, 0x02, 0x03);
+    rom[0x4000 * 3] = 0x33;
+    let mut cartridge = Cartridge::from_bytes(&rom).unwrap();
+    cartridge.write_rom(0x2000, 0x03);
+    assert_eq!(cartridge.read_rom(0x4000), 0x33);
+    cartridge.write_rom(0x0000, 0x0A);
+    cartridge.write_ram(0xA000, 0x42);
+    assert_eq!(cartridge.read_ram(0xA000), 0x42);
+}
@@ -1,11 +1,22 @@
 #![allow(non_snake_case)] // CPU Opcodes have capitalized names
 #![allow(dead_code)] // TODO
+#![allow(clippy::self_assignment)] // LD a,a and friends are real opcodes
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use boot::Model;
+use serial::SerialLink;
 
 const ZERO      : u8 = 0x80;
 const SUBTRACT  : u8 = 0x40;
 const HALFCARRY : u8 = 0x20;
 const CARRY     : u8 = 0x10;
 
+/// The screen is redrawn every 70224 clock cycles (about 59.7 times a second)
+pub const CYCLES_PER_FRAME : u32 = 70224;
+
 struct RegisterSet {
     // 8-bit registers
     a: u8,
@@ -34,8 +45,7 @@ macro_rules! register_pair {
         {
             let r1 = $regs.$r1 as u16;
             let r2 = $regs.$r2 as u16;
-            let result = r1<<8 | r2;
-            result
+            r1<<8 | r2
         }
     )
 }
@@ -80,8 +90,9 @@ impl Clock {
     }
 
     fn tick(&mut self, t:u8) {
-        self.m += t as u32;
-        self.t += t as u32 * 4;
+        // Let the clock roll over, rather than crash after a few minutes
+        self.m = self.m.wrapping_add(t as u32);
+        self.t = self.t.wrapping_add(t as u32 * 4);
     }
 }
 
@@ -89,9 +100,18 @@ pub struct Z80 {
     clock: Clock,
     regs: RegisterSet,
     mmu: ::mmu::MMU,
+    ime: bool,     // `Interrupt Master Enable`: set by EI, cleared by DI
+    ei_pending: bool, // EI only takes effect after the next instruction
+    halted: bool,  // Set by HALT
+    halt_bug: bool, // The byte after a buggy HALT is read twice
+    stopped: bool, // Set by STOP
+    locked: bool,  // Set by an unused opcode, which hangs the CPU for good
+    // T-cycles of this instruction already passed to the MMU, one M-cycle
+    // for each memory access
+    bus_cycles: u32,
 }
 
-/// CPU Opcode Macro Definitions
+// CPU Opcode Macro Definitions
 
 /// LD   r,r         xx         4 ---- r=r
 /// Load a register r1 with another register r2.
@@ -169,19 +189,110 @@ macro_rules! ADDr {
 /// ADC  A,r         8x         4 z0hc A=A+r+cy
 /// Add the the contents of register r to register a. If the carry bit is set,
 /// add 1 to the result.
-
 macro_rules! ADCr {
     ($cpu:ident, $r:ident) => (
         {
             let a = $cpu.regs.a;
             let r = $cpu.regs.$r;
-            $cpu.regs.a = $cpu.add8(a, r);
-            if $cpu.flag_is_set(CARRY) {
-                // This will never overflow (if the carry bit is set,
-                // it means we had an overflow and wrapped back around),
-                // so the direct add is ok
-                $cpu.regs.a += 1;
-            }
+            $cpu.regs.a = $cpu.adc8(a, r);
+            $cpu.clock.tick(1);
+        }
+    )
+}
+
+/// SUB  r           9x         4 z1hc A=A-r
+/// Subtract register r from register a
+macro_rules! SUBr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let a = $cpu.regs.a;
+            let r = $cpu.regs.$r;
+            $cpu.regs.a = $cpu.sub8(a, r);
+            $cpu.clock.tick(1);
+        }
+    )
+}
+
+/// SBC  A,r         9x         4 z1hc A=A-r-cy
+/// Subtract register r from register a. If the carry bit is set,
+/// subtract 1 more.
+macro_rules! SBCr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let a = $cpu.regs.a;
+            let r = $cpu.regs.$r;
+            $cpu.regs.a = $cpu.sbc8(a, r);
+            $cpu.clock.tick(1);
+        }
+    )
+}
+
+/// AND  r           Ax         4 z010 A=A & r
+macro_rules! ANDr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let a = $cpu.regs.a;
+            let r = $cpu.regs.$r;
+            $cpu.regs.a = $cpu.and8(a, r);
+            $cpu.clock.tick(1);
+        }
+    )
+}
+
+/// XOR  r           Ax         4 z000
+macro_rules! XORr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let a = $cpu.regs.a;
+            let r = $cpu.regs.$r;
+            $cpu.regs.a = $cpu.xor8(a, r);
+            $cpu.clock.tick(1);
+        }
+    )
+}
+
+/// OR   r           Bx         4 z000 A=A | r
+macro_rules! ORr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let a = $cpu.regs.a;
+            let r = $cpu.regs.$r;
+            $cpu.regs.a = $cpu.or8(a, r);
+            $cpu.clock.tick(1);
+        }
+    )
+}
+
+/// CP   r           Bx         4 z1hc compare A-r
+/// Subtract register r from register a, but only keep the flags
+macro_rules! CPr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let a = $cpu.regs.a;
+            let r = $cpu.regs.$r;
+            $cpu.sub8(a, r);
+            $cpu.clock.tick(1);
+        }
+    )
+}
+
+/// INC  r           xx         4 z0h- r=r+1
+macro_rules! INCr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let r = $cpu.regs.$r;
+            $cpu.regs.$r = $cpu.inc8(r);
+            $cpu.clock.tick(1);
+        }
+    )
+}
+
+/// DEC  r           xx         4 z1h- r=r-1
+macro_rules! DECr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let r = $cpu.regs.$r;
+            $cpu.regs.$r = $cpu.dec8(r);
             $cpu.clock.tick(1);
         }
     )
@@ -232,65 +343,207 @@ macro_rules! RLCr {
     ($cpu:ident, $r:ident) => (
         {
             let r = $cpu.regs.$r;
-            let leading_digit = r & 0x80;
-
-            $cpu.regs.$r = $cpu.rotate_left(r);
-
-            if leading_digit != 0 {
-                $cpu.set_flag(CARRY)
-            } else {
-                $cpu.unset_flag(CARRY)
-            }
-
+            $cpu.regs.$r = $cpu.rlc8(r);
             $cpu.clock.tick(2);
         }
     )
 }
 
+/// RL   r         CB 1x        8 z00c rotate left through carry
 macro_rules! RLr {
     ($cpu:ident, $r:ident) => (
         {
             let r = $cpu.regs.$r;
-            $cpu.regs.$r = $cpu.rotate_left_carry(r);
-
+            $cpu.regs.$r = $cpu.rl8(r);
             $cpu.clock.tick(2);
         }
     )
 }
 
+/// RRC  r         CB 0x        8 z00c rotate right
 macro_rules! RRCr {
     ($cpu:ident, $r:ident) => (
         {
             let r = $cpu.regs.$r;
-            let trailing_digit = r & 0x01;
+            $cpu.regs.$r = $cpu.rrc8(r);
+            $cpu.clock.tick(2);
+        }
+    )
+}
 
-            $cpu.regs.$r = $cpu.rotate_right(r);
+/// RR   r         CB 1x        8 z00c rotate right through carry
+macro_rules! RRr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let r = $cpu.regs.$r;
+            $cpu.regs.$r = $cpu.rr8(r);
+            $cpu.clock.tick(2);
+        }
+    )
+}
 
-            if trailing_digit != 0 {
-                $cpu.set_flag(CARRY)
-            } else {
-                $cpu.unset_flag(CARRY)
-            }
+/// SLA  r         CB 2x        8 z00c shift left arithmetic (b0=0)
+macro_rules! SLAr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let r = $cpu.regs.$r;
+            $cpu.regs.$r = $cpu.sla8(r);
+            $cpu.clock.tick(2);
+        }
+    )
+}
 
+/// SRA  r         CB 2x        8 z00c shift right arithmetic (b7=b7)
+macro_rules! SRAr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let r = $cpu.regs.$r;
+            $cpu.regs.$r = $cpu.sra8(r);
             $cpu.clock.tick(2);
         }
     )
 }
 
-macro_rules! RRr {
+/// SWAP r         CB 3x        8 z000 exchange low/hi-nibble
+macro_rules! SWAPr {
+    ($cpu:ident, $r:ident) => (
+        {
+            let r = $cpu.regs.$r;
+            $cpu.regs.$r = $cpu.swap8(r);
+            $cpu.clock.tick(2);
+        }
+    )
+}
+
+/// SRL  r         CB 3x        8 z00c shift right logical (b7=0)
+macro_rules! SRLr {
     ($cpu:ident, $r:ident) => (
         {
             let r = $cpu.regs.$r;
-            $cpu.regs.$r = $cpu.rotate_right_carry(r);
+            $cpu.regs.$r = $cpu.srl8(r);
+            $cpu.clock.tick(2);
+        }
+    )
+}
+
+/// BIT  n,r       CB xx        8 z01- test bit n
+/// Set the zero flag if bit n of register r is *not* set
+macro_rules! BITnr {
+    ($cpu:ident, $n:ident, $r:ident) => (
+        {
+            let r = $cpu.regs.$r;
+            $cpu.test_bit($n, r);
+            $cpu.clock.tick(2);
+        }
+    )
+}
+
+/// SET  n,r       CB xx        8 ---- set bit n
+macro_rules! SETnr {
+    ($cpu:ident, $n:ident, $r:ident) => (
+        {
+            $cpu.regs.$r |= 1 << $n;
+            $cpu.clock.tick(2);
+        }
+    )
+}
 
+/// RES  n,r       CB xx        8 ---- reset bit n
+macro_rules! RESnr {
+    ($cpu:ident, $n:ident, $r:ident) => (
+        {
+            $cpu.regs.$r &= !(1 << $n);
             $cpu.clock.tick(2);
         }
     )
 }
 
+/// JP   f,nn      xx nn nn 16;12 ---- conditional jump if nz,z,nc,c
+/// Jump to the immediate word nn if `flag` is `set` (or not). Taking the
+/// jump costs an extra tick.
+macro_rules! JPfnn {
+    ($cpu:ident, $flag:ident, $set:expr) => (
+        {
+            let nn = $cpu.read_immediate_word();
+            if $cpu.flag_is_set($flag) == $set {
+                $cpu.regs.pc = nn;
+                $cpu.clock.tick(4);
+            } else {
+                $cpu.clock.tick(3);
+            }
+        }
+    )
+}
+
+/// JR   f,PC+dd   xx dd     12;8 ---- conditional relative jump if nz,z,nc,c
+/// Jump by the signed immediate byte dd if `flag` is `set` (or not).
+macro_rules! JRfn {
+    ($cpu:ident, $flag:ident, $set:expr) => (
+        {
+            let dd = $cpu.read_immediate_byte();
+            if $cpu.flag_is_set($flag) == $set {
+                $cpu.relative_jump(dd);
+                $cpu.clock.tick(3);
+            } else {
+                $cpu.clock.tick(2);
+            }
+        }
+    )
+}
+
+/// CALL f,nn      xx nn nn 24;12 ---- conditional call if nz,z,nc,c
+/// Push the address of the next instruction and jump to nn if `flag` is
+/// `set` (or not).
+macro_rules! CALLfnn {
+    ($cpu:ident, $flag:ident, $set:expr) => (
+        {
+            let nn = $cpu.read_immediate_word();
+            if $cpu.flag_is_set($flag) == $set {
+                let pc = $cpu.regs.pc;
+                $cpu.stack_push(pc);
+                $cpu.regs.pc = nn;
+                $cpu.clock.tick(6);
+            } else {
+                $cpu.clock.tick(3);
+            }
+        }
+    )
+}
 
+/// RET  f         xx        20;8 ---- conditional return if nz,z,nc,c
+/// Pop the program counter off the stack if `flag` is `set` (or not).
+macro_rules! RETf {
+    ($cpu:ident, $flag:ident, $set:expr) => (
+        {
+            if $cpu.flag_is_set($flag) == $set {
+                $cpu.regs.pc = $cpu.stack_pop();
+                $cpu.clock.tick(5);
+            } else {
+                $cpu.clock.tick(2);
+            }
+        }
+    )
+}
 
+/// RST  n         xx          16 ---- call to 00,08,10,18,20,28,30,38
+/// Push the address of the next instruction and jump to a fixed address
+/// in the first page of memory.
+macro_rules! RSTn {
+    ($cpu:ident, $n:expr) => (
+        {
+            let pc = $cpu.regs.pc;
+            $cpu.stack_push(pc);
+            $cpu.regs.pc = $n;
+            $cpu.clock.tick(4);
+        }
+    )
+}
 
+impl Default for Z80 {
+    fn default() -> Z80 {
+        Z80::new()
+    }
+}
 
 impl Z80 {
     pub fn new() -> Z80 {
@@ -298,33 +551,228 @@ impl Z80 {
             clock: Clock::new(),
             regs: RegisterSet::new(),
             mmu: ::mmu::MMU::new(),
+            ime: false,
+            ei_pending: false,
+            halted: false,
+            halt_bug: false,
+            stopped: false,
+            locked: false,
+            bus_cycles: 0,
+        }
+    }
+
+    /// Plug a cartridge in. The CPU starts running it from 0x0100.
+    pub fn load_cartridge(&mut self, cartridge: ::cartridge::Cartridge) {
+        self.mmu.load_cartridge(cartridge);
+    }
+
+    /// Start up by running a boot ROM from 0x0000, the way the real
+    /// hardware does.
+    pub fn load_boot_rom<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.mmu.load_boot_rom(fs::read(path)?)?;
+        self.regs = RegisterSet::new();
+        self.regs.pc = 0x0000;
+        Ok(())
+    }
+
+    /// Start at 0x0100 without a boot ROM, with everything set up the
+    /// way `model`'s boot ROM would have left it. The CGB's own registers
+    /// are only set up if a CGB cartridge has turned CGB mode on.
+    pub fn skip_boot(&mut self, model: Model) {
+        let [a, f, b, c, d, e, h, l] = ::boot::registers(model);
+        self.regs = RegisterSet { a, f, b, c, d, e, h, l, pc: 0x0100, sp: 0xFFFE };
+        for (address, val) in ::boot::io_registers(model) {
+            self.mmu.write_byte(address, val);
+        }
+        self.mmu.unmap_boot_rom();
+    }
+
+    /// The buttons. Press and release them between frames.
+    pub fn joypad_mut(&mut self) -> &mut ::joypad::Joypad {
+        self.mmu.joypad_mut()
+    }
+
+    /// The sound. See `Apu::set_sample_rate` and `Apu::take_samples`.
+    pub fn apu_mut(&mut self) -> &mut ::apu::Apu {
+        self.mmu.apu_mut()
+    }
+
+    /// Plug something into the link port. See `serial::SerialLink`.
+    pub fn set_serial_link(&mut self, link: Box<dyn SerialLink>) {
+        self.mmu.serial_mut().set_link(link);
+    }
+
+    /// The screen, as drawn by the last frame. See `GPU::framebuffer`.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.mmu.gpu().framebuffer()
+    }
+
+    /// The screen in colour. See `GPU::rgb555_framebuffer`.
+    pub fn rgb555_framebuffer(&self) -> &[u16] {
+        self.mmu.gpu().rgb555_framebuffer()
+    }
+
+    /// Whether the Game Boy Color's hardware is on, which it is for games
+    /// made for it.
+    pub fn is_cgb(&self) -> bool {
+        self.mmu.cgb()
+    }
+
+    /// Whether the CPU is waiting in HALT for an interrupt.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Whether the whole system is waiting in STOP for a button press.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Whether the CPU has hung on an unused opcode.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Run a single instruction, and return how many clock cycles it took.
+    pub fn step(&mut self) -> u32 {
+        let start = self.clock.t;
+        if self.locked {
+            // Nothing gets the CPU going again, not even interrupts, but
+            // the rest of the system carries on
+            self.clock.tick(1);
+        } else if self.stopped {
+            // Nothing runs in STOP, not even interrupts. The only way out
+            // is a button press, which requests the joypad interrupt.
+            if self.mmu.requested_interrupts() & ::interrupt::JOYPAD != 0 {
+                self.stopped = false;
+            }
+            self.clock.tick(1);
+        } else if self.service_interrupts() {
+            // Jumping to the interrupt handler takes the whole step
+        } else if self.halted {
+            // Burn time until an interrupt comes in. That wakes the CPU
+            // up even when IME is off; it just isn't serviced.
+            if self.mmu.pending_interrupts() != 0 {
+                self.halted = false;
+            }
+            self.clock.tick(1);
+        } else {
+            // An EI takes effect after the instruction that follows it
+            let enable_interrupts = self.ei_pending;
+            // Read an instruction from memory, and move past it. Any
+            // immediate values are read by the instruction itself, which
+            // leaves the program counter at the next instruction.
+            let opcode = if self.halt_bug {
+                // ...except after the HALT bug, when the CPU forgets
+                // to move past it
+                self.halt_bug = false;
+                let pc = self.regs.pc;
+                self.read(pc)
+            } else {
+                self.read_immediate_byte()
+            };
+            self.call(opcode);
+            // ...unless that instruction was a DI
+            if enable_interrupts && self.ei_pending {
+                self.ime = true;
+                self.ei_pending = false;
+            }
+        }
+        // Let the rest of the system catch up on whatever time the
+        // instruction didn't spend on the bus
+        let cycles = self.clock.t.wrapping_sub(start);
+        self.mmu.tick(cycles.saturating_sub(self.bus_cycles));
+        self.bus_cycles = 0;
+        cycles
+    }
+
+    /// If interrupts are enabled and one is pending, call its handler.
+    /// Returns whether an interrupt was serviced.
+    fn service_interrupts(&mut self) -> bool {
+        if !self.ime {
+            return false;
+        }
+        let pending = self.mmu.pending_interrupts();
+        match ::interrupt::highest_priority(pending) {
+            Some(interrupt) => {
+                // Interrupts are disabled until the handler re-enables them,
+                // usually with RETI
+                self.ime = false;
+                self.halted = false;
+                self.mmu.acknowledge_interrupt(interrupt);
+                self.tick_m_cycle();
+                let pc = self.regs.pc;
+                self.stack_push(pc);
+                self.regs.pc = ::interrupt::vector(interrupt);
+                self.clock.tick(5);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Run instructions until at least `cycles` clock cycles have gone by,
+    /// and return how many actually did. An instruction is never split, so
+    /// this can run a few cycles over.
+    pub fn run_for_cycles(&mut self, cycles: u32) -> u32 {
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            elapsed += self.step();
+        }
+        elapsed
+    }
+
+    /// Run for the length of one screen refresh. At double speed, that's
+    /// twice as many cycles.
+    pub fn run_frame(&mut self) -> u32 {
+        if self.mmu.double_speed() {
+            self.run_for_cycles(CYCLES_PER_FRAME * 2)
+        } else {
+            self.run_for_cycles(CYCLES_PER_FRAME)
         }
     }
 
     // Utilities
+
+    /// Let one M-cycle go by for the rest of the system. Memory accesses
+    /// each take one, so IO registers like TIMA see the hardware as it is
+    /// partway through the instruction, not before it.
+    fn tick_m_cycle(&mut self) {
+        self.mmu.tick(4);
+        self.bus_cycles += 4;
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick_m_cycle();
+        self.mmu.read(address)
+    }
+
+    fn write(&mut self, address: u16, val: u8) {
+        self.tick_m_cycle();
+        self.mmu.write_byte(address, val);
+    }
+
     fn read_hl(&mut self) -> u8 {
         let hl = self.regs.hl();
-        self.mmu.read(hl)
+        self.read(hl)
     }
 
     fn write_hl(&mut self, b: u8) {
         let hl = self.regs.hl();
-        self.mmu.write_byte(hl, b);
+        self.write(hl, b);
     }
 
     fn read_immediate_byte(&mut self) -> u8 {
-        let n = self.mmu.read(self.regs.pc);
-        self.regs.pc += 1;
+        let pc = self.regs.pc;
+        let n = self.read(pc);
+        self.regs.pc = self.regs.pc.wrapping_add(1);
         n
     }
 
     fn read_immediate_word(&mut self) -> u16 {
-        let small_byte = self.mmu.read(self.regs.pc) as u16;
-        self.regs.pc += 1;
-        let large_byte = self.mmu.read(self.regs.pc) as u16;
-        self.regs.pc += 1;
-        let nn = large_byte<<8 | small_byte;
-        nn
+        let small_byte = self.read_immediate_byte() as u16;
+        let large_byte = self.read_immediate_byte() as u16;
+        large_byte<<8 | small_byte
     }
 
     fn flag_is_set(&mut self, flag: u8) -> bool {
@@ -332,28 +780,43 @@ impl Z80 {
     }
 
     fn clear_flags(&mut self) {
-        self.regs.f = 0x0;;
+        self.regs.f = 0x0;
     }
 
     fn set_flag(&mut self, flag: u8) {
-        self.regs.f = self.regs.f | flag;
+        self.regs.f |= flag;
     }
 
     fn unset_flag(&mut self, flag: u8) {
         let inverse_flag : u8 = !flag;
-        self.regs.f = self.regs.f & inverse_flag;
+        self.regs.f &= inverse_flag;
+    }
+
+    // Jump Utilities
+    fn relative_jump(&mut self, dd: u8) {
+        // dd is a signed offset from the *next* instruction
+        let offset = dd as i8 as i16 as u16;
+        self.regs.pc = self.regs.pc.wrapping_add(offset);
     }
 
     // Stack Utilities
     fn stack_push(&mut self, word: u16) {
-        // The stack is written from the end of memory toward the beginning
-        self.mmu.write_word(self.regs.sp, word);
-        self.regs.sp -= 2;
+        // The stack grows from the end of memory toward the beginning,
+        // and SP always points at the last word pushed. Decrementing SP
+        // takes a cycle of its own, and then the high byte goes first.
+        self.tick_m_cycle();
+        let sp = self.regs.sp;
+        self.write(sp.wrapping_sub(1), (word >> 8) as u8);
+        self.write(sp.wrapping_sub(2), word as u8);
+        self.regs.sp = sp.wrapping_sub(2);
     }
 
     fn stack_pop(&mut self) -> u16 {
-        self.regs.sp += 2;
-        self.mmu.read_word(self.regs.sp)
+        let sp = self.regs.sp;
+        let low = self.read(sp) as u16;
+        let high = self.read(sp.wrapping_add(1)) as u16;
+        self.regs.sp = sp.wrapping_add(2);
+        high << 8 | low
     }
 
     // Arithmetic Utilities
@@ -397,62 +860,194 @@ impl Z80 {
         a << 1 | carry as u8
     }
 
-    fn add8(&mut self, a:u8, b:u8) -> u8 {
-        // Cheat by holding result in a u16
-        let overflowing_sum : u16 = a as u16 + b as u16;
-        // Set the appropriate flags
+    fn set_shift_flags(&mut self, result:u8, carry:bool) {
+        // Every CB-prefixed rotate and shift sets z00c
         self.clear_flags();
-        if overflowing_sum == 0   { self.set_flag(ZERO) }
-        if overflowing_sum > 0xF  { self.set_flag(HALFCARRY) }
-        if overflowing_sum > 0xFF { self.set_flag(CARRY) }
-        // Mask result to 8 bits
-        let sum = overflowing_sum as u8;
-        sum
+        if result == 0 { self.set_flag(ZERO) }
+        if carry       { self.set_flag(CARRY) }
     }
 
-    fn add16(&mut self, a:u16, b:u16) -> u16 {
-        let overflowing_sum : u32 = a as u32 + b as u32;
-        // Set the appropriate flags
-        self.clear_flags();
-        if overflowing_sum == 0     { self.set_flag(ZERO) }
-        if overflowing_sum > 0xFF   { self.set_flag(HALFCARRY) }
-        if overflowing_sum > 0xFFFF { self.set_flag(CARRY) }
-        // Mask result to 8 bits
-        let sum = overflowing_sum as u16;
-        sum
+    fn rlc8(&mut self, n:u8) -> u8 {
+        let result = self.rotate_left(n);
+        self.set_shift_flags(result, n & 0x80 != 0);
+        result
     }
 
-    fn inc16(&mut self, n:u16) -> u16 {
-        let overflowing_sum : u32 = n as u32 + 1;
-        self.clear_flags();
-        if overflowing_sum == 0 { self.set_flag(ZERO) }
-        // NOTE(Lito): The manual says to NOT set the carry flag when INC
-        // overflows. That doesn't sound right, but let's leave it for now
-        let sum = overflowing_sum as u16;
-        sum
+    fn rl8(&mut self, n:u8) -> u8 {
+        let result = self.rotate_left_carry(n);
+        self.set_shift_flags(result, n & 0x80 != 0);
+        result
     }
 
-    // Z-80 CPU Instruction Set
-    // ---- --- ----------- ---
+    fn rrc8(&mut self, n:u8) -> u8 {
+        let result = self.rotate_right(n);
+        self.set_shift_flags(result, n & 0x01 != 0);
+        result
+    }
 
-    fn CCF(&mut self) { // Complement carry flag.
-        if self.flag_is_set(CARRY) {
-            self.unset_flag(CARRY);
-        } else {
-            self.set_flag(CARRY);
-        }
-        self.clock.tick(1)
+    fn rr8(&mut self, n:u8) -> u8 {
+        let result = self.rotate_right_carry(n);
+        self.set_shift_flags(result, n & 0x01 != 0);
+        result
     }
 
-    /// 8-bit Load Commands
-    /// ----- ---- --------
+    fn sla8(&mut self, n:u8) -> u8 {
+        let result = self.shift_left(n);
+        self.set_shift_flags(result, n & 0x80 != 0);
+        result
+    }
 
-    /// LD   r,r         xx         4 ---- r=r
-    /// Load a register r1 with another register r2.
-    /// See the macro definition LDrr above.
-    /// This works for registers a, b, c, d, e, h, and l!
+    fn sra8(&mut self, n:u8) -> u8 {
+        // An arithmetic shift keeps the sign bit where it is
+        let result = self.shift_right(n) | (n & 0x80);
+        self.set_shift_flags(result, n & 0x01 != 0);
+        result
+    }
 
-    //TODO(Lito): there's GOTTA be a way to metaprogram most of this away.
+    fn srl8(&mut self, n:u8) -> u8 {
+        let result = self.shift_right(n);
+        self.set_shift_flags(result, n & 0x01 != 0);
+        result
+    }
+
+    fn swap8(&mut self, n:u8) -> u8 {
+        let result = n.rotate_left(4);
+        self.set_shift_flags(result, false);
+        result
+    }
+
+    fn test_bit(&mut self, bit:u8, n:u8) {
+        // BIT leaves the carry flag alone
+        self.unset_flag(ZERO | SUBTRACT);
+        self.set_flag(HALFCARRY);
+        if n & (1 << bit) == 0 { self.set_flag(ZERO) }
+    }
+
+    fn add8(&mut self, a:u8, b:u8) -> u8 {
+        // A plain add is an add-with-carry where the carry is never set
+        self.unset_flag(CARRY);
+        self.adc8(a, b)
+    }
+
+    fn adc8(&mut self, a:u8, b:u8) -> u8 {
+        let carry = self.flag_is_set(CARRY) as u16;
+        // Cheat by holding result in a u16
+        let overflowing_sum : u16 = a as u16 + b as u16 + carry;
+        // The half-carry is a carry out of the low nibble
+        let half_sum : u16 = (a & 0x0F) as u16 + (b & 0x0F) as u16 + carry;
+        // Mask result to 8 bits
+        let sum = overflowing_sum as u8;
+        // Set the appropriate flags
+        self.clear_flags();
+        if sum == 0               { self.set_flag(ZERO) }
+        if half_sum > 0xF         { self.set_flag(HALFCARRY) }
+        if overflowing_sum > 0xFF { self.set_flag(CARRY) }
+        sum
+    }
+
+    fn sub8(&mut self, a:u8, b:u8) -> u8 {
+        self.unset_flag(CARRY);
+        self.sbc8(a, b)
+    }
+
+    fn sbc8(&mut self, a:u8, b:u8) -> u8 {
+        let carry = self.flag_is_set(CARRY) as i16;
+        // Hold the result in a signed number so we can see it borrow
+        let difference : i16 = a as i16 - b as i16 - carry;
+        let half_difference : i16 = (a & 0x0F) as i16 - (b & 0x0F) as i16 - carry;
+        let result = difference as u8;
+        self.clear_flags();
+        self.set_flag(SUBTRACT);
+        if result == 0          { self.set_flag(ZERO) }
+        if half_difference < 0  { self.set_flag(HALFCARRY) }
+        if difference < 0       { self.set_flag(CARRY) }
+        result
+    }
+
+    fn and8(&mut self, a:u8, b:u8) -> u8 {
+        let result = a & b;
+        self.clear_flags();
+        self.set_flag(HALFCARRY);
+        if result == 0 { self.set_flag(ZERO) }
+        result
+    }
+
+    fn xor8(&mut self, a:u8, b:u8) -> u8 {
+        let result = a ^ b;
+        self.clear_flags();
+        if result == 0 { self.set_flag(ZERO) }
+        result
+    }
+
+    fn or8(&mut self, a:u8, b:u8) -> u8 {
+        let result = a | b;
+        self.clear_flags();
+        if result == 0 { self.set_flag(ZERO) }
+        result
+    }
+
+    fn inc8(&mut self, n:u8) -> u8 {
+        let result = n.wrapping_add(1);
+        // INC leaves the carry flag alone
+        self.unset_flag(ZERO | SUBTRACT | HALFCARRY);
+        if result == 0       { self.set_flag(ZERO) }
+        if n & 0x0F == 0x0F  { self.set_flag(HALFCARRY) }
+        result
+    }
+
+    fn dec8(&mut self, n:u8) -> u8 {
+        let result = n.wrapping_sub(1);
+        // DEC leaves the carry flag alone, too
+        self.unset_flag(ZERO | HALFCARRY);
+        self.set_flag(SUBTRACT);
+        if result == 0       { self.set_flag(ZERO) }
+        if n & 0x0F == 0x00  { self.set_flag(HALFCARRY) }
+        result
+    }
+
+    fn add16(&mut self, a:u16, b:u16) -> u16 {
+        let overflowing_sum : u32 = a as u32 + b as u32;
+        // Set the appropriate flags. The zero flag is left alone, and
+        // the half-carry is a carry out of bit 11.
+        self.unset_flag(SUBTRACT | HALFCARRY | CARRY);
+        if (a & 0x0FFF) + (b & 0x0FFF) > 0x0FFF { self.set_flag(HALFCARRY) }
+        if overflowing_sum > 0xFFFF             { self.set_flag(CARRY) }
+        // Mask result to 16 bits
+        overflowing_sum as u16
+    }
+
+    fn inc16(&mut self, n:u16) -> u16 {
+        // 16-bit increments don't touch the flags at all
+        n.wrapping_add(1)
+    }
+
+    fn dec16(&mut self, n:u16) -> u16 {
+        n.wrapping_sub(1)
+    }
+
+    fn add_sp_offset(&mut self, dd:u8) -> u16 {
+        // SP+dd, where dd is signed. The flags come from treating the
+        // low byte of SP and dd as an unsigned 8-bit add.
+        let sp = self.regs.sp;
+        let offset = dd as i8 as i16 as u16;
+        self.clear_flags();
+        if (sp & 0x000F) + (dd as u16 & 0x000F) > 0x000F { self.set_flag(HALFCARRY) }
+        if (sp & 0x00FF) + (dd as u16) > 0x00FF          { self.set_flag(CARRY) }
+        sp.wrapping_add(offset)
+    }
+
+    // Z-80 CPU Instruction Set
+    // ---- --- ----------- ---
+
+    // 8-bit Load Commands
+    // ----- ---- --------
+
+    // LD   r,r         xx         4 ---- r=r
+    // Load a register r1 with another register r2.
+    // See the macro definition LDrr above.
+    // This works for registers a, b, c, d, e, h, and l!
+
+    //TODO(Lito): there's GOTTA be a way to metaprogram most of this away.
 
     // a
     fn LDrr_aa(&mut self) { LDrr!(self,a,a); }
@@ -521,7 +1116,6 @@ impl Z80 {
     /// Load a register r with a constant n, read from
     /// the immediate value under the progam counter
     /// This works for registers a, b, c, d, e, h, and l!
-
     fn LDrn_a(&mut self) { LDrn!(self,a); }
     fn LDrn_b(&mut self) { LDrn!(self,b); }
     fn LDrn_c(&mut self) { LDrn!(self,c); }
@@ -563,7 +1157,7 @@ impl Z80 {
     /// Load register a with the value at location BC
     fn LDABCm(&mut self) {
         let bc = self.regs.bc();
-        let bc_value = self.mmu.read(bc);
+        let bc_value = self.read(bc);
         self.regs.a = bc_value;
 
         self.clock.tick(2);
@@ -573,7 +1167,7 @@ impl Z80 {
     /// Load register a with the value at location DE
     fn LDADEm(&mut self) {
         let de = self.regs.de();
-        let de_value = self.mmu.read(de);
+        let de_value = self.read(de);
         self.regs.a = de_value;
 
         self.clock.tick(2);
@@ -584,7 +1178,7 @@ impl Z80 {
     /// immediate value with the least significant byte first.
     fn LDAnn(&mut self) {
         let nn = self.read_immediate_word();
-        let nn_value = self.mmu.read(nn);
+        let nn_value = self.read(nn);
         self.regs.a = nn_value;
         self.clock.tick(4);
     }
@@ -594,7 +1188,7 @@ impl Z80 {
     fn LDBCmA(&mut self) {
         let a = self.regs.a;
         let bc = self.regs.bc();
-        self.mmu.write_byte(bc, a);
+        self.write(bc, a);
         self.clock.tick(2);
     }
 
@@ -603,7 +1197,7 @@ impl Z80 {
     fn LDDEmA(&mut self) {
         let a = self.regs.a;
         let de = self.regs.de();
-        self.mmu.write_byte(de, a);
+        self.write(de, a);
 
         self.clock.tick(2);
     }
@@ -613,7 +1207,7 @@ impl Z80 {
     fn LDnmA(&mut self) {
         let nn = self.read_immediate_word();
         let a = self.regs.a;
-        self.mmu.write_byte(nn, a);
+        self.write(nn, a);
 
         self.clock.tick(4);
     }
@@ -644,8 +1238,72 @@ impl Z80 {
         self.clock.tick(2);
     }
 
-    /// 16-bit Load Commands
-    /// ------ ---- --------
+    /// LDD  (HL),A      32         8 ---- (HL)=A, HL=HL-1
+    /// Load location HL with contents of register a, then decrement HL
+    fn LDDHLmA(&mut self) {
+        let a = self.regs.a;
+        self.write_hl(a);
+
+        let hl = self.regs.hl();
+        let hld = self.dec16(hl);
+        self.regs.set_hl(hld);
+
+        self.clock.tick(2);
+    }
+
+    /// LDD  A,(HL)      3A         8 ---- A=(HL), HL=HL-1
+    /// Load register a with contents of location HL, then decrement HL
+    fn LDDAHLm(&mut self) {
+        let hl_value = self.read_hl();
+        self.regs.a = hl_value;
+
+        let hl = self.regs.hl();
+        let hld = self.dec16(hl);
+        self.regs.set_hl(hld);
+
+        self.clock.tick(2);
+    }
+
+    /// LD   (FF00+n),A  E0 nn     12 ---- write to io-port n (memory FF00+n)
+    /// Load the IO port given by the immediate value n with register a
+    fn LDIOnA(&mut self) {
+        let n = self.read_immediate_byte();
+        let a = self.regs.a;
+        self.write(0xFF00 | n as u16, a);
+
+        self.clock.tick(3);
+    }
+
+    /// LD   A,(FF00+n)  F0 nn     12 ---- read from io-port n (memory FF00+n)
+    /// Load register a with the IO port given by the immediate value n
+    fn LDAIOn(&mut self) {
+        let n = self.read_immediate_byte();
+        self.regs.a = self.read(0xFF00 | n as u16);
+
+        self.clock.tick(3);
+    }
+
+    /// LD   (FF00+C),A  E2         8 ---- write to io-port C (memory FF00+C)
+    /// Load the IO port given by register c with register a
+    fn LDIOCA(&mut self) {
+        let c = self.regs.c;
+        let a = self.regs.a;
+        self.write(0xFF00 | c as u16, a);
+
+        self.clock.tick(2);
+    }
+
+    /// LD   A,(FF00+C)  F2         8 ---- read from io-port C (memory FF00+C)
+    /// Load register a with the IO port given by register c
+    fn LDAIOC(&mut self) {
+        let c = self.regs.c;
+        self.regs.a = self.read(0xFF00 | c as u16);
+
+        self.clock.tick(2);
+    }
+
+    // 16-bit Load Commands
+    // ------ ---- --------
 
     /// LD   rr,nn       x1 nn nn  12 ---- rr=nn (rr may be BC,DE,HL or SP)
     /// Load register pair rr with immediate word nn
@@ -668,6 +1326,27 @@ impl Z80 {
         self.clock.tick(2);
     }
 
+    /// LD   (nn),SP     08 nn nn  20 ---- (nn)=SP
+    /// Store the stack pointer at the location given by the immediate word nn
+    fn LDnmSP(&mut self) {
+        let nn = self.read_immediate_word();
+        let sp = self.regs.sp;
+        self.write(nn, sp as u8);
+        self.write(nn.wrapping_add(1), (sp >> 8) as u8);
+
+        self.clock.tick(5);
+    }
+
+    /// LD   HL,SP+dd    F8 dd     12 00hc HL = SP +/- dd ;dd is 8bit signed number
+    /// Load registers HL with the stack pointer plus a signed immediate byte
+    fn LDHLSPn(&mut self) {
+        let dd = self.read_immediate_byte();
+        let result = self.add_sp_offset(dd);
+        self.regs.set_hl(result);
+
+        self.clock.tick(3);
+    }
+
     /// PUSH rr          x5        16 ---- SP=SP-2  (SP)=rr   (rr may be BC,DE,HL,AF)
     /// Push a register pair to the stack
     fn PUSHBC(&mut self) { PUSHrr!(self, bc) }
@@ -680,29 +1359,13 @@ impl Z80 {
     fn POPBC(&mut self) { POPrr!(self, b, c) }
     fn POPDE(&mut self) { POPrr!(self, d, e) }
     fn POPHL(&mut self) { POPrr!(self, h, l) }
-    fn POPAF(&mut self) { POPrr!(self, a, f) }
-
-
-    /*
-       OPCODES
-       =======
-
-# 8-bit Load Commands
-# ----- ---- --------
-LD   A,(FF00+n)  F0 nn     12 ---- read from io-port n (memory FF00+n)
-LD   (FF00+n),A  E0 nn     12 ---- write to io-port n (memory FF00+n)
-LD   A,(FF00+C)  F2         8 ---- read from io-port C (memory FF00+C)
-LD   (FF00+C),A  E2         8 ---- write to io-port C (memory FF00+C)
-LDD  (HL),A      32         8 ---- (HL)=A, HL=HL-1
-LDD  A,(HL)      3A         8 ---- A=(HL), HL=HL-1
-*/
-
+    fn POPAF(&mut self) {
+        POPrr!(self, a, f);
+        // The low nibble of the flags register doesn't exist in hardware
+        self.regs.f &= 0xF0;
+    }
 
-    /*
-# 16-bit Load Commands
-# ------ ---- --------
 
-*/
     /// ADD  A,r         8x         4 z0hc A=A+r
     /// Add any register to A and store the result in A
     fn ADDr_a(&mut self) { ADDr!(self,a); }
@@ -746,13 +1409,9 @@ LDD  A,(HL)      3A         8 ---- A=(HL), HL=HL-1
     /// Add the immediate value n to register a. If the carry bit is set,
     /// add 1 to the result.
     fn ADCn(&mut self) {
-
         let a = self.regs.a;
         let n = self.read_immediate_byte();
-        self.regs.a = self.add8(a, n);
-        if self.flag_is_set(CARRY) {
-            self.regs.a += 1;
-        }
+        self.regs.a = self.adc8(a, n);
         self.clock.tick(2);
     }
 
@@ -760,20 +1419,252 @@ LDD  A,(HL)      3A         8 ---- A=(HL), HL=HL-1
     /// Add the contents of location HL to register a. If the carry bit is set,
     /// add 1 to the result.
     fn ADCHL(&mut self) {
+        let a = self.regs.a;
+        let hl = self.read_hl();
+        self.regs.a = self.adc8(a, hl);
+        self.clock.tick(2);
+    }
+
+    /// SUB  r           9x         4 z1hc A=A-r
+    /// Subtract register r from register a
+    fn SUBr_a(&mut self) { SUBr!(self,a); }
+    fn SUBr_b(&mut self) { SUBr!(self,b); }
+    fn SUBr_c(&mut self) { SUBr!(self,c); }
+    fn SUBr_d(&mut self) { SUBr!(self,d); }
+    fn SUBr_e(&mut self) { SUBr!(self,e); }
+    fn SUBr_h(&mut self) { SUBr!(self,h); }
+    fn SUBr_l(&mut self) { SUBr!(self,l); }
+
+    /// SUB  n           D6 nn      8 z1hc A=A-n
+    /// Subtract the immediate value n from register a
+    fn SUBn(&mut self) {
+        let a = self.regs.a;
+        let n = self.read_immediate_byte();
+        self.regs.a = self.sub8(a, n);
+        self.clock.tick(2);
+    }
 
+    /// SUB  (HL)        96         8 z1hc A=A-(HL)
+    /// Subtract the contents of location HL from register a
+    fn SUBHL(&mut self) {
         let a = self.regs.a;
         let hl = self.read_hl();
-        self.regs.a = self.add8(a, hl);
-        if self.flag_is_set(CARRY) {
-            self.regs.a += 1;
-        }
+        self.regs.a = self.sub8(a, hl);
+        self.clock.tick(2);
+    }
+
+    /// SBC  A,r         9x         4 z1hc A=A-r-cy
+    /// Subtract register r from register a. If the carry bit is set,
+    /// subtract 1 more.
+    fn SBCr_a(&mut self) { SBCr!(self,a); }
+    fn SBCr_b(&mut self) { SBCr!(self,b); }
+    fn SBCr_c(&mut self) { SBCr!(self,c); }
+    fn SBCr_d(&mut self) { SBCr!(self,d); }
+    fn SBCr_e(&mut self) { SBCr!(self,e); }
+    fn SBCr_h(&mut self) { SBCr!(self,h); }
+    fn SBCr_l(&mut self) { SBCr!(self,l); }
+
+    /// SBC  A,n         DE nn      8 z1hc A=A-n-cy
+    fn SBCn(&mut self) {
+        let a = self.regs.a;
+        let n = self.read_immediate_byte();
+        self.regs.a = self.sbc8(a, n);
+        self.clock.tick(2);
+    }
+
+    /// SBC  A,(HL)      9E         8 z1hc A=A-(HL)-cy
+    fn SBCHL(&mut self) {
+        let a = self.regs.a;
+        let hl = self.read_hl();
+        self.regs.a = self.sbc8(a, hl);
+        self.clock.tick(2);
+    }
+
+    /// AND  r           Ax         4 z010 A=A & r
+    /// Bitwise-and register r into register a
+    fn ANDr_a(&mut self) { ANDr!(self,a); }
+    fn ANDr_b(&mut self) { ANDr!(self,b); }
+    fn ANDr_c(&mut self) { ANDr!(self,c); }
+    fn ANDr_d(&mut self) { ANDr!(self,d); }
+    fn ANDr_e(&mut self) { ANDr!(self,e); }
+    fn ANDr_h(&mut self) { ANDr!(self,h); }
+    fn ANDr_l(&mut self) { ANDr!(self,l); }
+
+    /// AND  n           E6 nn      8 z010 A=A & n
+    fn ANDn(&mut self) {
+        let a = self.regs.a;
+        let n = self.read_immediate_byte();
+        self.regs.a = self.and8(a, n);
+        self.clock.tick(2);
+    }
+
+    /// AND  (HL)        A6         8 z010 A=A & (HL)
+    fn ANDHL(&mut self) {
+        let a = self.regs.a;
+        let hl = self.read_hl();
+        self.regs.a = self.and8(a, hl);
+        self.clock.tick(2);
+    }
+
+    /// XOR  r           Ax         4 z000
+    /// Bitwise-xor register r into register a
+    fn XORr_a(&mut self) { XORr!(self,a); }
+    fn XORr_b(&mut self) { XORr!(self,b); }
+    fn XORr_c(&mut self) { XORr!(self,c); }
+    fn XORr_d(&mut self) { XORr!(self,d); }
+    fn XORr_e(&mut self) { XORr!(self,e); }
+    fn XORr_h(&mut self) { XORr!(self,h); }
+    fn XORr_l(&mut self) { XORr!(self,l); }
+
+    /// XOR  n           EE nn      8 z000
+    fn XORn(&mut self) {
+        let a = self.regs.a;
+        let n = self.read_immediate_byte();
+        self.regs.a = self.xor8(a, n);
+        self.clock.tick(2);
+    }
+
+    /// XOR  (HL)        AE         8 z000
+    fn XORHL(&mut self) {
+        let a = self.regs.a;
+        let hl = self.read_hl();
+        self.regs.a = self.xor8(a, hl);
+        self.clock.tick(2);
+    }
+
+    /// OR   r           Bx         4 z000 A=A | r
+    /// Bitwise-or register r into register a
+    fn ORr_a(&mut self) { ORr!(self,a); }
+    fn ORr_b(&mut self) { ORr!(self,b); }
+    fn ORr_c(&mut self) { ORr!(self,c); }
+    fn ORr_d(&mut self) { ORr!(self,d); }
+    fn ORr_e(&mut self) { ORr!(self,e); }
+    fn ORr_h(&mut self) { ORr!(self,h); }
+    fn ORr_l(&mut self) { ORr!(self,l); }
+
+    /// OR   n           F6 nn      8 z000 A=A | n
+    fn ORn(&mut self) {
+        let a = self.regs.a;
+        let n = self.read_immediate_byte();
+        self.regs.a = self.or8(a, n);
+        self.clock.tick(2);
+    }
 
+    /// OR   (HL)        B6         8 z000 A=A | (HL)
+    fn ORHL(&mut self) {
+        let a = self.regs.a;
+        let hl = self.read_hl();
+        self.regs.a = self.or8(a, hl);
+        self.clock.tick(2);
+    }
+
+    /// CP   r           Bx         4 z1hc compare A-r
+    /// Compare register r with register a, setting the flags as if
+    /// r had been subtracted from a
+    fn CPr_a(&mut self) { CPr!(self,a); }
+    fn CPr_b(&mut self) { CPr!(self,b); }
+    fn CPr_c(&mut self) { CPr!(self,c); }
+    fn CPr_d(&mut self) { CPr!(self,d); }
+    fn CPr_e(&mut self) { CPr!(self,e); }
+    fn CPr_h(&mut self) { CPr!(self,h); }
+    fn CPr_l(&mut self) { CPr!(self,l); }
+
+    /// CP   n           FE nn      8 z1hc compare A-n
+    fn CPn(&mut self) {
+        let a = self.regs.a;
+        let n = self.read_immediate_byte();
+        self.sub8(a, n);
+        self.clock.tick(2);
+    }
+
+    /// CP   (HL)        BE         8 z1hc compare A-(HL)
+    fn CPHL(&mut self) {
+        let a = self.regs.a;
+        let hl = self.read_hl();
+        self.sub8(a, hl);
         self.clock.tick(2);
     }
 
+    /// INC  r           xx         4 z0h- r=r+1
+    /// Increment register r
+    fn INCr_a(&mut self) { INCr!(self,a); }
+    fn INCr_b(&mut self) { INCr!(self,b); }
+    fn INCr_c(&mut self) { INCr!(self,c); }
+    fn INCr_d(&mut self) { INCr!(self,d); }
+    fn INCr_e(&mut self) { INCr!(self,e); }
+    fn INCr_h(&mut self) { INCr!(self,h); }
+    fn INCr_l(&mut self) { INCr!(self,l); }
+
+    /// INC  (HL)        34        12 z0h- (HL)=(HL)+1
+    /// Increment the contents of location HL
+    fn INCHLm(&mut self) {
+        let hl = self.read_hl();
+        let result = self.inc8(hl);
+        self.write_hl(result);
+        self.clock.tick(3);
+    }
+
+    /// DEC  r           xx         4 z1h- r=r-1
+    /// Decrement register r
+    fn DECr_a(&mut self) { DECr!(self,a); }
+    fn DECr_b(&mut self) { DECr!(self,b); }
+    fn DECr_c(&mut self) { DECr!(self,c); }
+    fn DECr_d(&mut self) { DECr!(self,d); }
+    fn DECr_e(&mut self) { DECr!(self,e); }
+    fn DECr_h(&mut self) { DECr!(self,h); }
+    fn DECr_l(&mut self) { DECr!(self,l); }
+
+    /// DEC  (HL)        35        12 z1h- (HL)=(HL)-1
+    /// Decrement the contents of location HL
+    fn DECHLm(&mut self) {
+        let hl = self.read_hl();
+        let result = self.dec8(hl);
+        self.write_hl(result);
+        self.clock.tick(3);
+    }
+
+    /// DAA              27         4 z-0x decimal adjust akku
+    /// Fix up register a after an add or subtract of two binary-coded
+    /// decimal numbers, so that each nibble is a digit from 0 to 9 again
+    fn DAA(&mut self) {
+        let a = self.regs.a;
+        let subtract = self.flag_is_set(SUBTRACT);
+        let mut carry = self.flag_is_set(CARRY);
+        let mut adjustment = 0x00;
+
+        if self.flag_is_set(HALFCARRY) || (!subtract && (a & 0x0F) > 0x09) {
+            adjustment |= 0x06;
+        }
+        if carry || (!subtract && a > 0x99) {
+            adjustment |= 0x60;
+            carry = true;
+        }
+
+        let result = if subtract {
+            a.wrapping_sub(adjustment)
+        } else {
+            a.wrapping_add(adjustment)
+        };
+        self.regs.a = result;
+
+        self.unset_flag(ZERO | HALFCARRY | CARRY);
+        if result == 0 { self.set_flag(ZERO) }
+        if carry       { self.set_flag(CARRY) }
+
+        self.clock.tick(1);
+    }
+
+    /// CPL              2F         4 -11- A = A xor FF
+    /// Flip every bit in register a
+    fn CPL(&mut self) {
+        self.regs.a = !self.regs.a;
+        self.set_flag(SUBTRACT | HALFCARRY);
+
+        self.clock.tick(1);
+    }
 
-    /// 16-bit Arithmetic
-    /// ------ ----------
+    // 16-bit Arithmetic
+    // ------ ----------
 
     /// ADD  HL,rr     x9           8 -0hc HL = HL+rr     ;rr may be BC,DE,HL,SP
     /// Add register pair rr to registers HL
@@ -822,43 +1713,46 @@ LDD  A,(HL)      3A         8 ---- A=(HL), HL=HL-1
         self.clock.tick(2);
     }
 
-/*
-# 8-bit Arithmetic Commands
-# ----- ---------- --------
-SUB  r           9x         4 z1hc A=A-r
-    /// Subtract register r from register a
-    SUB  n           D6 nn      8 z1hc A=A-n
-    SUB  (HL)        96         8 z1hc A=A-(HL)
-    SBC  A,r         9x         4 z1hc A=A-r-cy
-    SBC  A,n         DE nn      8 z1hc A=A-n-cy
-    SBC  A,(HL)      9E         8 z1hc A=A-(HL)-cy
-    AND  r           Ax         4 z010 A=A & r
-    AND  n           E6 nn      8 z010 A=A & n
-    AND  (HL)        A6         8 z010 A=A & (HL)
-    XOR  r           Ax         4 z000
-    XOR  n           EE nn      8 z000
-    XOR  (HL)        AE         8 z000
-    OR   r           Bx         4 z000 A=A | r
-    OR   n           F6 nn      8 z000 A=A | n
-    OR   (HL)        B6         8 z000 A=A | (HL)
-    CP   r           Bx         4 z1hc compare A-r
-    CP   n           FE nn      8 z1hc compare A-n
-    CP   (HL)        BE         8 z1hc compare A-(HL)
-    INC  r           xx         4 z0h- r=r+1
-    INC  (HL)        34        12 z0h- (HL)=(HL)+1
-    DEC  r           xx         4 z1h- r=r-1
-    DEC  (HL)        35        12 z1h- (HL)=(HL)-1
-    DAA              27         4 z-0x decimal adjust akku
-    CPL              2F         4 -11- A = A xor FF
-
-
-# 16-bit Arithmetic Commands
-# ------ ---------- --------
-DEC  rr        xB           8 ---- rr = rr-1      ;rr may be BC,DE,HL,SP
-ADD  SP,dd     E8          16 00hc SP = SP +/- dd ;dd is 8bit signed number
-LD   HL,SP+dd  F8          12 00hc HL = SP +/- dd ;dd is 8bit signed number
-
-*/
+    /// DEC  rr        xB           8 ---- rr = rr-1      ;rr may be BC,DE,HL,SP
+    /// Decrement register pair rr
+    fn DECBC(&mut self) {
+        let bc = self.regs.bc();
+        let result = self.dec16(bc);
+        self.regs.set_bc(result);
+
+        self.clock.tick(2);
+    }
+    fn DECDE(&mut self) {
+        let de = self.regs.de();
+        let result = self.dec16(de);
+        self.regs.set_de(result);
+
+        self.clock.tick(2);
+    }
+    fn DECHL(&mut self) {
+        let hl = self.regs.hl();
+        let result = self.dec16(hl);
+        self.regs.set_hl(result);
+
+        self.clock.tick(2);
+    }
+
+    fn DECSP(&mut self) {
+        let sp = self.regs.sp;
+        let spd = self.dec16(sp);
+        self.regs.sp = spd;
+
+        self.clock.tick(2);
+    }
+
+    /// ADD  SP,dd     E8          16 00hc SP = SP +/- dd ;dd is 8bit signed number
+    /// Add a signed immediate byte to the stack pointer
+    fn ADDSPn(&mut self) {
+        let dd = self.read_immediate_byte();
+        self.regs.sp = self.add_sp_offset(dd);
+
+        self.clock.tick(4);
+    }
 
     /// RLCA           07           4 000c rotate akku left
     /// Rotate register a to the left, and set the carry flag to whatever
@@ -873,39 +1767,22 @@ LD   HL,SP+dd  F8          12 00hc HL = SP +/- dd ;dd is 8bit signed number
         } else {
             self.unset_flag(CARRY)
         }
+        self.unset_flag(ZERO | SUBTRACT | HALFCARRY);
 
         self.clock.tick(1);
     }
 
-    /// RLC  r         CB 0x        8 z00c rotate left
-    /// Rotate register r to the left - which takes an extra tick - and set
-    /// the carry flag to whatever the leftmost digit
-    fn RLCr_b(&mut self) { RLCr!(self, b) }
-    fn RLCr_c(&mut self) { RLCr!(self, c) }
-    fn RLCr_d(&mut self) { RLCr!(self, d) }
-    fn RLCr_e(&mut self) { RLCr!(self, e) }
-    fn RLCr_f(&mut self) { RLCr!(self, f) }
-
     /// RLA            17           4 000c rotate akku left through carry
     /// Rotate register a to the left *through* the carry flag, moving the
     /// leftmost digit to the carry and the carry to the rightmost digit
     fn RLA(&mut self) {
         let r = self.regs.a;
         self.regs.a = self.rotate_left_carry(r);
+        self.unset_flag(ZERO | SUBTRACT | HALFCARRY);
 
         self.clock.tick(1);
     }
 
-    /// RL   r         CB 1x        8 z00c rotate left through carry
-    /// Rotate register r (an extra tick, again) to the left *through* the
-    /// carry flag, moving the leftmost digit to the carry and the carry
-    /// to the rightmost digit
-    fn RLr_b(&mut self) { RLr!(self, b) }
-    fn RLr_c(&mut self) { RLr!(self, c) }
-    fn RLr_d(&mut self) { RLr!(self, d) }
-    fn RLr_e(&mut self) { RLr!(self, e) }
-    fn RLr_f(&mut self) { RLr!(self, f) }
-
     /// RRCA           0F           4 000c rotate akku right
     /// Rotate register a to the right, and set the carry flag to whatever
     /// the right digit was
@@ -919,159 +1796,455 @@ LD   HL,SP+dd  F8          12 00hc HL = SP +/- dd ;dd is 8bit signed number
         } else {
             self.unset_flag(CARRY)
         }
+        self.unset_flag(ZERO | SUBTRACT | HALFCARRY);
 
         self.clock.tick(1);
     }
 
-    /// RRC  r         CB 0x        8 z00c rotate right
-    fn RRCr_b(&mut self) { RRCr!(self, b) }
-    fn RRCr_c(&mut self) { RRCr!(self, c) }
-    fn RRCr_d(&mut self) { RRCr!(self, d) }
-    fn RRCr_e(&mut self) { RRCr!(self, e) }
-    fn RRCr_f(&mut self) { RRCr!(self, f) }
-
-
     /// RRA            1F           4 000c rotate akku right through carry
     fn RRA(&mut self) {
         let r = self.regs.a;
         self.regs.a = self.rotate_right_carry(r);
+        self.unset_flag(ZERO | SUBTRACT | HALFCARRY);
 
         self.clock.tick(1);
     }
 
-    /// RR   r         CB 1x        8 z00c rotate right through carry
-    fn RRr_b(&mut self) { RRr!(self, b) }
-    fn RRr_c(&mut self) { RRr!(self, c) }
-    fn RRr_d(&mut self) { RRr!(self, d) }
-    fn RRr_e(&mut self) { RRr!(self, e) }
-    fn RRr_f(&mut self) { RRr!(self, f) }
-
-/*
-# Rotate and Shift Commands
-# ------ --- ----- --------
-RLC  (HL)      CB 06       16 z00c rotate left
-RL   (HL)      CB 16       16 z00c rotate left through carry
-RRC  (HL)      CB 0E       16 z00c rotate right
-RR   (HL)      CB 1E       16 z00c rotate right through carry
-SLA  r         CB 2x        8 z00c shift left arithmetic (b0=0)
-SLA  (HL)      CB 26       16 z00c shift left arithmetic (b0=0)
-SWAP r         CB 3x        8 z000 exchange low/hi-nibble
-SWAP (HL)      CB 36       16 z000 exchange low/hi-nibble
-SRA  r         CB 2x        8 z00c shift right arithmetic (b7=b7)
-SRA  (HL)      CB 2E       16 z00c shift right arithmetic (b7=b7)
-SRL  r         CB 3x        8 z00c shift right logical (b7=0)
-SRL  (HL)      CB 3E       16 z00c shift right logical (b7=0)
-
-# Single Bit Operation Commands
-# ------ --- --------- --------
-BIT  n,r       CB xx        8 z01- test bit n
-BIT  n,(HL)    CB xx       12 z01- test bit n
-SET  n,r       CB xx        8 ---- set bit n
-SET  n,(HL)    CB xx       16 ---- set bit n
-RES  n,r       CB xx        8 ---- reset bit n
-RES  n,(HL)    CB xx       16 ---- reset bit n
-
-
-# CPU Control Commands
-# --- ------- --------
-    CCF            3F           4 -00c cy=cy xor 1
-        SCF            37           4 -001 cy=1
-        NOP            00           4 ---- no operation
-        HALT           76         N*4 ---- halt until interrupt occurs (low power)
-        STOP           10 00        ? ---- low power standby mode (VERY low power)
-        DI             F3           4 ---- disable interrupts, IME=0
-        EI             FB           4 ---- enable interrupts, IME=1
-
-# Jump Commands
-# ---- --------
-        JP   nn        C3 nn nn    16 ---- jump to nn, PC=nn
-        JP   HL        E9           4 ---- jump to HL, PC=HL
-        JP   f,nn      xx nn nn 16;12 ---- conditional jump if nz,z,nc,c
-        JR   PC+dd     18 dd       12 ---- relative jump to nn (PC=PC+/-7bit)
-        JR   f,PC+dd   xx dd     12;8 ---- conditional relative jump if nz,z,nc,c
-        CALL nn        CD nn nn    24 ---- call to nn, SP=SP-2, (SP)=PC, PC=nn
-        CALL f,nn      xx nn nn 24;12 ---- conditional call if nz,z,nc,c
-        RET            C9          16 ---- return, PC=(SP), SP=SP+2
-        RET  f         xx        20;8 ---- conditional return if nz,z,nc,c
-        RETI           D9          16 ---- return and enable interrupts (IME=1)
-        RST  n         xx          16 ---- call to 00,08,10,18,20,28,30,38
-        */
-        fn NOP(&mut self) {
-            self.clock.tick(1);
-        }
-        fn XX(&mut self) {
-            panic!("Called an unsupported opcode!")
-        }
+    // Rotate and Shift Commands
+    // ------ --- ----- --------
+    // These are all on the second page of opcodes, after the 0xCB prefix.
+    // The prefix itself takes no extra time; it's counted in each command.
 
-        fn call(&mut self, opcode: u8) {
-            match opcode {
-                0x00 => self.NOP(),
-                0x01 => self.LDBC_nn(),
+    /// RLC  r         CB 0x        8 z00c rotate left
+    /// Rotate register r to the left - which takes an extra tick - and set
+    /// the carry flag to whatever the leftmost digit
+    fn RLCr_a(&mut self) { RLCr!(self, a) }
+    fn RLCr_b(&mut self) { RLCr!(self, b) }
+    fn RLCr_c(&mut self) { RLCr!(self, c) }
+    fn RLCr_d(&mut self) { RLCr!(self, d) }
+    fn RLCr_e(&mut self) { RLCr!(self, e) }
+    fn RLCr_h(&mut self) { RLCr!(self, h) }
+    fn RLCr_l(&mut self) { RLCr!(self, l) }
+
+    /// RLC  (HL)      CB 06       16 z00c rotate left
+    fn RLCHL(&mut self) {
+        let hl = self.read_hl();
+        let result = self.rlc8(hl);
+        self.write_hl(result);
+        self.clock.tick(4);
+    }
+
+    /// RL   r         CB 1x        8 z00c rotate left through carry
+    /// Rotate register r (an extra tick, again) to the left *through* the
+    /// carry flag, moving the leftmost digit to the carry and the carry
+    /// to the rightmost digit
+    fn RLr_a(&mut self) { RLr!(self, a) }
+    fn RLr_b(&mut self) { RLr!(self, b) }
+    fn RLr_c(&mut self) { RLr!(self, c) }
+    fn RLr_d(&mut self) { RLr!(self, d) }
+    fn RLr_e(&mut self) { RLr!(self, e) }
+    fn RLr_h(&mut self) { RLr!(self, h) }
+    fn RLr_l(&mut self) { RLr!(self, l) }
+
+    /// RL   (HL)      CB 16       16 z00c rotate left through carry
+    fn RLHL(&mut self) {
+        let hl = self.read_hl();
+        let result = self.rl8(hl);
+        self.write_hl(result);
+        self.clock.tick(4);
+    }
+
+    /// RRC  r         CB 0x        8 z00c rotate right
+    fn RRCr_a(&mut self) { RRCr!(self, a) }
+    fn RRCr_b(&mut self) { RRCr!(self, b) }
+    fn RRCr_c(&mut self) { RRCr!(self, c) }
+    fn RRCr_d(&mut self) { RRCr!(self, d) }
+    fn RRCr_e(&mut self) { RRCr!(self, e) }
+    fn RRCr_h(&mut self) { RRCr!(self, h) }
+    fn RRCr_l(&mut self) { RRCr!(self, l) }
+
+    /// RRC  (HL)      CB 0E       16 z00c rotate right
+    fn RRCHL(&mut self) {
+        let hl = self.read_hl();
+        let result = self.rrc8(hl);
+        self.write_hl(result);
+        self.clock.tick(4);
+    }
+
+    /// RR   r         CB 1x        8 z00c rotate right through carry
+    fn RRr_a(&mut self) { RRr!(self, a) }
+    fn RRr_b(&mut self) { RRr!(self, b) }
+    fn RRr_c(&mut self) { RRr!(self, c) }
+    fn RRr_d(&mut self) { RRr!(self, d) }
+    fn RRr_e(&mut self) { RRr!(self, e) }
+    fn RRr_h(&mut self) { RRr!(self, h) }
+    fn RRr_l(&mut self) { RRr!(self, l) }
+
+    /// RR   (HL)      CB 1E       16 z00c rotate right through carry
+    fn RRHL(&mut self) {
+        let hl = self.read_hl();
+        let result = self.rr8(hl);
+        self.write_hl(result);
+        self.clock.tick(4);
+    }
+
+    /// SLA  r         CB 2x        8 z00c shift left arithmetic (b0=0)
+    /// Shift register r to the left, moving the leftmost digit to the carry
+    fn SLAr_a(&mut self) { SLAr!(self, a) }
+    fn SLAr_b(&mut self) { SLAr!(self, b) }
+    fn SLAr_c(&mut self) { SLAr!(self, c) }
+    fn SLAr_d(&mut self) { SLAr!(self, d) }
+    fn SLAr_e(&mut self) { SLAr!(self, e) }
+    fn SLAr_h(&mut self) { SLAr!(self, h) }
+    fn SLAr_l(&mut self) { SLAr!(self, l) }
+
+    /// SLA  (HL)      CB 26       16 z00c shift left arithmetic (b0=0)
+    fn SLAHL(&mut self) {
+        let hl = self.read_hl();
+        let result = self.sla8(hl);
+        self.write_hl(result);
+        self.clock.tick(4);
+    }
+
+    /// SRA  r         CB 2x        8 z00c shift right arithmetic (b7=b7)
+    /// Shift register r to the right, keeping the leftmost digit, and
+    /// moving the rightmost digit to the carry
+    fn SRAr_a(&mut self) { SRAr!(self, a) }
+    fn SRAr_b(&mut self) { SRAr!(self, b) }
+    fn SRAr_c(&mut self) { SRAr!(self, c) }
+    fn SRAr_d(&mut self) { SRAr!(self, d) }
+    fn SRAr_e(&mut self) { SRAr!(self, e) }
+    fn SRAr_h(&mut self) { SRAr!(self, h) }
+    fn SRAr_l(&mut self) { SRAr!(self, l) }
+
+    /// SRA  (HL)      CB 2E       16 z00c shift right arithmetic (b7=b7)
+    fn SRAHL(&mut self) {
+        let hl = self.read_hl();
+        let result = self.sra8(hl);
+        self.write_hl(result);
+        self.clock.tick(4);
+    }
+
+    /// SWAP r         CB 3x        8 z000 exchange low/hi-nibble
+    /// Swap the top four and bottom four bits of register r
+    fn SWAPr_a(&mut self) { SWAPr!(self, a) }
+    fn SWAPr_b(&mut self) { SWAPr!(self, b) }
+    fn SWAPr_c(&mut self) { SWAPr!(self, c) }
+    fn SWAPr_d(&mut self) { SWAPr!(self, d) }
+    fn SWAPr_e(&mut self) { SWAPr!(self, e) }
+    fn SWAPr_h(&mut self) { SWAPr!(self, h) }
+    fn SWAPr_l(&mut self) { SWAPr!(self, l) }
+
+    /// SWAP (HL)      CB 36       16 z000 exchange low/hi-nibble
+    fn SWAPHL(&mut self) {
+        let hl = self.read_hl();
+        let result = self.swap8(hl);
+        self.write_hl(result);
+        self.clock.tick(4);
+    }
+
+    /// SRL  r         CB 3x        8 z00c shift right logical (b7=0)
+    /// Shift register r to the right, moving the rightmost digit to the carry
+    fn SRLr_a(&mut self) { SRLr!(self, a) }
+    fn SRLr_b(&mut self) { SRLr!(self, b) }
+    fn SRLr_c(&mut self) { SRLr!(self, c) }
+    fn SRLr_d(&mut self) { SRLr!(self, d) }
+    fn SRLr_e(&mut self) { SRLr!(self, e) }
+    fn SRLr_h(&mut self) { SRLr!(self, h) }
+    fn SRLr_l(&mut self) { SRLr!(self, l) }
+
+    /// SRL  (HL)      CB 3E       16 z00c shift right logical (b7=0)
+    fn SRLHL(&mut self) {
+        let hl = self.read_hl();
+        let result = self.srl8(hl);
+        self.write_hl(result);
+        self.clock.tick(4);
+    }
+
+    // Single Bit Operation Commands
+    // ------ --- --------- --------
+    // Each of these takes the bit number n (0-7) to operate on.
+
+    /// BIT  n,r       CB xx        8 z01- test bit n
+    /// Set the zero flag if bit n of register r is clear
+    fn BITr_a(&mut self, n: u8) { BITnr!(self, n, a) }
+    fn BITr_b(&mut self, n: u8) { BITnr!(self, n, b) }
+    fn BITr_c(&mut self, n: u8) { BITnr!(self, n, c) }
+    fn BITr_d(&mut self, n: u8) { BITnr!(self, n, d) }
+    fn BITr_e(&mut self, n: u8) { BITnr!(self, n, e) }
+    fn BITr_h(&mut self, n: u8) { BITnr!(self, n, h) }
+    fn BITr_l(&mut self, n: u8) { BITnr!(self, n, l) }
+
+    /// BIT  n,(HL)    CB xx       12 z01- test bit n
+    fn BITHL(&mut self, n: u8) {
+        let hl = self.read_hl();
+        self.test_bit(n, hl);
+        self.clock.tick(3);
+    }
+
+    /// SET  n,r       CB xx        8 ---- set bit n
+    /// Set bit n of register r to 1
+    fn SETr_a(&mut self, n: u8) { SETnr!(self, n, a) }
+    fn SETr_b(&mut self, n: u8) { SETnr!(self, n, b) }
+    fn SETr_c(&mut self, n: u8) { SETnr!(self, n, c) }
+    fn SETr_d(&mut self, n: u8) { SETnr!(self, n, d) }
+    fn SETr_e(&mut self, n: u8) { SETnr!(self, n, e) }
+    fn SETr_h(&mut self, n: u8) { SETnr!(self, n, h) }
+    fn SETr_l(&mut self, n: u8) { SETnr!(self, n, l) }
+
+    /// SET  n,(HL)    CB xx       16 ---- set bit n
+    fn SETHL(&mut self, n: u8) {
+        let hl = self.read_hl();
+        self.write_hl(hl | 1 << n);
+        self.clock.tick(4);
+    }
+
+    /// RES  n,r       CB xx        8 ---- reset bit n
+    /// Set bit n of register r to 0
+    fn RESr_a(&mut self, n: u8) { RESnr!(self, n, a) }
+    fn RESr_b(&mut self, n: u8) { RESnr!(self, n, b) }
+    fn RESr_c(&mut self, n: u8) { RESnr!(self, n, c) }
+    fn RESr_d(&mut self, n: u8) { RESnr!(self, n, d) }
+    fn RESr_e(&mut self, n: u8) { RESnr!(self, n, e) }
+    fn RESr_h(&mut self, n: u8) { RESnr!(self, n, h) }
+    fn RESr_l(&mut self, n: u8) { RESnr!(self, n, l) }
+
+    /// RES  n,(HL)    CB xx       16 ---- reset bit n
+    fn RESHL(&mut self, n: u8) {
+        let hl = self.read_hl();
+        self.write_hl(hl & !(1 << n));
+        self.clock.tick(4);
+    }
+
+    // CPU Control Commands
+    // --- ------- --------
+
+    /// CCF            3F           4 -00c cy=cy xor 1
+    /// Complement the carry flag
+    fn CCF(&mut self) {
+        if self.flag_is_set(CARRY) {
+            self.unset_flag(CARRY);
+        } else {
+            self.set_flag(CARRY);
+        }
+        self.unset_flag(SUBTRACT | HALFCARRY);
+        self.clock.tick(1)
+    }
+
+    /// SCF            37           4 -001 cy=1
+    /// Set the carry flag
+    fn SCF(&mut self) {
+        self.set_flag(CARRY);
+        self.unset_flag(SUBTRACT | HALFCARRY);
+        self.clock.tick(1)
+    }
+
+    /// NOP            00           4 ---- no operation
+    fn NOP(&mut self) {
+        self.clock.tick(1);
+    }
+
+    /// HALT           76         N*4 ---- halt until interrupt occurs (low power)
+    /// If interrupts are disabled but one is already pending, the CPU
+    /// doesn't halt at all. Instead, it fails to move the program counter
+    /// past the next opcode, so that byte gets run twice (the `HALT bug`).
+    fn HALT(&mut self) {
+        if !self.ime && self.mmu.pending_interrupts() != 0 {
+            self.halt_bug = true;
+        } else {
+            self.halted = true;
+        }
+        self.clock.tick(1);
+    }
+
+    /// STOP           10 00        ? ---- low power standby mode (VERY low power)
+    /// STOP is two bytes long; the second byte is always ignored.
+    /// The CPU and screen stop until a button is pressed; see `step`.
+    /// On the CGB, with a speed switch armed in KEY1, it switches speeds
+    /// instead.
+    fn STOP(&mut self) {
+        self.read_immediate_byte();
+        if !self.mmu.switch_speed() {
+            self.stopped = true;
+        }
+        self.clock.tick(1);
+    }
+
+    /// DI             F3           4 ---- disable interrupts, IME=0
+    fn DI(&mut self) {
+        self.ime = false;
+        self.ei_pending = false;
+        self.clock.tick(1);
+    }
+
+    /// EI             FB           4 ---- enable interrupts, IME=1
+    /// Interrupts are enabled after the *next* instruction; see `step`.
+    fn EI(&mut self) {
+        self.ei_pending = true;
+        self.clock.tick(1);
+    }
+
+    // Jump Commands
+    // ---- --------
+
+    /// JP   nn        C3 nn nn    16 ---- jump to nn, PC=nn
+    fn JPnn(&mut self) {
+        let nn = self.read_immediate_word();
+        self.regs.pc = nn;
+        self.clock.tick(4);
+    }
+
+    /// JP   HL        E9           4 ---- jump to HL, PC=HL
+    fn JPHL(&mut self) {
+        self.regs.pc = self.regs.hl();
+        self.clock.tick(1);
+    }
+
+    /// JP   f,nn      xx nn nn 16;12 ---- conditional jump if nz,z,nc,c
+    fn JPNZnn(&mut self) { JPfnn!(self, ZERO, false) }
+    fn JPZnn(&mut self)  { JPfnn!(self, ZERO, true) }
+    fn JPNCnn(&mut self) { JPfnn!(self, CARRY, false) }
+    fn JPCnn(&mut self)  { JPfnn!(self, CARRY, true) }
+
+    /// JR   PC+dd     18 dd       12 ---- relative jump to nn (PC=PC+/-7bit)
+    fn JRn(&mut self) {
+        let dd = self.read_immediate_byte();
+        self.relative_jump(dd);
+        self.clock.tick(3);
+    }
+
+    /// JR   f,PC+dd   xx dd     12;8 ---- conditional relative jump if nz,z,nc,c
+    fn JRNZn(&mut self) { JRfn!(self, ZERO, false) }
+    fn JRZn(&mut self)  { JRfn!(self, ZERO, true) }
+    fn JRNCn(&mut self) { JRfn!(self, CARRY, false) }
+    fn JRCn(&mut self)  { JRfn!(self, CARRY, true) }
+
+    /// CALL nn        CD nn nn    24 ---- call to nn, SP=SP-2, (SP)=PC, PC=nn
+    fn CALLnn(&mut self) {
+        let nn = self.read_immediate_word();
+        let pc = self.regs.pc;
+        self.stack_push(pc);
+        self.regs.pc = nn;
+        self.clock.tick(6);
+    }
+
+    /// CALL f,nn      xx nn nn 24;12 ---- conditional call if nz,z,nc,c
+    fn CALLNZnn(&mut self) { CALLfnn!(self, ZERO, false) }
+    fn CALLZnn(&mut self)  { CALLfnn!(self, ZERO, true) }
+    fn CALLNCnn(&mut self) { CALLfnn!(self, CARRY, false) }
+    fn CALLCnn(&mut self)  { CALLfnn!(self, CARRY, true) }
+
+    /// RET            C9          16 ---- return, PC=(SP), SP=SP+2
+    fn RET(&mut self) {
+        self.regs.pc = self.stack_pop();
+        self.clock.tick(4);
+    }
+
+    /// RET  f         xx        20;8 ---- conditional return if nz,z,nc,c
+    fn RETNZ(&mut self) { RETf!(self, ZERO, false) }
+    fn RETZ(&mut self)  { RETf!(self, ZERO, true) }
+    fn RETNC(&mut self) { RETf!(self, CARRY, false) }
+    fn RETC(&mut self)  { RETf!(self, CARRY, true) }
+
+    /// RETI           D9          16 ---- return and enable interrupts (IME=1)
+    fn RETI(&mut self) {
+        self.regs.pc = self.stack_pop();
+        self.ime = true;
+        self.clock.tick(4);
+    }
+
+    /// RST  n         xx          16 ---- call to 00,08,10,18,20,28,30,38
+    fn RST00(&mut self) { RSTn!(self, 0x00) }
+    fn RST08(&mut self) { RSTn!(self, 0x08) }
+    fn RST10(&mut self) { RSTn!(self, 0x10) }
+    fn RST18(&mut self) { RSTn!(self, 0x18) }
+    fn RST20(&mut self) { RSTn!(self, 0x20) }
+    fn RST28(&mut self) { RSTn!(self, 0x28) }
+    fn RST30(&mut self) { RSTn!(self, 0x30) }
+    fn RST38(&mut self) { RSTn!(self, 0x38) }
+
+        /// The opcodes with no instruction hang the CPU until it's switched
+        /// off. See `step`.
+        fn XX(&mut self) {
+            self.locked = true;
+            self.clock.tick(1);
+        }
+
+        /// Run the command after the 0xCB prefix
+        fn MAPcb(&mut self) {
+            let opcode = self.read_immediate_byte();
+            self.call_cb(opcode);
+        }
+
+        fn call(&mut self, opcode: u8) {
+            match opcode {
+                0x00 => self.NOP(),
+                0x01 => self.LDBC_nn(),
                 0x02 => self.LDBCmA(),
                 0x03 => self.INCBC(),
-                // 0x04 => self.INCr_b(),
-                // 0x05 => self.DECr_b(),
-                // 0x06 => self.LDrn_b(),
-                // 0x07 => self.RLCA(),
-                // 0x08 => self.LDnmSP(),
+                0x04 => self.INCr_b(),
+                0x05 => self.DECr_b(),
+                0x06 => self.LDrn_b(),
+                0x07 => self.RLCA(),
+                0x08 => self.LDnmSP(),
                 0x09 => self.ADDHLBC(),
                 0x0A => self.LDABCm(),
-                // 0x0B => self.DECBC(),
-                // 0x0C => self.INCr_c(),
-                // 0x0D => self.DECr_c(),
-                // 0x0E => self.LDrn_c(),
-                // 0x0F => self.RRCA(),
-
-                // 0x10 => self.DJNZn(),
-                // 0x11 => self.LDDEnn(),
-                // 0x12 => self.LDDEmA(),
-                // 0x13 => self.INCDE(),
-                // 0x14 => self.INCr_d(),
-                // 0x15 => self.DECr_d(),
-                // 0x16 => self.LDrn_d(),
-                // 0x17 => self.RLA(),
-                // 0x18 => self.JRn(),
+                0x0B => self.DECBC(),
+                0x0C => self.INCr_c(),
+                0x0D => self.DECr_c(),
+                0x0E => self.LDrn_c(),
+                0x0F => self.RRCA(),
+
+                0x10 => self.STOP(),
+                0x11 => self.LDDE_nn(),
+                0x12 => self.LDDEmA(),
+                0x13 => self.INCDE(),
+                0x14 => self.INCr_d(),
+                0x15 => self.DECr_d(),
+                0x16 => self.LDrn_d(),
+                0x17 => self.RLA(),
+                0x18 => self.JRn(),
                 0x19 => self.ADDHLDE(),
                 0x1A => self.LDADEm(),
-                // 0x1B => self.DECDE(),
-                // 0x1C => self.INCr_e(),
-                // 0x1D => self.DECr_e(),
-                // 0x1E => self.LDrn_e(),
-                // 0x1F => self.RRA(),
-
-                // 0x20 => self.JRNZn(),
-                // 0x21 => self.LDHLnn(),
+                0x1B => self.DECDE(),
+                0x1C => self.INCr_e(),
+                0x1D => self.DECr_e(),
+                0x1E => self.LDrn_e(),
+                0x1F => self.RRA(),
+
+                0x20 => self.JRNZn(),
+                0x21 => self.LDHL_nn(),
                 0x22 => self.LDIHLmA(),
-                // 0x23 => self.INCHL(),
-                // 0x24 => self.INCr_h(),
-                // 0x25 => self.DECr_h(),
-                // 0x26 => self.LDrn_h(),
-                // 0x27 => self.DAA(),
-                // 0x28 => self.JRZn(),
+                0x23 => self.INCHL(),
+                0x24 => self.INCr_h(),
+                0x25 => self.DECr_h(),
+                0x26 => self.LDrn_h(),
+                0x27 => self.DAA(),
+                0x28 => self.JRZn(),
                 0x29 => self.ADDHLHL(),
-                // 0x2A => self.LDIAHLm(),
-                // 0x2B => self.DECHL(),
-                // 0x2C => self.INCr_l(),
-                // 0x2D => self.DECr_l(),
-                // 0x2E => self.LDrn_l(),
-                // 0x2F => self.CPL(),
-
-                // 0x30 => self.JRNCn(),
-                // 0x31 => self.LDSPnn(),
-                // 0x32 => self.LDHLDA(),
+                0x2A => self.LDIAHLm(),
+                0x2B => self.DECHL(),
+                0x2C => self.INCr_l(),
+                0x2D => self.DECr_l(),
+                0x2E => self.LDrn_l(),
+                0x2F => self.CPL(),
+
+                0x30 => self.JRNCn(),
+                0x31 => self.LDSP_nn(),
+                0x32 => self.LDDHLmA(),
                 0x33 => self.INCSP(),
-                // 0x34 => self.INCHLm(),
-                // 0x35 => self.DECHLm(),
-                // 0x36 => self.LDHLmn(),
-                // 0x37 => self.SCF(),
-                // 0x38 => self.JRCn(),
+                0x34 => self.INCHLm(),
+                0x35 => self.DECHLm(),
+                0x36 => self.LDHLmn(),
+                0x37 => self.SCF(),
+                0x38 => self.JRCn(),
                 0x39 => self.ADDHLSP(),
-                // 0x3A => self.LDAHLD(),
-                // 0x3B => self.DECSP(),
-                // 0x3C => self.INCr_a(),
-                // 0x3D => self.DECr_a(),
-                // 0x3E => self.LDrn_a(),
-                // 0x3F => self.CCF(),
+                0x3A => self.LDDAHLm(),
+                0x3B => self.DECSP(),
+                0x3C => self.INCr_a(),
+                0x3D => self.DECr_a(),
+                0x3E => self.LDrn_a(),
+                0x3F => self.CCF(),
 
                 0x40 => self.LDrr_bb(),
                 0x41 => self.LDrr_bc(),
@@ -1124,14 +2297,14 @@ RES  n,(HL)    CB xx       16 ---- reset bit n
                 0x6E => self.LDrHLm_l(),
                 0x6F => self.LDrr_la(),
 
-                // 0x70 => self.LDHLmr_b(),
-                // 0x71 => self.LDHLmr_c(),
-                // 0x72 => self.LDHLmr_d(),
-                // 0x73 => self.LDHLmr_e(),
-                // 0x74 => self.LDHLmr_h(),
-                // 0x75 => self.LDHLmr_l(),
-                // 0x76 => self.HALT(),
-                // 0x77 => self.LDHLmr_a(),
+                0x70 => self.LDHLmr_b(),
+                0x71 => self.LDHLmr_c(),
+                0x72 => self.LDHLmr_d(),
+                0x73 => self.LDHLmr_e(),
+                0x74 => self.LDHLmr_h(),
+                0x75 => self.LDHLmr_l(),
+                0x76 => self.HALT(),
+                0x77 => self.LDHLmr_a(),
                 0x78 => self.LDrr_ab(),
                 0x79 => self.LDrr_ac(),
                 0x7A => self.LDrr_ad(),
@@ -1158,125 +2331,400 @@ RES  n,(HL)    CB xx       16 ---- reset bit n
                 0x8E => self.ADCHL(),
                 0x8F => self.ADCr_a(),
 
-                // 0x90 => self.SUBr_b(),
-                // 0x91 => self.SUBr_c(),
-                // 0x92 => self.SUBr_d(),
-                // 0x93 => self.SUBr_e(),
-                // 0x94 => self.SUBr_h(),
-                // 0x95 => self.SUBr_l(),
-                // 0x96 => self.SUBHL(),
-                // 0x97 => self.SUBr_a(),
-                // 0x98 => self.SBCr_b(),
-                // 0x99 => self.SBCr_c(),
-                // 0x9A => self.SBCr_d(),
-                // 0x9B => self.SBCr_e(),
-                // 0x9C => self.SBCr_h(),
-                // 0x9D => self.SBCr_l(),
-                // 0x9E => self.SBCHL(),
-                // 0x9F => self.SBCr_a(),
-
-                // 0xA0 => self.ANDr_b(),
-                // 0xA1 => self.ANDr_c(),
-                // 0xA2 => self.ANDr_d(),
-                // 0xA3 => self.ANDr_e(),
-                // 0xA4 => self.ANDr_h(),
-                // 0xA5 => self.ANDr_l(),
-                // 0xA6 => self.ANDHL(),
-                // 0xA7 => self.ANDr_a(),
-                // 0xA8 => self.XORr_b(),
-                // 0xA9 => self.XORr_c(),
-                // 0xAA => self.XORr_d(),
-                // 0xAB => self.XORr_e(),
-                // 0xAC => self.XORr_h(),
-                // 0xAD => self.XORr_l(),
-                // 0xAE => self.XORHL(),
-                // 0xAF => self.XORr_a(),
-
-                // 0xB0 => self.ORr_b(),
-                // 0xB1 => self.ORr_c(),
-                // 0xB2 => self.ORr_d(),
-                // 0xB3 => self.ORr_e(),
-                // 0xB4 => self.ORr_h(),
-                // 0xB5 => self.ORr_l(),
-                // 0xB6 => self.ORHL(),
-                // 0xB7 => self.ORr_a(),
-                // 0xB8 => self.CPr_b(),
-                // 0xB9 => self.CPr_c(),
-                // 0xBA => self.CPr_d(),
-                // 0xBB => self.CPr_e(),
-                // 0xBC => self.CPr_h(),
-                // 0xBD => self.CPr_l(),
-                // 0xBE => self.CPHL(),
-                // 0xBF => self.CPr_a(),
-
-                // 0xC0 => self.RETNZ(),
+                0x90 => self.SUBr_b(),
+                0x91 => self.SUBr_c(),
+                0x92 => self.SUBr_d(),
+                0x93 => self.SUBr_e(),
+                0x94 => self.SUBr_h(),
+                0x95 => self.SUBr_l(),
+                0x96 => self.SUBHL(),
+                0x97 => self.SUBr_a(),
+                0x98 => self.SBCr_b(),
+                0x99 => self.SBCr_c(),
+                0x9A => self.SBCr_d(),
+                0x9B => self.SBCr_e(),
+                0x9C => self.SBCr_h(),
+                0x9D => self.SBCr_l(),
+                0x9E => self.SBCHL(),
+                0x9F => self.SBCr_a(),
+
+                0xA0 => self.ANDr_b(),
+                0xA1 => self.ANDr_c(),
+                0xA2 => self.ANDr_d(),
+                0xA3 => self.ANDr_e(),
+                0xA4 => self.ANDr_h(),
+                0xA5 => self.ANDr_l(),
+                0xA6 => self.ANDHL(),
+                0xA7 => self.ANDr_a(),
+                0xA8 => self.XORr_b(),
+                0xA9 => self.XORr_c(),
+                0xAA => self.XORr_d(),
+                0xAB => self.XORr_e(),
+                0xAC => self.XORr_h(),
+                0xAD => self.XORr_l(),
+                0xAE => self.XORHL(),
+                0xAF => self.XORr_a(),
+
+                0xB0 => self.ORr_b(),
+                0xB1 => self.ORr_c(),
+                0xB2 => self.ORr_d(),
+                0xB3 => self.ORr_e(),
+                0xB4 => self.ORr_h(),
+                0xB5 => self.ORr_l(),
+                0xB6 => self.ORHL(),
+                0xB7 => self.ORr_a(),
+                0xB8 => self.CPr_b(),
+                0xB9 => self.CPr_c(),
+                0xBA => self.CPr_d(),
+                0xBB => self.CPr_e(),
+                0xBC => self.CPr_h(),
+                0xBD => self.CPr_l(),
+                0xBE => self.CPHL(),
+                0xBF => self.CPr_a(),
+
+                0xC0 => self.RETNZ(),
                 0xC1 => self.POPBC(),
-                // 0xC2 => self.JPNZnn(),
-                // 0xC3 => self.JPnn(),
-                // 0xC4 => self.CALLNZnn(),
+                0xC2 => self.JPNZnn(),
+                0xC3 => self.JPnn(),
+                0xC4 => self.CALLNZnn(),
                 0xC5 => self.PUSHBC(),
-                // 0xC6 => self.ADDn(),
-                // 0xC7 => self.RST00(),
-                // 0xC8 => self.RETZ(),
-                // 0xC9 => self.RET(),
-                // 0xCA => self.JPZnn(),
-                // 0xCB => self.MAPcb(),
-                // 0xCC => self.CALLZnn(),
-                // 0xCD => self.CALLnn(),
-                // 0xCE => self.ADCn(),
-                // 0xCF => self.RST08(),
-
-                // 0xD0 => self.RETNC(),
+                0xC6 => self.ADDn(),
+                0xC7 => self.RST00(),
+                0xC8 => self.RETZ(),
+                0xC9 => self.RET(),
+                0xCA => self.JPZnn(),
+                0xCB => self.MAPcb(),
+                0xCC => self.CALLZnn(),
+                0xCD => self.CALLnn(),
+                0xCE => self.ADCn(),
+                0xCF => self.RST08(),
+
+                0xD0 => self.RETNC(),
                 0xD1 => self.POPDE(),
-                // 0xD2 => self.JPNCnn(),
+                0xD2 => self.JPNCnn(),
                 0xD3 => self.XX(),
-                // 0xD4 => self.CALLNCnn(),
+                0xD4 => self.CALLNCnn(),
                 0xD5 => self.PUSHDE(),
-                // 0xD6 => self.SUBn(),
-                // 0xD7 => self.RST10(),
-                // 0xD8 => self.RETC(),
-                // 0xD9 => self.RETI(),
-                // 0xDA => self.JPCnn(),
-                // 0xDB => self.XX(),
-                // 0xDC => self.CALLCnn(),
+                0xD6 => self.SUBn(),
+                0xD7 => self.RST10(),
+                0xD8 => self.RETC(),
+                0xD9 => self.RETI(),
+                0xDA => self.JPCnn(),
+                0xDB => self.XX(),
+                0xDC => self.CALLCnn(),
                 0xDD => self.XX(),
-                // 0xDE => self.SBCn(),
-                // 0xDF => self.RST18(),
+                0xDE => self.SBCn(),
+                0xDF => self.RST18(),
 
-                // 0xE0 => self.LDIOnA(),
+                0xE0 => self.LDIOnA(),
                 0xE1 => self.POPHL(),
-                // 0xE2 => self.LDIOCA(),
+                0xE2 => self.LDIOCA(),
                 0xE3 => self.XX(),
                 0xE4 => self.XX(),
                 0xE5 => self.PUSHHL(),
-                // 0xE6 => self.ANDn(),
-                // 0xE7 => self.RST20(),
-                // 0xE8 => self.ADDSPn(),
-                // 0xE9 => self.JPHL(),
-                // 0xEA => self.LDnmA(),
+                0xE6 => self.ANDn(),
+                0xE7 => self.RST20(),
+                0xE8 => self.ADDSPn(),
+                0xE9 => self.JPHL(),
+                0xEA => self.LDnmA(),
                 0xEB => self.XX(),
                 0xEC => self.XX(),
                 0xED => self.XX(),
-                // 0xEE => self.ORn(),
-                // 0xEF => self.RST28(),
+                0xEE => self.XORn(),
+                0xEF => self.RST28(),
 
-                // 0xF0 => self.LDAIOn(),
+                0xF0 => self.LDAIOn(),
                 0xF1 => self.POPAF(),
-                // 0xF2 => self.LDAIOC(),
-                // 0xF3 => self.DI(),
+                0xF2 => self.LDAIOC(),
+                0xF3 => self.DI(),
                 0xF4 => self.XX(),
                 0xF5 => self.PUSHAF(),
-                // 0xF6 => self.XORn(),
-                // 0xF7 => self.RST30(),
-                // 0xF8 => self.LDHLSPn(),
+                0xF6 => self.ORn(),
+                0xF7 => self.RST30(),
+                0xF8 => self.LDHLSPn(),
                 0xF9 => self.LDSPHL(),
-                // 0xFA => self.LDAnm(),
-                // 0xFB => self.EI(),
+                0xFA => self.LDAnn(),
+                0xFB => self.EI(),
                 0xFC => self.XX(),
                 0xFD => self.XX(),
-                // 0xFE => self.CPn(),
-                // 0xFF => self.RST38(),
-                _    => self.XX()
+                0xFE => self.CPn(),
+                0xFF => self.RST38(),
+            }
+        }
+
+        fn call_cb(&mut self, opcode: u8) {
+            match opcode {
+                0x00 => self.RLCr_b(),
+                0x01 => self.RLCr_c(),
+                0x02 => self.RLCr_d(),
+                0x03 => self.RLCr_e(),
+                0x04 => self.RLCr_h(),
+                0x05 => self.RLCr_l(),
+                0x06 => self.RLCHL(),
+                0x07 => self.RLCr_a(),
+                0x08 => self.RRCr_b(),
+                0x09 => self.RRCr_c(),
+                0x0A => self.RRCr_d(),
+                0x0B => self.RRCr_e(),
+                0x0C => self.RRCr_h(),
+                0x0D => self.RRCr_l(),
+                0x0E => self.RRCHL(),
+                0x0F => self.RRCr_a(),
+
+                0x10 => self.RLr_b(),
+                0x11 => self.RLr_c(),
+                0x12 => self.RLr_d(),
+                0x13 => self.RLr_e(),
+                0x14 => self.RLr_h(),
+                0x15 => self.RLr_l(),
+                0x16 => self.RLHL(),
+                0x17 => self.RLr_a(),
+                0x18 => self.RRr_b(),
+                0x19 => self.RRr_c(),
+                0x1A => self.RRr_d(),
+                0x1B => self.RRr_e(),
+                0x1C => self.RRr_h(),
+                0x1D => self.RRr_l(),
+                0x1E => self.RRHL(),
+                0x1F => self.RRr_a(),
+
+                0x20 => self.SLAr_b(),
+                0x21 => self.SLAr_c(),
+                0x22 => self.SLAr_d(),
+                0x23 => self.SLAr_e(),
+                0x24 => self.SLAr_h(),
+                0x25 => self.SLAr_l(),
+                0x26 => self.SLAHL(),
+                0x27 => self.SLAr_a(),
+                0x28 => self.SRAr_b(),
+                0x29 => self.SRAr_c(),
+                0x2A => self.SRAr_d(),
+                0x2B => self.SRAr_e(),
+                0x2C => self.SRAr_h(),
+                0x2D => self.SRAr_l(),
+                0x2E => self.SRAHL(),
+                0x2F => self.SRAr_a(),
+
+                0x30 => self.SWAPr_b(),
+                0x31 => self.SWAPr_c(),
+                0x32 => self.SWAPr_d(),
+                0x33 => self.SWAPr_e(),
+                0x34 => self.SWAPr_h(),
+                0x35 => self.SWAPr_l(),
+                0x36 => self.SWAPHL(),
+                0x37 => self.SWAPr_a(),
+                0x38 => self.SRLr_b(),
+                0x39 => self.SRLr_c(),
+                0x3A => self.SRLr_d(),
+                0x3B => self.SRLr_e(),
+                0x3C => self.SRLr_h(),
+                0x3D => self.SRLr_l(),
+                0x3E => self.SRLHL(),
+                0x3F => self.SRLr_a(),
+
+                0x40 => self.BITr_b(0),
+                0x41 => self.BITr_c(0),
+                0x42 => self.BITr_d(0),
+                0x43 => self.BITr_e(0),
+                0x44 => self.BITr_h(0),
+                0x45 => self.BITr_l(0),
+                0x46 => self.BITHL(0),
+                0x47 => self.BITr_a(0),
+                0x48 => self.BITr_b(1),
+                0x49 => self.BITr_c(1),
+                0x4A => self.BITr_d(1),
+                0x4B => self.BITr_e(1),
+                0x4C => self.BITr_h(1),
+                0x4D => self.BITr_l(1),
+                0x4E => self.BITHL(1),
+                0x4F => self.BITr_a(1),
+
+                0x50 => self.BITr_b(2),
+                0x51 => self.BITr_c(2),
+                0x52 => self.BITr_d(2),
+                0x53 => self.BITr_e(2),
+                0x54 => self.BITr_h(2),
+                0x55 => self.BITr_l(2),
+                0x56 => self.BITHL(2),
+                0x57 => self.BITr_a(2),
+                0x58 => self.BITr_b(3),
+                0x59 => self.BITr_c(3),
+                0x5A => self.BITr_d(3),
+                0x5B => self.BITr_e(3),
+                0x5C => self.BITr_h(3),
+                0x5D => self.BITr_l(3),
+                0x5E => self.BITHL(3),
+                0x5F => self.BITr_a(3),
+
+                0x60 => self.BITr_b(4),
+                0x61 => self.BITr_c(4),
+                0x62 => self.BITr_d(4),
+                0x63 => self.BITr_e(4),
+                0x64 => self.BITr_h(4),
+                0x65 => self.BITr_l(4),
+                0x66 => self.BITHL(4),
+                0x67 => self.BITr_a(4),
+                0x68 => self.BITr_b(5),
+                0x69 => self.BITr_c(5),
+                0x6A => self.BITr_d(5),
+                0x6B => self.BITr_e(5),
+                0x6C => self.BITr_h(5),
+                0x6D => self.BITr_l(5),
+                0x6E => self.BITHL(5),
+                0x6F => self.BITr_a(5),
+
+                0x70 => self.BITr_b(6),
+                0x71 => self.BITr_c(6),
+                0x72 => self.BITr_d(6),
+                0x73 => self.BITr_e(6),
+                0x74 => self.BITr_h(6),
+                0x75 => self.BITr_l(6),
+                0x76 => self.BITHL(6),
+                0x77 => self.BITr_a(6),
+                0x78 => self.BITr_b(7),
+                0x79 => self.BITr_c(7),
+                0x7A => self.BITr_d(7),
+                0x7B => self.BITr_e(7),
+                0x7C => self.BITr_h(7),
+                0x7D => self.BITr_l(7),
+                0x7E => self.BITHL(7),
+                0x7F => self.BITr_a(7),
+
+                0x80 => self.RESr_b(0),
+                0x81 => self.RESr_c(0),
+                0x82 => self.RESr_d(0),
+                0x83 => self.RESr_e(0),
+                0x84 => self.RESr_h(0),
+                0x85 => self.RESr_l(0),
+                0x86 => self.RESHL(0),
+                0x87 => self.RESr_a(0),
+                0x88 => self.RESr_b(1),
+                0x89 => self.RESr_c(1),
+                0x8A => self.RESr_d(1),
+                0x8B => self.RESr_e(1),
+                0x8C => self.RESr_h(1),
+                0x8D => self.RESr_l(1),
+                0x8E => self.RESHL(1),
+                0x8F => self.RESr_a(1),
+
+                0x90 => self.RESr_b(2),
+                0x91 => self.RESr_c(2),
+                0x92 => self.RESr_d(2),
+                0x93 => self.RESr_e(2),
+                0x94 => self.RESr_h(2),
+                0x95 => self.RESr_l(2),
+                0x96 => self.RESHL(2),
+                0x97 => self.RESr_a(2),
+                0x98 => self.RESr_b(3),
+                0x99 => self.RESr_c(3),
+                0x9A => self.RESr_d(3),
+                0x9B => self.RESr_e(3),
+                0x9C => self.RESr_h(3),
+                0x9D => self.RESr_l(3),
+                0x9E => self.RESHL(3),
+                0x9F => self.RESr_a(3),
+
+                0xA0 => self.RESr_b(4),
+                0xA1 => self.RESr_c(4),
+                0xA2 => self.RESr_d(4),
+                0xA3 => self.RESr_e(4),
+                0xA4 => self.RESr_h(4),
+                0xA5 => self.RESr_l(4),
+                0xA6 => self.RESHL(4),
+                0xA7 => self.RESr_a(4),
+                0xA8 => self.RESr_b(5),
+                0xA9 => self.RESr_c(5),
+                0xAA => self.RESr_d(5),
+                0xAB => self.RESr_e(5),
+                0xAC => self.RESr_h(5),
+                0xAD => self.RESr_l(5),
+                0xAE => self.RESHL(5),
+                0xAF => self.RESr_a(5),
+
+                0xB0 => self.RESr_b(6),
+                0xB1 => self.RESr_c(6),
+                0xB2 => self.RESr_d(6),
+                0xB3 => self.RESr_e(6),
+                0xB4 => self.RESr_h(6),
+                0xB5 => self.RESr_l(6),
+                0xB6 => self.RESHL(6),
+                0xB7 => self.RESr_a(6),
+                0xB8 => self.RESr_b(7),
+                0xB9 => self.RESr_c(7),
+                0xBA => self.RESr_d(7),
+                0xBB => self.RESr_e(7),
+                0xBC => self.RESr_h(7),
+                0xBD => self.RESr_l(7),
+                0xBE => self.RESHL(7),
+                0xBF => self.RESr_a(7),
+
+                0xC0 => self.SETr_b(0),
+                0xC1 => self.SETr_c(0),
+                0xC2 => self.SETr_d(0),
+                0xC3 => self.SETr_e(0),
+                0xC4 => self.SETr_h(0),
+                0xC5 => self.SETr_l(0),
+                0xC6 => self.SETHL(0),
+                0xC7 => self.SETr_a(0),
+                0xC8 => self.SETr_b(1),
+                0xC9 => self.SETr_c(1),
+                0xCA => self.SETr_d(1),
+                0xCB => self.SETr_e(1),
+                0xCC => self.SETr_h(1),
+                0xCD => self.SETr_l(1),
+                0xCE => self.SETHL(1),
+                0xCF => self.SETr_a(1),
+
+                0xD0 => self.SETr_b(2),
+                0xD1 => self.SETr_c(2),
+                0xD2 => self.SETr_d(2),
+                0xD3 => self.SETr_e(2),
+                0xD4 => self.SETr_h(2),
+                0xD5 => self.SETr_l(2),
+                0xD6 => self.SETHL(2),
+                0xD7 => self.SETr_a(2),
+                0xD8 => self.SETr_b(3),
+                0xD9 => self.SETr_c(3),
+                0xDA => self.SETr_d(3),
+                0xDB => self.SETr_e(3),
+                0xDC => self.SETr_h(3),
+                0xDD => self.SETr_l(3),
+                0xDE => self.SETHL(3),
+                0xDF => self.SETr_a(3),
+
+                0xE0 => self.SETr_b(4),
+                0xE1 => self.SETr_c(4),
+                0xE2 => self.SETr_d(4),
+                0xE3 => self.SETr_e(4),
+                0xE4 => self.SETr_h(4),
+                0xE5 => self.SETr_l(4),
+                0xE6 => self.SETHL(4),
+                0xE7 => self.SETr_a(4),
+                0xE8 => self.SETr_b(5),
+                0xE9 => self.SETr_c(5),
+                0xEA => self.SETr_d(5),
+                0xEB => self.SETr_e(5),
+                0xEC => self.SETr_h(5),
+                0xED => self.SETr_l(5),
+                0xEE => self.SETHL(5),
+                0xEF => self.SETr_a(5),
+
+                0xF0 => self.SETr_b(6),
+                0xF1 => self.SETr_c(6),
+                0xF2 => self.SETr_d(6),
+                0xF3 => self.SETr_e(6),
+                0xF4 => self.SETr_h(6),
+                0xF5 => self.SETr_l(6),
+                0xF6 => self.SETHL(6),
+                0xF7 => self.SETr_a(6),
+                0xF8 => self.SETr_b(7),
+                0xF9 => self.SETr_c(7),
+                0xFA => self.SETr_d(7),
+                0xFB => self.SETr_e(7),
+                0xFC => self.SETr_h(7),
+                0xFD => self.SETr_l(7),
+                0xFE => self.SETHL(7),
+                0xFF => self.SETr_a(7),
             }
         }
 }
@@ -1294,14 +2742,68 @@ fn test_register_getting_pairs() {
 
 #[test]
 fn test_registers_initialize_sp_with_default() {
-    let mut cpu = Z80::new();
+    let cpu = Z80::new();
     assert_eq!(cpu.regs.sp, 0xFFFE);
 }
 
 #[test]
 fn test_registers_initialize_pc_at_bios() {
+    let cpu = Z80::new();
+    assert_eq!(cpu.regs.pc, 0x0100);
+}
+
+#[test]
+fn test_skipping_the_boot_rom_sets_up_registers() {
     let mut cpu = Z80::new();
+    cpu.skip_boot(Model::Dmg);
+    assert_eq!(cpu.regs.af(), 0x01B0);
+    assert_eq!(cpu.regs.bc(), 0x0013);
+    assert_eq!(cpu.regs.de(), 0x00D8);
+    assert_eq!(cpu.regs.hl(), 0x014D);
+    assert_eq!(cpu.regs.sp, 0xFFFE);
     assert_eq!(cpu.regs.pc, 0x0100);
+    assert_eq!(cpu.mmu.read(0xFF0F), 0xE1);
+    cpu.skip_boot(Model::Cgb);
+    assert_eq!(cpu.regs.a, 0x11);
+}
+
+#[test]
+fn test_skipping_the_cgb_boot_rom_sets_up_cgb_registers() {
+    let mut cpu = Z80::new();
+    cpu.mmu.set_cgb(true);
+    cpu.mmu.write_byte(0xFF4F, 0x01);
+    cpu.mmu.write_byte(0xFF70, 0x03);
+    cpu.mmu.write_byte(0xFF68, 0x85);
+    cpu.mmu.write_byte(0xFF69, 0x00);
+    cpu.skip_boot(Model::Cgb);
+    assert_eq!(cpu.mmu.read(0xFF4D), 0x7E);
+    assert_eq!(cpu.mmu.read(0xFF4F), 0xFE);
+    assert_eq!(cpu.mmu.read(0xFF70), 0xF8);
+    // The background palettes are all white
+    for index in 0..64 {
+        cpu.mmu.write_byte(0xFF68, index);
+        assert_eq!(cpu.mmu.read(0xFF69), 0xFF);
+    }
+}
+
+#[test]
+fn test_the_boot_rom_hands_over_to_the_cartridge() {
+    // LD A,1; LDH (50),A, then NOPs up to 0x0100
+    let mut boot_rom = vec![0; 0x100];
+    boot_rom[..4].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+    let path = ::std::env::temp_dir().join(format!("gb-{}-boot.bin", ::std::process::id()));
+    fs::write(&path, boot_rom).unwrap();
+    let mut cartridge = ::cartridge::test_rom(0x00, 0x00, 0x00);
+    cartridge[0x0000] = 0x76; // HALT, which the boot ROM hides
+    let mut cpu = Z80::new();
+    cpu.load_cartridge(::cartridge::Cartridge::from_bytes(&cartridge).unwrap());
+    cpu.load_boot_rom(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(cpu.regs.pc, 0x0000);
+    assert_eq!(cpu.mmu.read(0x0000), 0x3E);
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.mmu.read(0x0000), 0x76);
 }
 
 #[test]
@@ -1325,6 +2827,278 @@ fn test_the_clock_moves_forward_in_time() {
     assert_eq!(cpu.clock.t, 4+16);
 }
 
+#[test]
+fn test_stepping_runs_one_instruction() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x00); // NOP
+    cpu.mmu.write_byte(0xC001, 0x01); // LD BC,0x1234
+    cpu.mmu.write_byte(0xC002, 0x34);
+    cpu.mmu.write_byte(0xC003, 0x12);
+    cpu.mmu.write_byte(0xC004, 0xCB); // SET 0,a
+    cpu.mmu.write_byte(0xC005, 0xC7);
+    assert_eq!(cpu.step(), 4);
+    assert_eq!(cpu.regs.pc, 0xC001);
+    assert_eq!(cpu.step(), 12);
+    assert_eq!(cpu.regs.bc(), 0x1234);
+    assert_eq!(cpu.regs.pc, 0xC004);
+    assert_eq!(cpu.step(), 8);
+    assert_eq!(cpu.regs.a, 0x01);
+    assert_eq!(cpu.regs.pc, 0xC006);
+}
+
+#[test]
+fn test_stepping_follows_jumps() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x18); // JR -2
+    cpu.mmu.write_byte(0xC001, 0xFE);
+    assert_eq!(cpu.step(), 12);
+    assert_eq!(cpu.regs.pc, 0xC000);
+}
+
+#[test]
+fn test_stepping_while_halted_burns_time() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.halted = true;
+    assert_eq!(cpu.step(), 4);
+    assert_eq!(cpu.regs.pc, 0xC000);
+}
+
+#[test]
+fn test_running_for_a_number_of_cycles() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x18); // JR -2, forever
+    cpu.mmu.write_byte(0xC001, 0xFE);
+    // Instructions aren't split, so we run 3 * 12
+    assert_eq!(cpu.run_for_cycles(30), 36);
+    // ...and a frame happens to be a whole number of jumps
+    assert_eq!(cpu.run_frame(), CYCLES_PER_FRAME);
+}
+
+/// A CPU about to run `program` from 0xC000, with TIMA counting every 16
+/// cycles from 0xFF, and the counter just reset
+#[cfg(test)]
+fn timed_cpu(program: &[u8]) -> Z80 {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    for (i, &byte) in program.iter().enumerate() {
+        cpu.mmu.write_byte(0xC000 + i as u16, byte);
+    }
+    cpu.mmu.write_byte(::io::TMA, 0x80);
+    cpu.mmu.write_byte(::io::TIMA, 0xFF);
+    cpu.mmu.write_byte(::io::TAC, 0x05);
+    cpu.mmu.write_byte(::io::DIV, 0x00);
+    cpu
+}
+
+#[test]
+fn test_memory_is_read_partway_through_an_instruction() {
+    // NOP; LDH A,(TIMA). TIMA overflows 16 cycles in, which is when the
+    // LDH reads it: its third M-cycle.
+    let mut cpu = timed_cpu(&[0x00, 0xF0, 0x05]);
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.regs.a, 0x00);
+}
+
+#[test]
+fn test_writing_tima_is_timed_to_the_m_cycle() {
+    // NOP; LDH (TIMA),A, written in the cycle TIMA overflows: it cancels
+    // the reload
+    let mut cpu = timed_cpu(&[0x00, 0xE0, 0x05]);
+    cpu.regs.a = 0x42;
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.mmu.read(::io::TIMA), 0x42);
+    assert_eq!(cpu.mmu.read(0xFF0F) & ::interrupt::TIMER, 0x00);
+    // NOP; NOP; LDH (TIMA),A, written while TIMA is being reloaded: it's
+    // ignored
+    let mut cpu = timed_cpu(&[0x00, 0x00, 0xE0, 0x05]);
+    cpu.regs.a = 0x42;
+    for _ in 0..3 {
+        cpu.step();
+    }
+    assert_eq!(cpu.mmu.read(::io::TIMA), 0x80);
+    assert_eq!(cpu.mmu.read(0xFF0F) & ::interrupt::TIMER, ::interrupt::TIMER);
+}
+
+// Interrupts
+#[test]
+fn test_interrupts_jump_to_their_vector() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.ime = true;
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::TIMER);
+    cpu.mmu.request_interrupt(::interrupt::TIMER);
+    assert_eq!(cpu.step(), 20);
+    assert_eq!(cpu.regs.pc, 0x0050);
+    assert_eq!(cpu.stack_pop(), 0xC000);
+    // Servicing clears the request, and disables further interrupts
+    assert!(!cpu.ime);
+    assert_eq!(cpu.mmu.read(0xFF0F) & ::interrupt::ALL, 0x00);
+}
+
+#[test]
+fn test_interrupts_are_serviced_in_priority_order() {
+    let mut cpu = Z80::new();
+    cpu.ime = true;
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::ALL);
+    cpu.mmu.request_interrupt(::interrupt::JOYPAD | ::interrupt::LCD_STAT);
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0x0048);
+    assert_eq!(cpu.mmu.pending_interrupts(), ::interrupt::JOYPAD);
+}
+
+#[test]
+fn test_interrupts_wait_for_ime_and_ie() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.request_interrupt(::interrupt::VBLANK);
+    // Not enabled in IE
+    cpu.ime = true;
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0xC001);
+    // Enabled in IE, but IME is off
+    cpu.ime = false;
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::VBLANK);
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0xC002);
+}
+
+#[test]
+fn test_interrupts_are_enabled_one_instruction_after_EI() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0xFB); // EI
+    cpu.mmu.write_byte(0xC001, 0x00); // NOP
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::VBLANK);
+    cpu.mmu.request_interrupt(::interrupt::VBLANK);
+    cpu.step();
+    assert!(!cpu.ime);
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0xC002);
+    assert!(cpu.ime);
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0x0040);
+}
+
+#[test]
+fn test_DI_straight_after_EI_cancels_it() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0xFB); // EI
+    cpu.mmu.write_byte(0xC001, 0xF3); // DI
+    cpu.step();
+    cpu.step();
+    assert!(!cpu.ime);
+    assert!(!cpu.ei_pending);
+}
+
+#[test]
+fn test_RETI_enables_interrupts_immediately() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.stack_push(0xC100);
+    cpu.mmu.write_byte(0xC000, 0xD9); // RETI
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::SERIAL);
+    cpu.mmu.request_interrupt(::interrupt::SERIAL);
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0xC100);
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0x0058);
+}
+
+// Low power modes
+#[test]
+fn test_HALT_waits_for_an_interrupt() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x76); // HALT
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::TIMER);
+    cpu.step();
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.run_for_cycles(400), 400);
+    assert_eq!(cpu.regs.pc, 0xC001);
+    // With IME off, the interrupt wakes the CPU without being serviced
+    cpu.mmu.request_interrupt(::interrupt::TIMER);
+    assert_eq!(cpu.step(), 4);
+    assert!(!cpu.is_halted());
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0xC002);
+}
+
+#[test]
+fn test_HALT_services_interrupts_when_enabled() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.ime = true;
+    cpu.mmu.write_byte(0xC000, 0x76); // HALT
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::VBLANK);
+    cpu.step();
+    cpu.step();
+    assert!(cpu.is_halted());
+    cpu.mmu.request_interrupt(::interrupt::VBLANK);
+    assert_eq!(cpu.step(), 20);
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.regs.pc, 0x0040);
+    assert_eq!(cpu.stack_pop(), 0xC001);
+}
+
+#[test]
+fn test_the_HALT_bug_runs_the_next_byte_twice() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x76); // HALT
+    cpu.mmu.write_byte(0xC001, 0x3C); // INC a
+    cpu.mmu.write_byte(0xC002, 0x00); // NOP
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::SERIAL);
+    cpu.mmu.request_interrupt(::interrupt::SERIAL);
+    cpu.step();
+    assert!(!cpu.is_halted());
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0xC001);
+    cpu.step();
+    assert_eq!(cpu.regs.pc, 0xC002);
+    assert_eq!(cpu.regs.a, 0x02);
+}
+
+#[test]
+fn test_STOP_waits_for_a_button_press() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.ime = true;
+    cpu.mmu.write_byte(0xC000, 0x10); // STOP
+    cpu.mmu.write_byte(0xC001, 0x00);
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::ALL);
+    cpu.step();
+    assert!(cpu.is_stopped());
+    // Other interrupts don't get through
+    cpu.mmu.request_interrupt(::interrupt::TIMER);
+    cpu.run_for_cycles(400);
+    assert!(cpu.is_stopped());
+    assert_eq!(cpu.regs.pc, 0xC002);
+    cpu.mmu.request_interrupt(::interrupt::JOYPAD);
+    cpu.step();
+    assert!(!cpu.is_stopped());
+}
+
+#[test]
+fn test_pressing_a_button_wakes_the_cpu_from_STOP() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x10); // STOP
+    cpu.mmu.write_byte(::io::P1, 0x10); // Select the action buttons
+    cpu.step();
+    cpu.run_for_cycles(400);
+    assert!(cpu.is_stopped());
+    cpu.joypad_mut().press(::joypad::Button::Start);
+    cpu.run_for_cycles(8);
+    assert!(!cpu.is_stopped());
+}
+
 
 #[test]
 fn test_incrementing_16_bit_numbers() {
@@ -1419,17 +3193,46 @@ fn test_the_alu_adds_8_bit_numbers() {
     let added = cpu.add8(200, 100);
     assert_eq!(added, 44);
     assert!(cpu.flag_is_set(CARRY));
+    // 0x8 + 0x4 doesn't carry out of the low nibble
+    assert!(!cpu.flag_is_set(HALFCARRY));
+    let added = cpu.add8(0x0F, 0x01);
+    assert_eq!(added, 0x10);
+    assert!(!cpu.flag_is_set(CARRY));
     assert!(cpu.flag_is_set(HALFCARRY));
+    let added = cpu.add8(0x80, 0x80);
+    assert_eq!(added, 0x00);
+    assert!(cpu.flag_is_set(ZERO));
 }
 
 #[test]
-fn test_the_alu_adds_16_bit_numbers() {
+fn test_the_alu_subtracts_8_bit_numbers() {
     let mut cpu = Z80::new();
-    let added = cpu.add16(65535, 50);
-    assert_eq!(added, 49);
-    assert!(cpu.flag_is_set(CARRY));
+    let subtracted = cpu.sub8(0x10, 0x01);
+    assert_eq!(subtracted, 0x0F);
+    assert!(cpu.flag_is_set(SUBTRACT));
     assert!(cpu.flag_is_set(HALFCARRY));
-}
+    assert!(!cpu.flag_is_set(CARRY));
+    let subtracted = cpu.sub8(0x01, 0x02);
+    assert_eq!(subtracted, 0xFF);
+    assert!(cpu.flag_is_set(CARRY));
+    let subtracted = cpu.sub8(0x42, 0x42);
+    assert_eq!(subtracted, 0x00);
+    assert!(cpu.flag_is_set(ZERO));
+}
+
+#[test]
+fn test_the_alu_adds_16_bit_numbers() {
+    let mut cpu = Z80::new();
+    let added = cpu.add16(65535, 50);
+    assert_eq!(added, 49);
+    assert!(cpu.flag_is_set(CARRY));
+    assert!(cpu.flag_is_set(HALFCARRY));
+    // 16-bit adds leave the zero flag alone
+    cpu.set_flag(ZERO);
+    cpu.add16(0x0001, 0x0001);
+    assert!(cpu.flag_is_set(ZERO));
+    assert!(!cpu.flag_is_set(CARRY));
+}
 
 #[test]
 fn test_setting_flags() {
@@ -1451,11 +3254,11 @@ fn test_unsetting_flags() {
 fn test_pushing_to_the_stack() {
     let mut cpu = Z80::new();
     cpu.stack_push(0x4455);
-    assert_eq!(cpu.mmu.read_word(0xFFFE), 0x4455);
+    assert_eq!(cpu.mmu.read_word(0xFFFE - 2), 0x4455);
     assert_eq!(cpu.regs.sp, 0xFFFE - 2);
     // Check that the stack pushes *down*
     cpu.stack_push(0x6677);
-    assert_eq!(cpu.mmu.read_word(0xFFFE - 2), 0x6677);
+    assert_eq!(cpu.mmu.read_word(0xFFFE - 4), 0x6677);
     assert_eq!(cpu.regs.sp, 0xFFFE - 4);
 }
 
@@ -1634,6 +3437,75 @@ fn test_the_instruction_set_can_LDIAHLm() {
     assert_eq!(cpu.clock.t, 8);
 }
 
+#[test]
+fn test_the_instruction_set_can_LDDHLmA() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x01;
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0x05);
+    cpu.LDDHLmA();
+    assert_eq!(cpu.mmu.read(0xC001), 0x01);
+    assert_eq!(cpu.regs.l, 0x00);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_LDDAHLm() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x01;
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x00;
+    cpu.mmu.write_byte(0xC000, 0x05);
+    cpu.LDDAHLm();
+    assert_eq!(cpu.regs.a, 0x05);
+    assert_eq!(cpu.regs.hl(), 0xBFFF);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_LDIOnA() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x05;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x80);
+    cpu.LDIOnA();
+    assert_eq!(cpu.mmu.read(0xFF80), 0x05);
+    assert_eq!(cpu.regs.pc, 0xC001);
+    assert_eq!(cpu.clock.t, 12);
+}
+
+#[test]
+fn test_the_instruction_set_can_LDAIOn() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x80);
+    cpu.mmu.write_byte(0xFF80, 0x05);
+    cpu.LDAIOn();
+    assert_eq!(cpu.regs.a, 0x05);
+    assert_eq!(cpu.clock.t, 12);
+}
+
+#[test]
+fn test_the_instruction_set_can_LDIOCA() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x05;
+    cpu.regs.c = 0x81;
+    cpu.LDIOCA();
+    assert_eq!(cpu.mmu.read(0xFF81), 0x05);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_LDAIOC() {
+    let mut cpu = Z80::new();
+    cpu.regs.c = 0x81;
+    cpu.mmu.write_byte(0xFF81, 0x05);
+    cpu.LDAIOC();
+    assert_eq!(cpu.regs.a, 0x05);
+    assert_eq!(cpu.clock.t, 8);
+}
+
 
 // 16-bit loads
 #[test]
@@ -1663,6 +3535,38 @@ fn test_the_instruction_set_can_LDSPHL() {
     assert_eq!(cpu.clock.t, 8);
 }
 
+#[test]
+fn test_the_instruction_set_can_LDnmSP() {
+    let mut cpu = Z80::new();
+    cpu.regs.sp = 0x1234;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x10);
+    cpu.mmu.write_byte(0xC001, 0xC0);
+    cpu.LDnmSP();
+    assert_eq!(cpu.mmu.read(0xC010), 0x34);
+    assert_eq!(cpu.mmu.read(0xC011), 0x12);
+    assert_eq!(cpu.clock.t, 20);
+}
+
+#[test]
+fn test_the_instruction_set_can_LDHLSPn() {
+    let mut cpu = Z80::new();
+    cpu.regs.sp = 0xFFF8;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x02);
+    cpu.LDHLSPn();
+    assert_eq!(cpu.regs.hl(), 0xFFFA);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 12);
+    // Negative offsets
+    cpu.mmu.write_byte(0xC001, 0xFE); // -2
+    cpu.LDHLSPn();
+    assert_eq!(cpu.regs.hl(), 0xFFF6);
+    assert!(cpu.flag_is_set(CARRY));
+    assert!(cpu.flag_is_set(HALFCARRY));
+    assert!(!cpu.flag_is_set(ZERO));
+}
+
 #[test]
 fn test_the_instruction_set_can_PUSHrr() {
     let mut cpu = Z80::new();
@@ -1684,6 +3588,17 @@ fn test_the_instruction_set_can_POPrr() {
     assert_eq!(cpu.clock.t, 16+12);
 }
 
+#[test]
+fn test_the_instruction_set_can_POPAF() {
+    let mut cpu = Z80::new();
+    cpu.stack_push(0x12FF);
+    cpu.POPAF();
+    assert_eq!(cpu.regs.a, 0x12);
+    // The low nibble of f always reads back as zero
+    assert_eq!(cpu.regs.f, 0xF0);
+    assert_eq!(cpu.clock.t, 12);
+}
+
 
 // 8-bit adds
 #[test]
@@ -1725,6 +3640,7 @@ fn test_the_instruction_set_can_ADDHL() {
 #[test]
 fn test_the_instruction_set_can_ADCr_b() {
     let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
     cpu.regs.a = 0x64;
     cpu.regs.b = 0xC8;
     cpu.mmu.write_byte(0xC001, 0xC8);
@@ -1737,6 +3653,7 @@ fn test_the_instruction_set_can_ADCr_b() {
 #[test]
 fn test_the_instruction_set_can_ADCn() {
     let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
     cpu.regs.a = 0x64;
     cpu.regs.pc = 0xC000;
     cpu.mmu.write_byte(0xC000, 0xC8);
@@ -1749,6 +3666,7 @@ fn test_the_instruction_set_can_ADCn() {
 #[test]
 fn test_the_instruction_set_can_ADCHL() {
     let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
     cpu.regs.a = 0x64;
     cpu.regs.h = 0xC0;
     cpu.regs.l = 0x01;
@@ -1759,161 +3677,981 @@ fn test_the_instruction_set_can_ADCHL() {
     assert_eq!(cpu.clock.t, 8);
 }
 
-// 16-bit arithmetic
+// 8-bit subtracts and logic
 #[test]
-fn test_the_instruction_set_can_ADDHLrr() {
+fn test_the_instruction_set_can_SUBr() {
     let mut cpu = Z80::new();
-    cpu.regs.h = 0x10;
-    cpu.regs.l = 0x01;
-    cpu.regs.b = 0x11;
-    cpu.regs.c = 0x02;
-    cpu.ADDHLBC();
-    assert_eq!(cpu.regs.hl(), 0x2103);
-    assert_eq!(cpu.clock.t, 8);
+    cpu.regs.a = 0x3E;
+    cpu.regs.b = 0x3E;
+    cpu.SUBr_b();
+    assert_eq!(cpu.regs.a, 0x00);
+    assert!(cpu.flag_is_set(ZERO));
+    assert!(cpu.flag_is_set(SUBTRACT));
+    assert_eq!(cpu.clock.t, 4);
 }
 
-// Same class of opcode, but this one has non-macro logic,
-// so we'll double-check
 #[test]
-fn test_the_instruction_set_can_ADDHLSP() {
+fn test_the_instruction_set_can_SUBn() {
     let mut cpu = Z80::new();
-    cpu.regs.h = 0x10;
-    cpu.regs.l = 0x01;
-    cpu.regs.sp = 0x1102;
-    cpu.ADDHLSP();
-    assert_eq!(cpu.regs.hl(), 0x2103);
+    cpu.regs.a = 0x3E;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x0F);
+    cpu.SUBn();
+    assert_eq!(cpu.regs.a, 0x2F);
+    assert!(cpu.flag_is_set(HALFCARRY));
+    assert!(!cpu.flag_is_set(CARRY));
     assert_eq!(cpu.clock.t, 8);
 }
 
 #[test]
-fn test_the_instruction_set_can_INCrr() {
+fn test_the_instruction_set_can_SUBHL() {
     let mut cpu = Z80::new();
-    cpu.regs.h = 0x10;
+    cpu.regs.a = 0x3E;
+    cpu.regs.h = 0xC0;
     cpu.regs.l = 0x01;
-    cpu.INCHL();
-    assert_eq!(cpu.regs.hl(), 0x1002);
+    cpu.mmu.write_byte(0xC001, 0x40);
+    cpu.SUBHL();
+    assert_eq!(cpu.regs.a, 0xFE);
+    assert!(cpu.flag_is_set(CARRY));
     assert_eq!(cpu.clock.t, 8);
 }
 
 #[test]
-fn test_the_instruction_set_can_INCSP() {
+fn test_the_instruction_set_can_SBCr() {
     let mut cpu = Z80::new();
-    cpu.regs.sp = 0x1102;
-    cpu.INCSP();
-    assert_eq!(cpu.regs.sp, 0x1103);
-    assert_eq!(cpu.clock.t, 8);
+    cpu.set_flag(CARRY);
+    cpu.regs.a = 0x3B;
+    cpu.regs.h = 0x2A;
+    cpu.SBCr_h();
+    assert_eq!(cpu.regs.a, 0x10);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 4);
 }
 
+#[test]
+fn test_the_instruction_set_can_SBCn() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.a = 0x3B;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x3A);
+    cpu.SBCn();
+    assert_eq!(cpu.regs.a, 0x00);
+    assert!(cpu.flag_is_set(ZERO));
+    assert_eq!(cpu.clock.t, 8);
+}
 
 #[test]
-fn test_the_instruction_set_can_RLCA() {
+fn test_the_instruction_set_can_SBCHL() {
     let mut cpu = Z80::new();
     cpu.set_flag(CARRY);
-    cpu.regs.a = 0b00001001;
-    cpu.RLCA();
-    assert_eq!(cpu.regs.a, 0b00010010);
-    assert!(!cpu.flag_is_set(CARRY));
+    cpu.regs.a = 0x3B;
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0x4F);
+    cpu.SBCHL();
+    assert_eq!(cpu.regs.a, 0xEB);
+    assert!(cpu.flag_is_set(HALFCARRY));
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_ANDr() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x5A;
+    cpu.regs.l = 0x3F;
+    cpu.ANDr_l();
+    assert_eq!(cpu.regs.a, 0x1A);
+    assert_eq!(cpu.regs.f, HALFCARRY);
     assert_eq!(cpu.clock.t, 4);
 }
 
+#[test]
+fn test_the_instruction_set_can_ANDn() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x5A;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x38);
+    cpu.ANDn();
+    assert_eq!(cpu.regs.a, 0x18);
+    assert_eq!(cpu.clock.t, 8);
+}
 
 #[test]
-fn test_the_instruction_set_can_RLCr_n() {
+fn test_the_instruction_set_can_ANDHL() {
     let mut cpu = Z80::new();
-    cpu.set_flag(CARRY);
-    cpu.regs.b = 0b00001001;
-    cpu.RLCr_b();
-    assert_eq!(cpu.regs.b, 0b00010010);
-    assert!(!cpu.flag_is_set(CARRY));
+    cpu.regs.a = 0x5A;
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.ANDHL();
+    assert_eq!(cpu.regs.a, 0x00);
+    assert_eq!(cpu.regs.f, ZERO | HALFCARRY);
     assert_eq!(cpu.clock.t, 8);
 }
 
 #[test]
-fn test_the_instruction_set_can_RLA() {
+fn test_the_instruction_set_can_XORr() {
     let mut cpu = Z80::new();
-    cpu.set_flag(CARRY);
-    cpu.regs.a = 0b00001001;
-    cpu.RLA();
-    assert_eq!(cpu.regs.a, 0b00010011);
-    assert!(!cpu.flag_is_set(CARRY));
+    cpu.regs.a = 0xFF;
+    cpu.XORr_a();
+    assert_eq!(cpu.regs.a, 0x00);
+    assert_eq!(cpu.regs.f, ZERO);
     assert_eq!(cpu.clock.t, 4);
 }
 
-
 #[test]
-fn test_the_instruction_set_can_RLr_n() {
+fn test_the_instruction_set_can_XORn() {
     let mut cpu = Z80::new();
-    cpu.set_flag(CARRY);
-    cpu.regs.b = 0b00001001;
-    cpu.RLr_b();
-    assert_eq!(cpu.regs.b, 0b00010011);
-    assert!(!cpu.flag_is_set(CARRY));
+    cpu.regs.a = 0xFF;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x0F);
+    cpu.XORn();
+    assert_eq!(cpu.regs.a, 0xF0);
     assert_eq!(cpu.clock.t, 8);
 }
 
-// Right rotates
+#[test]
+fn test_the_instruction_set_can_XORHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0xFF;
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0x8A);
+    cpu.XORHL();
+    assert_eq!(cpu.regs.a, 0x75);
+    assert_eq!(cpu.clock.t, 8);
+}
 
 #[test]
-fn test_the_instruction_set_can_RRCA() {
+fn test_the_instruction_set_can_ORr() {
     let mut cpu = Z80::new();
-    cpu.set_flag(CARRY);
-    cpu.regs.a = 0b00010010;
-    cpu.RRCA();
-    assert_eq!(cpu.regs.a, 0b00001001);
-    assert!(!cpu.flag_is_set(CARRY));
+    cpu.regs.a = 0x5A;
+    cpu.ORr_a();
+    assert_eq!(cpu.regs.a, 0x5A);
+    assert_eq!(cpu.regs.f, 0x00);
     assert_eq!(cpu.clock.t, 4);
 }
 
+#[test]
+fn test_the_instruction_set_can_ORn() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x5A;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x03);
+    cpu.ORn();
+    assert_eq!(cpu.regs.a, 0x5B);
+    assert_eq!(cpu.clock.t, 8);
+}
 
 #[test]
-fn test_the_instruction_set_can_RRCr_n() {
+fn test_the_instruction_set_can_ORHL() {
     let mut cpu = Z80::new();
-    cpu.set_flag(CARRY);
-    cpu.regs.b = 0b00010010;
-    cpu.RRCr_b();
-    assert_eq!(cpu.regs.b, 0b00001001);
-    assert!(!cpu.flag_is_set(CARRY));
+    cpu.regs.a = 0x5A;
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0x0F);
+    cpu.ORHL();
+    assert_eq!(cpu.regs.a, 0x5F);
     assert_eq!(cpu.clock.t, 8);
 }
 
 #[test]
-fn test_the_instruction_set_can_RRA() {
+fn test_the_instruction_set_can_CPr() {
     let mut cpu = Z80::new();
-    cpu.set_flag(CARRY);
-    cpu.regs.a = 0b00010010;
-    cpu.RRA();
-    assert_eq!(cpu.regs.a, 0b10001001);
+    cpu.regs.a = 0x3C;
+    cpu.regs.b = 0x2F;
+    cpu.CPr_b();
+    // Compare only changes the flags
+    assert_eq!(cpu.regs.a, 0x3C);
+    assert!(cpu.flag_is_set(SUBTRACT));
+    assert!(cpu.flag_is_set(HALFCARRY));
     assert!(!cpu.flag_is_set(CARRY));
     assert_eq!(cpu.clock.t, 4);
 }
 
+#[test]
+fn test_the_instruction_set_can_CPn() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x3C;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x3C);
+    cpu.CPn();
+    assert_eq!(cpu.regs.a, 0x3C);
+    assert!(cpu.flag_is_set(ZERO));
+    assert_eq!(cpu.clock.t, 8);
+}
 
 #[test]
-fn test_the_instruction_set_can_RRr_n() {
+fn test_the_instruction_set_can_CPHL() {
     let mut cpu = Z80::new();
-    cpu.set_flag(CARRY);
-    cpu.regs.b = 0b00010010;
-    cpu.RRr_b();
-    assert_eq!(cpu.regs.b, 0b10001001);
-    assert!(!cpu.flag_is_set(CARRY));
+    cpu.regs.a = 0x3C;
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0x40);
+    cpu.CPHL();
+    assert_eq!(cpu.regs.a, 0x3C);
+    assert!(cpu.flag_is_set(CARRY));
     assert_eq!(cpu.clock.t, 8);
 }
 
+#[test]
+fn test_the_instruction_set_can_INCr() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.a = 0xFF;
+    cpu.INCr_a();
+    assert_eq!(cpu.regs.a, 0x00);
+    assert!(cpu.flag_is_set(ZERO));
+    assert!(cpu.flag_is_set(HALFCARRY));
+    // INC leaves the carry alone
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 4);
+}
+
+#[test]
+fn test_the_instruction_set_can_INCHLm() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0x50);
+    cpu.INCHLm();
+    assert_eq!(cpu.mmu.read(0xC001), 0x51);
+    assert_eq!(cpu.regs.f, 0x00);
+    assert_eq!(cpu.clock.t, 12);
+}
 
 #[test]
-fn test_the_instruction_set_can_CCF() {
+fn test_the_instruction_set_can_DECr() {
     let mut cpu = Z80::new();
-    cpu.CCF();
-    assert!( cpu.flag_is_set(CARRY) );
+    cpu.regs.l = 0x01;
+    cpu.DECr_l();
+    assert_eq!(cpu.regs.l, 0x00);
+    assert!(cpu.flag_is_set(ZERO));
+    assert!(cpu.flag_is_set(SUBTRACT));
+    assert!(!cpu.flag_is_set(HALFCARRY));
     assert_eq!(cpu.clock.t, 4);
-    cpu.CCF();
-    assert!( !cpu.flag_is_set(CARRY) );
-    assert_eq!(cpu.clock.t, 8);
 }
 
 #[test]
-#[should_panic]
-fn test_the_instruction_set_panics_on_unused_opcodes() {
+fn test_the_instruction_set_can_DECHLm() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.DECHLm();
+    assert_eq!(cpu.mmu.read(0xC001), 0xFF);
+    assert!(cpu.flag_is_set(HALFCARRY));
+    assert_eq!(cpu.clock.t, 12);
+}
+
+#[test]
+fn test_the_instruction_set_can_DAA() {
     let mut cpu = Z80::new();
-    cpu.XX();
+    // 0x45 + 0x38 = 0x7D in binary, 83 in decimal
+    cpu.regs.a = 0x45;
+    cpu.regs.b = 0x38;
+    cpu.ADDr_b();
+    cpu.DAA();
+    assert_eq!(cpu.regs.a, 0x83);
+    assert!(!cpu.flag_is_set(CARRY));
+    // 0x83 - 0x38 = 0x4B in binary, 45 in decimal
+    cpu.SUBr_b();
+    cpu.DAA();
+    assert_eq!(cpu.regs.a, 0x45);
+    // 0x99 + 0x01 wraps around to 00 with a carry
+    cpu.regs.a = 0x99;
+    cpu.regs.c = 0x01;
+    cpu.ADDr_c();
+    cpu.DAA();
+    assert_eq!(cpu.regs.a, 0x00);
+    assert!(cpu.flag_is_set(ZERO));
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 6*4);
+}
+
+#[test]
+fn test_the_instruction_set_can_CPL() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x35;
+    cpu.CPL();
+    assert_eq!(cpu.regs.a, 0xCA);
+    assert!(cpu.flag_is_set(SUBTRACT));
+    assert!(cpu.flag_is_set(HALFCARRY));
+    assert_eq!(cpu.clock.t, 4);
+}
+
+// 16-bit arithmetic
+#[test]
+fn test_the_instruction_set_can_ADDHLrr() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0x10;
+    cpu.regs.l = 0x01;
+    cpu.regs.b = 0x11;
+    cpu.regs.c = 0x02;
+    cpu.ADDHLBC();
+    assert_eq!(cpu.regs.hl(), 0x2103);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+// Same class of opcode, but this one has non-macro logic,
+// so we'll double-check
+#[test]
+fn test_the_instruction_set_can_ADDHLSP() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0x10;
+    cpu.regs.l = 0x01;
+    cpu.regs.sp = 0x1102;
+    cpu.ADDHLSP();
+    assert_eq!(cpu.regs.hl(), 0x2103);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_INCrr() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0x10;
+    cpu.regs.l = 0x01;
+    cpu.INCHL();
+    assert_eq!(cpu.regs.hl(), 0x1002);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_INCSP() {
+    let mut cpu = Z80::new();
+    cpu.regs.sp = 0x1102;
+    cpu.INCSP();
+    assert_eq!(cpu.regs.sp, 0x1103);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_DECrr() {
+    let mut cpu = Z80::new();
+    cpu.regs.d = 0x10;
+    cpu.regs.e = 0x00;
+    cpu.DECDE();
+    assert_eq!(cpu.regs.de(), 0x0FFF);
+    // 16-bit decrements don't touch the flags
+    assert_eq!(cpu.regs.f, 0x00);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_DECSP() {
+    let mut cpu = Z80::new();
+    cpu.regs.sp = 0x0000;
+    cpu.DECSP();
+    assert_eq!(cpu.regs.sp, 0xFFFF);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_ADDSPn() {
+    let mut cpu = Z80::new();
+    cpu.regs.sp = 0xFFF8;
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x02);
+    cpu.ADDSPn();
+    assert_eq!(cpu.regs.sp, 0xFFFA);
+    assert_eq!(cpu.regs.f, 0x00);
+    assert_eq!(cpu.clock.t, 16);
+}
+
+
+#[test]
+fn test_the_instruction_set_can_RLCA() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.a = 0b00001001;
+    cpu.RLCA();
+    assert_eq!(cpu.regs.a, 0b00010010);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 4);
+}
+
+
+#[test]
+fn test_the_instruction_set_can_RLCr_n() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.b = 0b00001001;
+    cpu.RLCr_b();
+    assert_eq!(cpu.regs.b, 0b00010010);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_RLA() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.a = 0b00001001;
+    cpu.RLA();
+    assert_eq!(cpu.regs.a, 0b00010011);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 4);
+}
+
+
+#[test]
+fn test_the_instruction_set_can_RLr_n() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.b = 0b00001001;
+    cpu.RLr_b();
+    assert_eq!(cpu.regs.b, 0b00010011);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 8);
+}
+
+// Right rotates
+
+#[test]
+fn test_the_instruction_set_can_RRCA() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.a = 0b00010010;
+    cpu.RRCA();
+    assert_eq!(cpu.regs.a, 0b00001001);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 4);
+}
+
+
+#[test]
+fn test_the_instruction_set_can_RRCr_n() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.b = 0b00010010;
+    cpu.RRCr_b();
+    assert_eq!(cpu.regs.b, 0b00001001);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_RRA() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.a = 0b00010010;
+    cpu.RRA();
+    assert_eq!(cpu.regs.a, 0b10001001);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 4);
+}
+
+
+#[test]
+fn test_the_instruction_set_can_RRr_n() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.b = 0b00010010;
+    cpu.RRr_b();
+    assert_eq!(cpu.regs.b, 0b10001001);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_sets_zero_on_CB_rotates() {
+    let mut cpu = Z80::new();
+    cpu.regs.c = 0x80;
+    cpu.RLr_c();
+    assert_eq!(cpu.regs.c, 0x00);
+    assert!(cpu.flag_is_set(ZERO));
+    assert!(cpu.flag_is_set(CARRY));
+    // ...unlike the one-byte accumulator rotates
+    cpu.regs.a = 0x80;
+    cpu.unset_flag(CARRY);
+    cpu.RLA();
+    assert_eq!(cpu.regs.a, 0x00);
+    assert!(!cpu.flag_is_set(ZERO));
+}
+
+#[test]
+fn test_the_instruction_set_can_RLCHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0b10000001);
+    cpu.RLCHL();
+    assert_eq!(cpu.mmu.read(0xC001), 0b00000011);
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_RLHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0b10000001);
+    cpu.RLHL();
+    assert_eq!(cpu.mmu.read(0xC001), 0b00000010);
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_RRCHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0b00000001);
+    cpu.RRCHL();
+    assert_eq!(cpu.mmu.read(0xC001), 0b10000000);
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_RRHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0b00000001);
+    cpu.RRHL();
+    assert_eq!(cpu.mmu.read(0xC001), 0b00000000);
+    assert!(cpu.flag_is_set(ZERO));
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 16);
+}
+
+// Shifts
+#[test]
+fn test_the_instruction_set_can_SLAr() {
+    let mut cpu = Z80::new();
+    cpu.regs.d = 0b10000001;
+    cpu.SLAr_d();
+    assert_eq!(cpu.regs.d, 0b00000010);
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_SLAHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0b11111111);
+    cpu.SLAHL();
+    assert_eq!(cpu.mmu.read(0xC001), 0b11111110);
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_SRAr() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0b10001010;
+    cpu.SRAr_a();
+    assert_eq!(cpu.regs.a, 0b11000101);
+    assert!(!cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_SRAHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0b00000001);
+    cpu.SRAHL();
+    assert_eq!(cpu.mmu.read(0xC001), 0b00000000);
+    assert!(cpu.flag_is_set(ZERO));
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_SWAPr() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.e = 0xF1;
+    cpu.SWAPr_e();
+    assert_eq!(cpu.regs.e, 0x1F);
+    assert_eq!(cpu.regs.f, 0x00);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_SWAPHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.SWAPHL();
+    assert_eq!(cpu.mmu.read(0xC001), 0x00);
+    assert_eq!(cpu.regs.f, ZERO);
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_SRLr() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0b10001011;
+    cpu.SRLr_a();
+    assert_eq!(cpu.regs.a, 0b01000101);
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_SRLHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0xFF);
+    cpu.SRLHL();
+    assert_eq!(cpu.mmu.read(0xC001), 0x7F);
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 16);
+}
+
+// Single bit operations
+#[test]
+fn test_the_instruction_set_can_BITr() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(CARRY);
+    cpu.regs.a = 0x80;
+    cpu.BITr_a(7);
+    assert!(!cpu.flag_is_set(ZERO));
+    assert!(cpu.flag_is_set(HALFCARRY));
+    cpu.BITr_a(6);
+    assert!(cpu.flag_is_set(ZERO));
+    // BIT leaves the carry alone
+    assert!(cpu.flag_is_set(CARRY));
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_BITHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0xFE);
+    cpu.BITHL(0);
+    assert!(cpu.flag_is_set(ZERO));
+    assert_eq!(cpu.clock.t, 12);
+}
+
+#[test]
+fn test_the_instruction_set_can_SETr() {
+    let mut cpu = Z80::new();
+    cpu.regs.a = 0x80;
+    cpu.SETr_a(3);
+    assert_eq!(cpu.regs.a, 0x88);
+    assert_eq!(cpu.regs.f, 0x00);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_SETHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.SETHL(7);
+    assert_eq!(cpu.mmu.read(0xC001), 0x80);
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_RESr() {
+    let mut cpu = Z80::new();
+    cpu.regs.l = 0x39;
+    cpu.RESr_l(3);
+    assert_eq!(cpu.regs.l, 0x31);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_RESHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.h = 0xC0;
+    cpu.regs.l = 0x01;
+    cpu.mmu.write_byte(0xC001, 0xFF);
+    cpu.RESHL(0);
+    assert_eq!(cpu.mmu.read(0xC001), 0xFE);
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_MAPcb() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x7C); // BIT 7,h
+    cpu.regs.h = 0x80;
+    cpu.call(0xCB);
+    assert!(!cpu.flag_is_set(ZERO));
+    assert_eq!(cpu.regs.pc, 0xC001);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_dispatches_every_CB_opcode() {
+    for opcode in 0..0x100 {
+        let mut cpu = Z80::new();
+        cpu.regs.set_hl(0xC000);
+        cpu.call_cb(opcode as u8);
+        // Every (HL) command is slower than the register ones
+        let expected = match (opcode & 0x07, opcode & 0xC0) {
+            (0x06, 0x40) => 12,
+            (0x06, _)    => 16,
+            _            => 8,
+        };
+        assert_eq!(cpu.clock.t, expected, "opcode CB {:02X}", opcode);
+    }
+}
+
+
+#[test]
+fn test_the_instruction_set_can_CCF() {
+    let mut cpu = Z80::new();
+    cpu.CCF();
+    assert!( cpu.flag_is_set(CARRY) );
+    assert_eq!(cpu.clock.t, 4);
+    cpu.CCF();
+    assert!( !cpu.flag_is_set(CARRY) );
+    assert_eq!(cpu.clock.t, 8);
+}
+
+#[test]
+fn test_the_instruction_set_can_SCF() {
+    let mut cpu = Z80::new();
+    cpu.set_flag(SUBTRACT | HALFCARRY);
+    cpu.SCF();
+    assert_eq!(cpu.regs.f, CARRY);
+    assert_eq!(cpu.clock.t, 4);
+}
+
+#[test]
+fn test_the_instruction_set_can_NOP() {
+    let mut cpu = Z80::new();
+    cpu.NOP();
+    assert_eq!(cpu.regs.pc, 0x0100);
+    assert_eq!(cpu.clock.t, 4);
+}
+
+#[test]
+fn test_the_instruction_set_can_HALT() {
+    let mut cpu = Z80::new();
+    cpu.HALT();
+    assert!(cpu.halted);
+    assert_eq!(cpu.clock.t, 4);
+}
+
+#[test]
+fn test_the_instruction_set_can_STOP() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.STOP();
+    assert!(cpu.stopped);
+    // Skips the padding byte
+    assert_eq!(cpu.regs.pc, 0xC001);
+    assert_eq!(cpu.clock.t, 4);
+}
+
+#[test]
+fn test_the_instruction_set_can_DI_and_EI() {
+    let mut cpu = Z80::new();
+    cpu.EI();
+    assert!(cpu.ei_pending);
+    cpu.DI();
+    assert!(!cpu.ime);
+    assert!(!cpu.ei_pending);
+    assert_eq!(cpu.clock.t, 8);
+}
+
+// Jumps
+#[test]
+fn test_the_instruction_set_can_JPnn() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x34);
+    cpu.mmu.write_byte(0xC001, 0x12);
+    cpu.JPnn();
+    assert_eq!(cpu.regs.pc, 0x1234);
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_JPHL() {
+    let mut cpu = Z80::new();
+    cpu.regs.set_hl(0x1234);
+    cpu.JPHL();
+    assert_eq!(cpu.regs.pc, 0x1234);
+    assert_eq!(cpu.clock.t, 4);
+}
+
+#[test]
+fn test_the_instruction_set_can_JPfnn() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x34);
+    cpu.mmu.write_byte(0xC001, 0x12);
+    // Not taken
+    cpu.JPZnn();
+    assert_eq!(cpu.regs.pc, 0xC002);
+    assert_eq!(cpu.clock.t, 12);
+    // Taken
+    cpu.regs.pc = 0xC000;
+    cpu.JPNZnn();
+    assert_eq!(cpu.regs.pc, 0x1234);
+    assert_eq!(cpu.clock.t, 12+16);
+}
+
+#[test]
+fn test_the_instruction_set_can_JRn() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x05);
+    cpu.JRn();
+    assert_eq!(cpu.regs.pc, 0xC006);
+    assert_eq!(cpu.clock.t, 12);
+    // Jump backwards
+    cpu.mmu.write_byte(0xC006, 0xFE); // -2
+    cpu.JRn();
+    assert_eq!(cpu.regs.pc, 0xC005);
+}
+
+#[test]
+fn test_the_instruction_set_can_JRfn() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x05);
+    // Not taken
+    cpu.JRCn();
+    assert_eq!(cpu.regs.pc, 0xC001);
+    assert_eq!(cpu.clock.t, 8);
+    // Taken
+    cpu.regs.pc = 0xC000;
+    cpu.set_flag(CARRY);
+    cpu.JRCn();
+    assert_eq!(cpu.regs.pc, 0xC006);
+    assert_eq!(cpu.clock.t, 8+12);
+}
+
+#[test]
+fn test_the_instruction_set_can_CALLnn() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x34);
+    cpu.mmu.write_byte(0xC001, 0x12);
+    cpu.CALLnn();
+    assert_eq!(cpu.regs.pc, 0x1234);
+    // The return address is the instruction after the call
+    assert_eq!(cpu.stack_pop(), 0xC002);
+    assert_eq!(cpu.clock.t, 24);
+}
+
+#[test]
+fn test_the_instruction_set_can_CALLfnn() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x34);
+    cpu.mmu.write_byte(0xC001, 0x12);
+    // Not taken
+    cpu.set_flag(CARRY);
+    cpu.CALLNCnn();
+    assert_eq!(cpu.regs.pc, 0xC002);
+    assert_eq!(cpu.regs.sp, 0xFFFE);
+    assert_eq!(cpu.clock.t, 12);
+    // Taken
+    cpu.regs.pc = 0xC000;
+    cpu.CALLCnn();
+    assert_eq!(cpu.regs.pc, 0x1234);
+    assert_eq!(cpu.stack_pop(), 0xC002);
+    assert_eq!(cpu.clock.t, 12+24);
+}
+
+#[test]
+fn test_the_instruction_set_can_RET() {
+    let mut cpu = Z80::new();
+    cpu.stack_push(0x1234);
+    cpu.RET();
+    assert_eq!(cpu.regs.pc, 0x1234);
+    assert_eq!(cpu.regs.sp, 0xFFFE);
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_RETf() {
+    let mut cpu = Z80::new();
+    cpu.stack_push(0x1234);
+    // Not taken
+    cpu.RETZ();
+    assert_eq!(cpu.regs.pc, 0x0100);
+    assert_eq!(cpu.clock.t, 8);
+    // Taken
+    cpu.RETNZ();
+    assert_eq!(cpu.regs.pc, 0x1234);
+    assert_eq!(cpu.clock.t, 8+20);
+}
+
+#[test]
+fn test_the_instruction_set_can_RETI() {
+    let mut cpu = Z80::new();
+    cpu.stack_push(0x1234);
+    cpu.RETI();
+    assert_eq!(cpu.regs.pc, 0x1234);
+    assert!(cpu.ime);
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_can_RST() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.RST38();
+    assert_eq!(cpu.regs.pc, 0x0038);
+    assert_eq!(cpu.stack_pop(), 0xC000);
+    assert_eq!(cpu.clock.t, 16);
+}
+
+#[test]
+fn test_the_instruction_set_dispatches_every_documented_opcode() {
+    let unused = [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD];
+    for opcode in 0..0x100 {
+        if unused.contains(&opcode) { continue }
+        let mut cpu = Z80::new();
+        cpu.regs.pc = 0xC000;
+        cpu.call(opcode as u8);
+        assert!(cpu.clock.t > 0, "opcode {:02X} took no time", opcode);
+    }
+}
+
+#[test]
+fn test_unused_opcodes_lock_up_the_cpu() {
+    let mut cpu = Z80::new();
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0xD3);
+    assert_eq!(cpu.step(), 4);
+    assert!(cpu.is_locked());
+    assert_eq!(cpu.regs.pc, 0xC001);
+    // Interrupts don't get it going again
+    cpu.ime = true;
+    cpu.mmu.write_byte(0xFFFF, ::interrupt::ALL);
+    cpu.mmu.request_interrupt(::interrupt::VBLANK);
+    assert_eq!(cpu.run_for_cycles(400), 400);
+    assert_eq!(cpu.regs.pc, 0xC001);
+    assert!(cpu.is_locked());
+}
+
+#[test]
+fn test_STOP_switches_speed_on_the_cgb() {
+    let mut cpu = Z80::new();
+    cpu.mmu.set_cgb(true);
+    cpu.regs.pc = 0xC000;
+    cpu.mmu.write_byte(0xC000, 0x10); // STOP
+    cpu.mmu.write_byte(0xC001, 0x00);
+    cpu.mmu.write_byte(::io::KEY1, 0x01);
+    cpu.step();
+    assert!(!cpu.is_stopped());
+    assert!(cpu.mmu.double_speed());
+    // The screen takes twice as many cycles to draw now
+    cpu.mmu.write_byte(::io::LCDC, 0x80);
+    cpu.mmu.write_byte(0xC002, 0x18); // JR -2
+    cpu.mmu.write_byte(0xC003, 0xFE);
+    cpu.run_for_cycles(::gpu::CYCLES_PER_LINE * 2);
+    assert_eq!(cpu.mmu.read(::io::LY), 1);
 }
@@ -1,14 +1,923 @@
+// The GPU
+// --- ---
+// The screen is drawn one line at a time, 154 lines a frame (the last ten
+// are off the bottom of the screen: VBlank), 456 clock cycles a line.
+// Each visible line goes through three modes: searching OAM for sprites,
+// transferring pixels to the LCD, and waiting out the rest of the line in
+// HBlank.
+//
+// The Game Boy Color has twice the VRAM: bank 1 holds more tiles, and an
+// attribute for each entry in the tile maps (its palette, its bank and
+// flips). Colours come from palette RAM instead of BGP and OBP0/1, as
+// 15-bit RGB.
+
+use io;
+
+pub const SCREEN_WIDTH  : usize = 160;
+pub const SCREEN_HEIGHT : usize = 144;
+
+/// How long each line takes to draw, in clock cycles
+pub const CYCLES_PER_LINE : u32 = 456;
+const OAM_SCAN_CYCLES : u32 = 80;
+// This really depends on scrolling, the window and sprites, but 172 is
+// the shortest it can be
+const TRANSFER_CYCLES : u32 = 172;
+
+/// Lines on the screen, and then in VBlank
+const VISIBLE_LINES : u8 = 144;
+const LINES_PER_FRAME : u8 = 154;
+
+// LCDC
+const LCD_ENABLE    : u8 = 0x80;
+const WINDOW_MAP    : u8 = 0x40; // 0x9800 or 0x9C00
+const WINDOW_ENABLE : u8 = 0x20;
+const TILE_DATA     : u8 = 0x10; // 0x8800 (signed) or 0x8000 (unsigned)
+const BG_MAP        : u8 = 0x08; // 0x9800 or 0x9C00
+const OBJ_SIZE      : u8 = 0x04; // 8x8 or 8x16
+const OBJ_ENABLE    : u8 = 0x02;
+const BG_ENABLE     : u8 = 0x01;
+
+// Sprite attributes
+const BEHIND_BG : u8 = 0x80; // Only shows through background colour 0
+const Y_FLIP    : u8 = 0x40;
+const X_FLIP    : u8 = 0x20;
+const PALETTE   : u8 = 0x10; // OBP0 or OBP1
+// Game Boy Color sprite and tile map attributes. Tile maps use BEHIND_BG
+// and the flips too, with BEHIND_BG putting the tile over sprites.
+const VRAM_BANK   : u8 = 0x08;
+const CGB_PALETTE : u8 = 0x07;
+
+/// Only the first ten sprites on a line (in OAM order) are drawn
+const SPRITES_PER_LINE : usize = 10;
+
+// STAT
+const LYC_INTERRUPT    : u8 = 0x40;
+const OAM_INTERRUPT    : u8 = 0x20;
+const VBLANK_INTERRUPT : u8 = 0x10;
+const HBLANK_INTERRUPT : u8 = 0x08;
+const COINCIDENCE      : u8 = 0x04;
+
+// BCPS and OCPS
+const AUTO_INCREMENT : u8 = 0x80;
+
+/// The DMG's four shades, in RGB555 for `rgb555_framebuffer`
+const DMG_COLORS : [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+/// One of the 40 entries in OAM
+#[derive(Debug, Clone, Copy)]
+struct Sprite {
+    y: i16, // The top of the sprite, on screen
+    x: i16, // The left of the sprite, on screen
+    tile: u8,
+    attributes: u8,
+}
+
+impl Sprite {
+    fn from_oam(oam: &[u8]) -> Sprite {
+        // The coordinates are of the bottom right of a 16x16 sprite, so
+        // that sprites can be partly (or entirely) off the screen
+        Sprite {
+            y: oam[0] as i16 - 16,
+            x: oam[1] as i16 - 8,
+            tile: oam[2],
+            attributes: oam[3],
+        }
+    }
+}
+
+/// What the GPU is doing, as it appears in the bottom two bits of STAT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    HBlank   = 0,
+    VBlank   = 1,
+    OamScan  = 2,
+    Transfer = 3,
+}
+
 pub struct GPU {
-    pub vram: [u8; (1024*8)],
+    pub vram: [u8; 1024*16], // Two banks, though only the CGB has bank 1
     pub oam:  [u8; 160],
+    mode: Mode,
+    cycles: u32, // How far through the current line we are
+    // Interrupts to request, collected by `tick`
+    interrupts: u8,
+    // The STAT interrupt is requested when any of its sources comes on,
+    // and not again until they've all gone off
+    stat_line: bool,
+    // Shades 0 (white) to 3 (black), a line at a time
+    framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // ...and the same in RGB555, which is the only way to see colour
+    rgb555_framebuffer: [u16; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // The background's colour numbers on the line being drawn, before
+    // the palette. Sprites need these to know whether they're behind.
+    line_colors: [u8; SCREEN_WIDTH],
+    // Whether each background pixel on the line is over sprites (CGB)
+    line_priority: [bool; SCREEN_WIDTH],
+    // The window keeps its own line count: lines it isn't drawn on don't
+    // count. It only shows up once LY has reached WY in a frame.
+    window_line: u8,
+    window_triggered: bool,
+    // Whether the CPU is kept out of VRAM and OAM while they're in use.
+    // Turning this off can help with debugging.
+    memory_locking: bool,
+    // LCD registers, at 0xFF40 ..= 0xFF4B
+    lcdc: u8, // LCD control
+    stat: u8, // LCD status. Only the interrupt selects are kept here.
+    scy: u8,  // Background scroll
+    scx: u8,
+    ly: u8,   // The line being drawn
+    lyc: u8,  // Compared against LY, for the STAT interrupt
+    bgp: u8,  // Palettes
+    obp0: u8,
+    obp1: u8,
+    wy: u8,   // Window position
+    wx: u8,
+    // Game Boy Color only
+    cgb: bool,
+    vram_bank: usize, // VBK
+    bg_palettes: [u8; 64], // Eight palettes of four RGB555 colours
+    obj_palettes: [u8; 64],
+    bcps: u8, // Which byte of palette RAM BCPD and OCPD get at
+    ocps: u8,
+    opri: u8, // Whether sprites are prioritised by X (1), or OAM order (0)
+}
+
+impl Default for GPU {
+    fn default() -> GPU {
+        GPU::new()
+    }
 }
 
 impl GPU {
     pub fn new() -> GPU {
         GPU {
-            vram: [0; (1024*8)],
+            vram: [0; 1024*16],
             oam:  [0; 160],
+            mode: Mode::HBlank,
+            cycles: 0,
+            interrupts: 0x00,
+            stat_line: false,
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            rgb555_framebuffer: [DMG_COLORS[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+            line_colors: [0; SCREEN_WIDTH],
+            line_priority: [false; SCREEN_WIDTH],
+            window_line: 0,
+            window_triggered: false,
+            memory_locking: true,
+            lcdc: 0x00,
+            stat: 0x00,
+            scy: 0x00,
+            scx: 0x00,
+            ly: 0x00,
+            lyc: 0x00,
+            bgp: 0x00,
+            obp0: 0x00,
+            obp1: 0x00,
+            wy: 0x00,
+            wx: 0x00,
+            cgb: false,
+            vram_bank: 0,
+            // The CGB boot ROM leaves every colour white
+            bg_palettes: [0xFF; 64],
+            obj_palettes: [0xFF; 64],
+            bcps: 0x00,
+            ocps: 0x00,
+            opri: 0x00,
+        }
+    }
+
+    /// Draw in colour, with the Game Boy Color's registers.
+    pub fn set_cgb(&mut self, cgb: bool) {
+        self.cgb = cgb;
+    }
+
+    /// Read from 0x8000 ..= 0x9FFF, in the bank VBK selects
+    pub fn read_vram(&self, address: u16) -> u8 {
+        self.vram[self.vram_bank * 0x2000 + (address as usize & 0x1FFF)]
+    }
+
+    pub fn write_vram(&mut self, address: u16, val: u8) {
+        self.vram[self.vram_bank * 0x2000 + (address as usize & 0x1FFF)] = val;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    /// The screen, in shades from 0 (white) to 3 (black), row by row.
+    /// It's complete once LY reaches VBlank. On the Game Boy Color, these
+    /// are colour numbers before the palette instead.
+    pub fn framebuffer(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.framebuffer
+    }
+
+    /// The screen in colour: 0bbbbbgggggrrrrr, row by row. On the DMG, the
+    /// four shades are greys.
+    pub fn rgb555_framebuffer(&self) -> &[u16; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.rgb555_framebuffer
+    }
+
+    /// Whether the CPU can get at VRAM. It can't while pixels are being
+    /// transferred to the LCD.
+    pub fn vram_accessible(&self) -> bool {
+        !self.memory_locking || self.mode != Mode::Transfer
+    }
+
+    /// Whether the CPU can get at OAM. It can't while the GPU is searching
+    /// it for sprites, or drawing them.
+    pub fn oam_accessible(&self) -> bool {
+        !self.memory_locking || (self.mode != Mode::OamScan && self.mode != Mode::Transfer)
+    }
+
+    pub fn set_memory_locking(&mut self, enabled: bool) {
+        self.memory_locking = enabled;
+    }
+
+    fn lcd_enabled(&self) -> bool {
+        self.lcdc & LCD_ENABLE != 0
+    }
+
+    /// Let `cycles` T-cycles pass. Returns the interrupts (VBLANK and
+    /// LCD_STAT) to request.
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        if self.lcd_enabled() {
+            for _ in 0..cycles {
+                self.tick_once();
+            }
+        }
+        let interrupts = self.interrupts;
+        self.interrupts = 0x00;
+        interrupts
+    }
+
+    fn tick_once(&mut self) {
+        self.cycles += 1;
+        if self.cycles == CYCLES_PER_LINE {
+            self.cycles = 0;
+            self.ly = (self.ly + 1) % LINES_PER_FRAME;
+            if self.ly == 0 {
+                self.window_line = 0;
+                self.window_triggered = false;
+            }
+            if self.ly == VISIBLE_LINES {
+                self.mode = Mode::VBlank;
+                self.interrupts |= ::interrupt::VBLANK;
+            } else if self.ly < VISIBLE_LINES {
+                self.mode = Mode::OamScan;
+            }
+        } else if self.ly < VISIBLE_LINES {
+            if self.cycles == OAM_SCAN_CYCLES {
+                self.mode = Mode::Transfer;
+            } else if self.cycles == OAM_SCAN_CYCLES + TRANSFER_CYCLES {
+                self.render_line();
+                self.mode = Mode::HBlank;
+            }
+        }
+        self.update_stat_line();
+    }
+
+    /// Draw the background and window on line LY into the framebuffer
+    fn render_line(&mut self) {
+        if self.ly == self.wy {
+            self.window_triggered = true;
+        }
+        let y = self.ly;
+        // The window's left edge is at WX - 7
+        let window_x = self.wx as i16 - 7;
+        let window = self.lcdc & WINDOW_ENABLE != 0 && self.window_triggered
+            && window_x < SCREEN_WIDTH as i16;
+        // On the DMG, turning the background off turns off the window too.
+        // The CGB draws them both anyway, but puts sprites over them.
+        let background = self.lcdc & BG_ENABLE != 0 || self.cgb;
+        for x in 0..SCREEN_WIDTH {
+            let (color, attributes) = if !background {
+                (0, 0x00)
+            } else if window && x as i16 >= window_x {
+                let map = if self.lcdc & WINDOW_MAP != 0 { 0x9C00 } else { 0x9800 };
+                self.tile_map_pixel(map, (x as i16 - window_x) as u8, self.window_line)
+            } else {
+                // The background wraps around
+                let map = if self.lcdc & BG_MAP != 0 { 0x9C00 } else { 0x9800 };
+                self.tile_map_pixel(map, (x as u8).wrapping_add(self.scx), y.wrapping_add(self.scy))
+            };
+            self.line_colors[x] = color;
+            self.line_priority[x] = attributes & BEHIND_BG != 0;
+            let pixel = y as usize * SCREEN_WIDTH + x;
+            if self.cgb {
+                self.framebuffer[pixel] = color;
+                self.rgb555_framebuffer[pixel] =
+                    palette_color(&self.bg_palettes, attributes & CGB_PALETTE, color);
+            } else if !background {
+                // Plain white, whatever BGP says
+                self.framebuffer[pixel] = 0;
+                self.rgb555_framebuffer[pixel] = DMG_COLORS[0];
+            } else {
+                self.framebuffer[pixel] = shade(self.bgp, color);
+                self.rgb555_framebuffer[pixel] = DMG_COLORS[self.framebuffer[pixel] as usize];
+            }
+        }
+        if window && background {
+            self.window_line += 1;
+        }
+        if self.lcdc & OBJ_ENABLE != 0 {
+            self.render_sprites();
+        }
+    }
+
+    /// Draw the sprites on line LY over the background
+    fn render_sprites(&mut self) {
+        let y = self.ly as i16;
+        let height = if self.lcdc & OBJ_SIZE != 0 { 16 } else { 8 };
+        let mut sprites = self.oam.chunks(4)
+            .map(Sprite::from_oam)
+            .filter(|sprite| y >= sprite.y && y < sprite.y + height)
+            .take(SPRITES_PER_LINE)
+            .collect::<Vec<Sprite>>();
+        // The leftmost sprite goes on top. When two sprites start in the
+        // same place, the first in OAM wins (the sort is stable). The CGB
+        // just goes by OAM, unless OPRI asks for the DMG's way.
+        if !self.cgb || self.opri & 0x01 != 0 {
+            sprites.sort_by_key(|sprite| sprite.x);
+        }
+
+        for x in 0..SCREEN_WIDTH as i16 {
+            // The first sprite with a pixel here wins, even when it's then
+            // hidden behind the background
+            let pixel = sprites.iter()
+                .filter(|sprite| x >= sprite.x && x < sprite.x + 8)
+                .map(|sprite| (sprite, self.sprite_pixel(sprite, x, y, height)))
+                .find(|&(_, color)| color != 0);
+            if let Some((sprite, color)) = pixel {
+                if self.behind_background(sprite, x as usize) {
+                    continue;
+                }
+                let index = y as usize * SCREEN_WIDTH + x as usize;
+                if self.cgb {
+                    let palette = sprite.attributes & CGB_PALETTE;
+                    self.framebuffer[index] = color;
+                    self.rgb555_framebuffer[index] = palette_color(&self.obj_palettes, palette, color);
+                } else {
+                    let palette = if sprite.attributes & PALETTE != 0 { self.obp1 } else { self.obp0 };
+                    self.framebuffer[index] = shade(palette, color);
+                    self.rgb555_framebuffer[index] = DMG_COLORS[self.framebuffer[index] as usize];
+                }
+            }
+        }
+    }
+
+    /// Whether the background at `x` on this line hides `sprite`. Only
+    /// background colour 0 never does.
+    fn behind_background(&self, sprite: &Sprite, x: usize) -> bool {
+        if self.line_colors[x] == 0 {
+            return false;
+        }
+        if self.cgb {
+            // With BG_ENABLE off, sprites go over everything
+            self.lcdc & BG_ENABLE != 0
+                && (sprite.attributes & BEHIND_BG != 0 || self.line_priority[x])
+        } else {
+            sprite.attributes & BEHIND_BG != 0
+        }
+    }
+
+    /// The colour number of `sprite` at (x, y) on the screen. Colour 0 is
+    /// transparent.
+    fn sprite_pixel(&self, sprite: &Sprite, x: i16, y: i16, height: i16) -> u8 {
+        let mut row = y - sprite.y;
+        let mut column = x - sprite.x;
+        if sprite.attributes & Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+        if sprite.attributes & X_FLIP != 0 {
+            column = 7 - column;
+        }
+        // Tall sprites are two tiles, one on top of the other. Bit 0 of
+        // the tile number is ignored.
+        let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+        let address = 0x8000 + tile as u16 * 16 + (row as u16 / 8) * 16;
+        let bank = self.cgb && sprite.attributes & VRAM_BANK != 0;
+        tile_pixel(self.tile_bank(bank), address, column as u8, row as u8 % 8)
+    }
+
+    fn tile_bank(&self, bank_1: bool) -> &[u8] {
+        if bank_1 { &self.vram[0x2000..] } else { &self.vram[..0x2000] }
+    }
+
+    /// The colour number (before the palette) at (x, y) in a 256x256
+    /// tile map, and the tile's attributes (always 0 on the DMG)
+    fn tile_map_pixel(&self, map: u16, x: u8, y: u8) -> (u8, u8) {
+        let index = (map + (y as u16 / 8) * 32 + x as u16 / 8) as usize & 0x1FFF;
+        let tile = self.vram[index];
+        // The attributes are in the same place in bank 1
+        let attributes = if self.cgb { self.vram[0x2000 + index] } else { 0x00 };
+        let address = if self.lcdc & TILE_DATA != 0 {
+            0x8000 + tile as u16 * 16
+        } else {
+            // Tiles 0x80 ..= 0xFF are shared between both modes
+            0x9000u16.wrapping_add((tile as i8 as i16 * 16) as u16)
+        };
+        let (mut column, mut row) = (x % 8, y % 8);
+        if attributes & X_FLIP != 0 {
+            column = 7 - column;
+        }
+        if attributes & Y_FLIP != 0 {
+            row = 7 - row;
+        }
+        let color = tile_pixel(self.tile_bank(attributes & VRAM_BANK != 0), address, column, row);
+        (color, attributes)
+    }
+
+    /// Request the STAT interrupt if one of its sources has just come on
+    fn update_stat_line(&mut self) {
+        let line = self.lcd_enabled() && (
+            (self.stat & LYC_INTERRUPT != 0 && self.ly == self.lyc) ||
+            (self.stat & OAM_INTERRUPT != 0 && self.mode == Mode::OamScan) ||
+            (self.stat & VBLANK_INTERRUPT != 0 && self.mode == Mode::VBlank) ||
+            (self.stat & HBLANK_INTERRUPT != 0 && self.mode == Mode::HBlank));
+        if line && !self.stat_line {
+            self.interrupts |= ::interrupt::LCD_STAT;
         }
+        self.stat_line = line;
     }
-    pub fn write_byte(&mut self, address: u16, val: u8) {}
+
+    fn read_stat(&self) -> u8 {
+        let mut stat = self.stat | self.mode as u8;
+        if self.ly == self.lyc {
+            stat |= COINCIDENCE;
+        }
+        stat
+    }
+
+    fn write_lcdc(&mut self, val: u8) {
+        let was_enabled = self.lcd_enabled();
+        self.lcdc = val;
+        if was_enabled && !self.lcd_enabled() {
+            // Turning the LCD off resets it to the top of the screen
+            self.ly = 0;
+            self.cycles = 0;
+            self.mode = Mode::HBlank;
+        } else if !was_enabled && self.lcd_enabled() {
+            self.mode = Mode::OamScan;
+        }
+    }
+
+    /// Read one of the LCD registers
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            io::LCDC => self.lcdc,
+            io::STAT => self.read_stat(),
+            io::SCY  => self.scy,
+            io::SCX  => self.scx,
+            io::LY   => self.ly,
+            io::LYC  => self.lyc,
+            io::BGP  => self.bgp,
+            io::OBP0 => self.obp0,
+            io::OBP1 => self.obp1,
+            io::WY   => self.wy,
+            io::WX   => self.wx,
+            io::VBK  if self.cgb => self.vram_bank as u8,
+            io::BCPS if self.cgb => self.bcps,
+            io::BCPD if self.cgb => self.bg_palettes[(self.bcps & 0x3F) as usize],
+            io::OCPS if self.cgb => self.ocps,
+            io::OCPD if self.cgb => self.obj_palettes[(self.ocps & 0x3F) as usize],
+            io::OPRI if self.cgb => self.opri,
+            _        => 0xFF,
+        }
+    }
+
+    /// Write one of the LCD registers
+    pub fn write_register(&mut self, address: u16, val: u8) {
+        match address {
+            io::LCDC => self.write_lcdc(val),
+            // The mode and coincidence bits are read-only
+            io::STAT => self.stat = val & 0x78,
+            io::SCY  => self.scy = val,
+            io::SCX  => self.scx = val,
+            io::LYC  => self.lyc = val,
+            io::BGP  => self.bgp = val,
+            io::OBP0 => self.obp0 = val,
+            io::OBP1 => self.obp1 = val,
+            io::WY   => self.wy = val,
+            io::WX   => self.wx = val,
+            io::VBK  if self.cgb => self.vram_bank = (val & 0x01) as usize,
+            io::BCPS if self.cgb => self.bcps = val & 0xBF,
+            io::BCPD if self.cgb => {
+                self.bg_palettes[(self.bcps & 0x3F) as usize] = val;
+                self.bcps = increment_palette_index(self.bcps);
+            },
+            io::OCPS if self.cgb => self.ocps = val & 0xBF,
+            io::OCPD if self.cgb => {
+                self.obj_palettes[(self.ocps & 0x3F) as usize] = val;
+                self.ocps = increment_palette_index(self.ocps);
+            },
+            io::OPRI if self.cgb => self.opri = val & 0x01,
+            // LY can't be written
+            _        => {},
+        }
+        // Changing LYC or the interrupt selects can set off the interrupt
+        self.update_stat_line();
+    }
+}
+
+/// Tiles are 8x8, at two bits per pixel. Each row is two bytes: the low
+/// bits of its eight pixels, and then the high bits.
+fn tile_pixel(vram: &[u8], address: u16, x: u8, y: u8) -> u8 {
+    let row = (address as usize & 0x1FFF) + y as usize * 2;
+    let bit = 7 - x;
+    let low = (vram[row] >> bit) & 1;
+    let high = (vram[row + 1] >> bit) & 1;
+    high << 1 | low
+}
+
+/// Move BCPS or OCPS on to the next byte after a write, if it's set to
+fn increment_palette_index(index: u8) -> u8 {
+    if index & AUTO_INCREMENT != 0 {
+        AUTO_INCREMENT | ((index + 1) & 0x3F)
+    } else {
+        index
+    }
+}
+
+/// Look up a colour number in one of the CGB's eight palettes: two bytes
+/// a colour, little-endian
+fn palette_color(palettes: &[u8; 64], palette: u8, color: u8) -> u16 {
+    let index = palette as usize * 8 + color as usize * 2;
+    (palettes[index] as u16 | (palettes[index + 1] as u16) << 8) & 0x7FFF
+}
+
+/// Look up a colour number in a palette (BGP, OBP0 or OBP1)
+fn shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0x03
+}
+
+#[test]
+fn test_each_line_goes_through_three_modes() {
+    let mut gpu = GPU::new();
+    gpu.write_register(io::LCDC, LCD_ENABLE);
+    assert_eq!(gpu.mode(), Mode::OamScan);
+    gpu.tick(OAM_SCAN_CYCLES);
+    assert_eq!(gpu.mode(), Mode::Transfer);
+    gpu.tick(TRANSFER_CYCLES);
+    assert_eq!(gpu.mode(), Mode::HBlank);
+    assert_eq!(gpu.read_register(io::STAT) & 0x03, 0);
+    gpu.tick(CYCLES_PER_LINE - OAM_SCAN_CYCLES - TRANSFER_CYCLES);
+    assert_eq!(gpu.mode(), Mode::OamScan);
+    assert_eq!(gpu.ly(), 1);
+}
+
+#[test]
+fn test_vblank_comes_after_the_last_visible_line() {
+    let mut gpu = GPU::new();
+    gpu.write_register(io::LCDC, LCD_ENABLE);
+    let interrupts = gpu.tick(CYCLES_PER_LINE * 144);
+    assert_eq!(interrupts, ::interrupt::VBLANK);
+    assert_eq!(gpu.mode(), Mode::VBlank);
+    assert_eq!(gpu.read_register(io::STAT) & 0x03, 1);
+    gpu.tick(CYCLES_PER_LINE * 10);
+    assert_eq!(gpu.ly(), 0);
+    assert_eq!(gpu.mode(), Mode::OamScan);
+}
+
+#[test]
+fn test_stat_interrupt_on_lyc() {
+    let mut gpu = GPU::new();
+    gpu.write_register(io::LCDC, LCD_ENABLE);
+    gpu.write_register(io::LYC, 2);
+    gpu.write_register(io::STAT, LYC_INTERRUPT);
+    assert_eq!(gpu.tick(CYCLES_PER_LINE), 0x00);
+    assert_eq!(gpu.tick(CYCLES_PER_LINE), ::interrupt::LCD_STAT);
+    assert_eq!(gpu.read_register(io::STAT) & COINCIDENCE, COINCIDENCE);
+    // ...only once, while the line stays high
+    assert_eq!(gpu.tick(CYCLES_PER_LINE - 1), 0x00);
+}
+
+#[test]
+fn test_stat_interrupt_on_hblank() {
+    let mut gpu = GPU::new();
+    gpu.write_register(io::LCDC, LCD_ENABLE);
+    gpu.write_register(io::STAT, HBLANK_INTERRUPT);
+    assert_eq!(gpu.tick(OAM_SCAN_CYCLES + TRANSFER_CYCLES - 1), 0x00);
+    assert_eq!(gpu.tick(1), ::interrupt::LCD_STAT);
+}
+
+#[test]
+fn test_turning_the_lcd_off_resets_ly() {
+    let mut gpu = GPU::new();
+    gpu.write_register(io::LCDC, LCD_ENABLE);
+    gpu.tick(CYCLES_PER_LINE * 5);
+    assert_eq!(gpu.read_register(io::LY), 5);
+    gpu.write_register(io::LCDC, 0x00);
+    assert_eq!(gpu.read_register(io::LY), 0);
+    assert_eq!(gpu.tick(CYCLES_PER_LINE * 5), 0x00);
+    assert_eq!(gpu.read_register(io::LY), 0);
+}
+
+#[cfg(test)]
+fn drawing_gpu() -> GPU {
+    let mut gpu = GPU::new();
+    // Tile 1 is a solid colour 3 block, and tile 2 is colour 1 on its
+    // top-left pixel only
+    for i in 0..16 {
+        gpu.vram[0x0010 + i] = 0xFF;
+    }
+    gpu.vram[0x0020] = 0x80;
+    gpu.bgp = 0xE4; // 3, 2, 1, 0
+    gpu
+}
+
+#[cfg(test)]
+fn draw_frame(gpu: &mut GPU) {
+    gpu.write_register(io::LCDC, gpu.lcdc | LCD_ENABLE);
+    gpu.tick(CYCLES_PER_LINE * LINES_PER_FRAME as u32);
+}
+
+#[test]
+fn test_drawing_the_background() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = BG_ENABLE | TILE_DATA;
+    // Tile 1 at the top left of the map, and tile 2 next to it
+    gpu.vram[0x1800] = 1;
+    gpu.vram[0x1801] = 2;
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[0], 3);
+    assert_eq!(gpu.framebuffer()[7 * SCREEN_WIDTH + 7], 3);
+    assert_eq!(gpu.framebuffer()[8], 1);
+    assert_eq!(gpu.framebuffer()[9], 0);
+    assert_eq!(gpu.framebuffer()[8 * SCREEN_WIDTH], 0);
+}
+
+#[test]
+fn test_the_background_scrolls_and_wraps() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = BG_ENABLE | TILE_DATA | BG_MAP;
+    gpu.vram[0x1C00] = 2;
+    gpu.scx = 0xFF;
+    gpu.scy = 0xFF;
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[SCREEN_WIDTH + 1], 1);
+}
+
+#[test]
+fn test_turning_the_dmg_background_off_draws_white() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = TILE_DATA | OBJ_ENABLE;
+    gpu.bgp = 0x1B; // Colour 0 is black
+    gpu.obp0 = 0xE4;
+    gpu.vram[0x1800] = 1;
+    put_sprite(&mut gpu, 0, 8, 0, 1, BEHIND_BG);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[0], 0);
+    assert_eq!(gpu.rgb555_framebuffer()[0], DMG_COLORS[0]);
+    // Sprites still go over it, even ones behind the background
+    assert_eq!(gpu.framebuffer()[8], 3);
+}
+
+#[test]
+fn test_signed_tile_addressing() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = BG_ENABLE;
+    // Tile 0 is at 0x9000 in this mode, and tile 0xFF just before it
+    for i in 0..16 {
+        gpu.vram[0x0FF0 + i] = 0xFF;
+    }
+    gpu.vram[0x1800] = 0xFF;
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[0], 3);
+    assert_eq!(gpu.framebuffer()[8], 0);
+}
+
+#[test]
+fn test_drawing_the_window() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = BG_ENABLE | TILE_DATA | WINDOW_ENABLE | WINDOW_MAP;
+    gpu.vram[0x1C00] = 1;
+    gpu.wx = 7 + 100;
+    gpu.wy = 50;
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[50 * SCREEN_WIDTH + 99], 0);
+    assert_eq!(gpu.framebuffer()[50 * SCREEN_WIDTH + 100], 3);
+    assert_eq!(gpu.framebuffer()[57 * SCREEN_WIDTH + 107], 3);
+    assert_eq!(gpu.framebuffer()[58 * SCREEN_WIDTH + 100], 0);
+    assert_eq!(gpu.framebuffer()[49 * SCREEN_WIDTH + 100], 0);
+}
+
+#[test]
+fn test_the_window_line_only_counts_lines_it_was_drawn_on() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = BG_ENABLE | TILE_DATA | WINDOW_ENABLE | WINDOW_MAP;
+    gpu.vram[0x1C00] = 1;
+    gpu.wx = 7;
+    gpu.write_register(io::LCDC, gpu.lcdc | LCD_ENABLE);
+    gpu.tick(CYCLES_PER_LINE * 4);
+    // Hide the window for a few lines, by moving it off the screen
+    gpu.wx = 200;
+    gpu.tick(CYCLES_PER_LINE * 10);
+    gpu.wx = 7;
+    gpu.tick(CYCLES_PER_LINE * 130);
+    // So it picks up on line 4 of its first row of tiles
+    assert_eq!(gpu.framebuffer()[14 * SCREEN_WIDTH], 3);
+    assert_eq!(gpu.framebuffer()[17 * SCREEN_WIDTH], 3);
+    assert_eq!(gpu.framebuffer()[18 * SCREEN_WIDTH], 0);
+}
+
+#[test]
+fn test_the_background_palette() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = BG_ENABLE | TILE_DATA;
+    gpu.vram[0x1800] = 1;
+    gpu.bgp = 0x00;
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[0], 0);
+    gpu.bgp = 0x40;
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[0], 1);
+}
+
+#[cfg(test)]
+fn put_sprite(gpu: &mut GPU, index: usize, x: u8, y: u8, tile: u8, attributes: u8) {
+    gpu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y + 16, x + 8, tile, attributes]);
+}
+
+#[test]
+fn test_drawing_sprites() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = OBJ_ENABLE;
+    gpu.obp0 = 0xE4;
+    gpu.obp1 = 0x1B; // 0, 1, 2, 3
+    put_sprite(&mut gpu, 0, 10, 20, 1, 0x00);
+    put_sprite(&mut gpu, 1, 30, 20, 1, PALETTE);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 9], 0);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 10], 3);
+    assert_eq!(gpu.framebuffer()[27 * SCREEN_WIDTH + 17], 3);
+    assert_eq!(gpu.framebuffer()[28 * SCREEN_WIDTH + 10], 0);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 30], 0);
+}
+
+#[test]
+fn test_flipping_sprites() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = OBJ_ENABLE;
+    gpu.obp0 = 0xE4;
+    // Tile 2 only has its top left pixel set
+    put_sprite(&mut gpu, 0, 10, 20, 2, X_FLIP | Y_FLIP);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 10], 0);
+    assert_eq!(gpu.framebuffer()[27 * SCREEN_WIDTH + 17], 1);
+}
+
+#[test]
+fn test_tall_sprites() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = OBJ_ENABLE | OBJ_SIZE;
+    gpu.obp0 = 0xE4;
+    // Tiles 2 and 3: the bottom half has nothing in it
+    put_sprite(&mut gpu, 0, 10, 20, 3, Y_FLIP);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 10], 0);
+    assert_eq!(gpu.framebuffer()[35 * SCREEN_WIDTH + 10], 1);
+}
+
+#[test]
+fn test_sprites_behind_the_background() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = BG_ENABLE | TILE_DATA | OBJ_ENABLE;
+    gpu.obp0 = 0x55; // Everything is 1
+    // The background is colour 1 in the top left pixel, and 0 elsewhere
+    gpu.vram[0x1800] = 2;
+    put_sprite(&mut gpu, 0, 0, 0, 1, BEHIND_BG);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[0], 1);
+    assert_eq!(gpu.framebuffer()[1], 1);
+    gpu.bgp = 0x00;
+    draw_frame(&mut gpu);
+    // Hidden by the background, even though that's white now
+    assert_eq!(gpu.framebuffer()[0], 0);
+    assert_eq!(gpu.framebuffer()[1], 1);
+}
+
+#[test]
+fn test_only_ten_sprites_per_line() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = OBJ_ENABLE;
+    gpu.obp0 = 0xE4;
+    for i in 0..11 {
+        put_sprite(&mut gpu, i, i as u8 * 8, 20, 1, 0x00);
+    }
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 72], 3);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 80], 0);
+}
+
+#[test]
+fn test_the_leftmost_sprite_goes_on_top() {
+    let mut gpu = drawing_gpu();
+    gpu.lcdc = OBJ_ENABLE;
+    gpu.obp0 = 0xE4;
+    gpu.obp1 = 0x55;
+    put_sprite(&mut gpu, 0, 12, 20, 1, 0x00);
+    put_sprite(&mut gpu, 1, 10, 20, 1, PALETTE);
+    // Same place: the first in OAM wins
+    put_sprite(&mut gpu, 2, 50, 20, 1, PALETTE);
+    put_sprite(&mut gpu, 3, 50, 20, 1, 0x00);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 12], 1);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 18], 3);
+    assert_eq!(gpu.framebuffer()[20 * SCREEN_WIDTH + 50], 1);
+}
+
+#[test]
+fn test_vram_and_oam_are_locked_while_in_use() {
+    let mut gpu = GPU::new();
+    // Everything's open with the LCD off
+    assert!(gpu.vram_accessible() && gpu.oam_accessible());
+    gpu.write_register(io::LCDC, LCD_ENABLE);
+    assert!(gpu.vram_accessible() && !gpu.oam_accessible());
+    gpu.tick(OAM_SCAN_CYCLES);
+    assert!(!gpu.vram_accessible() && !gpu.oam_accessible());
+    gpu.set_memory_locking(false);
+    assert!(gpu.vram_accessible() && gpu.oam_accessible());
+    gpu.set_memory_locking(true);
+    gpu.tick(TRANSFER_CYCLES);
+    assert!(gpu.vram_accessible() && gpu.oam_accessible());
+}
+
+#[test]
+fn test_palette_ram_auto_increments() {
+    let mut gpu = GPU::new();
+    gpu.set_cgb(true);
+    gpu.write_register(io::BCPS, AUTO_INCREMENT | 0x3F);
+    gpu.write_register(io::BCPD, 0x12);
+    gpu.write_register(io::BCPD, 0x34);
+    // It wraps around
+    assert_eq!(gpu.read_register(io::BCPS), AUTO_INCREMENT | 0x01);
+    gpu.write_register(io::BCPS, 0x3F);
+    assert_eq!(gpu.read_register(io::BCPD), 0x12);
+    assert_eq!(gpu.bg_palettes[0], 0x34);
+    // Without auto-increment, it stays put
+    gpu.write_register(io::OCPS, 0x02);
+    gpu.write_register(io::OCPD, 0x56);
+    assert_eq!(gpu.read_register(io::OCPS), 0x02);
+    assert_eq!(gpu.read_register(io::OCPD), 0x56);
+}
+
+#[cfg(test)]
+fn cgb_drawing_gpu() -> GPU {
+    let mut gpu = drawing_gpu();
+    gpu.set_cgb(true);
+    // Background palette 2, colour 3 is red. Sprite palette 5, colour 3 is
+    // blue.
+    gpu.bg_palettes[2 * 8 + 6..2 * 8 + 8].copy_from_slice(&[0x1F, 0x00]);
+    gpu.obj_palettes[5 * 8 + 6..5 * 8 + 8].copy_from_slice(&[0x00, 0x7C]);
+    gpu
+}
+
+#[test]
+fn test_tile_map_attributes() {
+    let mut gpu = cgb_drawing_gpu();
+    gpu.lcdc = BG_ENABLE | TILE_DATA;
+    // Tile 3 in bank 1 has only its top left pixel set, in colour 3
+    gpu.vram[0x2030] = 0x80;
+    gpu.vram[0x2031] = 0x80;
+    gpu.vram[0x1800] = 3;
+    gpu.vram[0x3800] = VRAM_BANK | X_FLIP | 2;
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.framebuffer()[7], 3);
+    assert_eq!(gpu.rgb555_framebuffer()[7], 0x001F);
+    assert_eq!(gpu.rgb555_framebuffer()[0], 0x7FFF);
+}
+
+#[test]
+fn test_cgb_sprites_go_by_oam_order() {
+    let mut gpu = cgb_drawing_gpu();
+    gpu.lcdc = OBJ_ENABLE;
+    put_sprite(&mut gpu, 0, 12, 20, 1, 5);
+    put_sprite(&mut gpu, 1, 10, 20, 1, 0);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.rgb555_framebuffer()[20 * SCREEN_WIDTH + 12], 0x7C00);
+    assert_eq!(gpu.rgb555_framebuffer()[20 * SCREEN_WIDTH + 10], 0x7FFF);
+    // ...unless OPRI says otherwise
+    gpu.write_register(io::OPRI, 0x01);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.rgb555_framebuffer()[20 * SCREEN_WIDTH + 12], 0x7FFF);
+}
+
+#[test]
+fn test_cgb_background_priority() {
+    let mut gpu = cgb_drawing_gpu();
+    gpu.lcdc = BG_ENABLE | TILE_DATA | OBJ_ENABLE;
+    gpu.vram[0x1800] = 1;
+    gpu.vram[0x3800] = BEHIND_BG | 2;
+    put_sprite(&mut gpu, 0, 0, 0, 1, 5);
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.rgb555_framebuffer()[0], 0x001F);
+    // With BG_ENABLE off, the background is still drawn, but under sprites
+    gpu.lcdc = TILE_DATA | OBJ_ENABLE;
+    draw_frame(&mut gpu);
+    assert_eq!(gpu.rgb555_framebuffer()[0], 0x7C00);
+    assert_eq!(gpu.rgb555_framebuffer()[8], 0x7FFF);
 }
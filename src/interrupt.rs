@@ -0,0 +1,42 @@
+// The five interrupt sources, as they appear as bits in the IE (0xFFFF)
+// and IF (0xFF0F) registers. When more than one is pending, the lowest
+// bit wins.
+pub const VBLANK   : u8 = 0x01;
+pub const LCD_STAT : u8 = 0x02;
+pub const TIMER    : u8 = 0x04;
+pub const SERIAL   : u8 = 0x08;
+pub const JOYPAD   : u8 = 0x10;
+
+pub const ALL      : u8 = 0x1F;
+
+/// The highest-priority interrupt in `interrupts`, if there is one.
+pub fn highest_priority(interrupts: u8) -> Option<u8> {
+    if interrupts & ALL == 0 {
+        None
+    } else {
+        // Isolate the lowest set bit
+        Some(interrupts & interrupts.wrapping_neg())
+    }
+}
+
+/// The address the CPU jumps to when it services `interrupt`:
+/// 0x40, 0x48, 0x50, 0x58 or 0x60.
+pub fn vector(interrupt: u8) -> u16 {
+    0x0040 + 8 * interrupt.trailing_zeros() as u16
+}
+
+#[test]
+fn test_interrupt_priority() {
+    assert_eq!(highest_priority(0x00), None);
+    assert_eq!(highest_priority(TIMER | JOYPAD), Some(TIMER));
+    assert_eq!(highest_priority(VBLANK | JOYPAD), Some(VBLANK));
+}
+
+#[test]
+fn test_interrupt_vectors() {
+    assert_eq!(vector(VBLANK), 0x40);
+    assert_eq!(vector(LCD_STAT), 0x48);
+    assert_eq!(vector(TIMER), 0x50);
+    assert_eq!(vector(SERIAL), 0x58);
+    assert_eq!(vector(JOYPAD), 0x60);
+}
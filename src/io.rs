@@ -0,0 +1,113 @@
+// IO Registers
+// -- ---------
+// 0xFF00 ..= 0xFF7F is where the CPU talks to the rest of the hardware.
+// Each register belongs to one component; the MMU hands reads and writes
+// on to whichever that is.
+
+// Joypad
+pub const P1   : u16 = 0xFF00;
+// Serial
+pub const SB   : u16 = 0xFF01;
+pub const SC   : u16 = 0xFF02;
+// Timer
+pub const DIV  : u16 = 0xFF04;
+pub const TIMA : u16 = 0xFF05;
+pub const TMA  : u16 = 0xFF06;
+pub const TAC  : u16 = 0xFF07;
+// Interrupts
+pub const IF   : u16 = 0xFF0F;
+// Sound, including wave RAM at 0xFF30 ..= 0xFF3F
+pub const NR10 : u16 = 0xFF10;
+pub const NR52 : u16 = 0xFF26;
+pub const WAVE_START : u16 = 0xFF30;
+pub const WAVE_END   : u16 = 0xFF3F;
+// LCD
+pub const LCDC : u16 = 0xFF40;
+pub const STAT : u16 = 0xFF41;
+pub const SCY  : u16 = 0xFF42;
+pub const SCX  : u16 = 0xFF43;
+pub const LY   : u16 = 0xFF44;
+pub const LYC  : u16 = 0xFF45;
+pub const DMA  : u16 = 0xFF46;
+pub const BGP  : u16 = 0xFF47;
+pub const OBP0 : u16 = 0xFF48;
+pub const OBP1 : u16 = 0xFF49;
+pub const WY   : u16 = 0xFF4A;
+pub const WX   : u16 = 0xFF4B;
+// Boot ROM disable
+pub const BOOT : u16 = 0xFF50;
+// Game Boy Color only
+pub const KEY1  : u16 = 0xFF4D; // Speed switch
+pub const VBK   : u16 = 0xFF4F; // VRAM bank
+pub const HDMA1 : u16 = 0xFF51; // VRAM DMA, up to...
+pub const HDMA5 : u16 = 0xFF55;
+pub const RP    : u16 = 0xFF56; // Infrared
+pub const BCPS  : u16 = 0xFF68; // Palettes
+pub const BCPD  : u16 = 0xFF69;
+pub const OCPS  : u16 = 0xFF6A;
+pub const OCPD  : u16 = 0xFF6B;
+pub const OPRI  : u16 = 0xFF6C; // Object priority mode
+pub const SVBK  : u16 = 0xFF70; // WRAM bank
+
+/// The bits of an IO register that don't exist (or can't be read back),
+/// which read as 1. Addresses with no register at all read 0xFF, and so
+/// do the Game Boy Color's registers on anything else.
+pub fn unused_bits(address: u16, cgb: bool) -> u8 {
+    match address {
+        P1   => 0xC0,
+        SB   => 0x00,
+        SC   => if cgb { 0x7C } else { 0x7E },
+        DIV ..= TMA => 0x00,
+        TAC  => 0xF8,
+        IF   => 0xE0,
+        // Sound. Lots of these are write-only, or partly so.
+        0xFF10 => 0x80, // NR10
+        0xFF11 => 0x3F, // NR11
+        0xFF12 => 0x00, // NR12
+        0xFF13 => 0xFF, // NR13
+        0xFF14 => 0xBF, // NR14
+        0xFF16 => 0x3F, // NR21
+        0xFF17 => 0x00, // NR22
+        0xFF18 => 0xFF, // NR23
+        0xFF19 => 0xBF, // NR24
+        0xFF1A => 0x7F, // NR30
+        0xFF1B => 0xFF, // NR31
+        0xFF1C => 0x9F, // NR32
+        0xFF1D => 0xFF, // NR33
+        0xFF1E => 0xBF, // NR34
+        0xFF20 => 0xFF, // NR41
+        0xFF21 => 0x00, // NR42
+        0xFF22 => 0x00, // NR43
+        0xFF23 => 0xBF, // NR44
+        0xFF24 => 0x00, // NR50
+        0xFF25 => 0x00, // NR51
+        NR52 => 0x70,
+        WAVE_START ..= WAVE_END => 0x00,
+        // LCD
+        STAT => 0x80,
+        LCDC | SCY ..= WX => 0x00,
+        BOOT => 0xFE,
+        // Color
+        KEY1 if cgb => 0x7E,
+        VBK if cgb => 0xFE,
+        HDMA5 if cgb => 0x00,
+        RP if cgb => 0x3C,
+        BCPS | OCPS if cgb => 0x40,
+        BCPD | OCPD if cgb => 0x00,
+        OPRI if cgb => 0xFE,
+        SVBK if cgb => 0xF8,
+        // Nothing here (or write-only, like HDMA1 ..= HDMA4)
+        _ => 0xFF,
+    }
+}
+
+#[test]
+fn test_unused_bits_read_as_1() {
+    assert_eq!(unused_bits(P1, false), 0xC0);
+    assert_eq!(unused_bits(IF, false), 0xE0);
+    assert_eq!(unused_bits(0xFF03, false), 0xFF);
+    assert_eq!(unused_bits(HDMA1, true), 0xFF);
+    // Color registers aren't there on the original Game Boy
+    assert_eq!(unused_bits(SVBK, false), 0xFF);
+    assert_eq!(unused_bits(SVBK, true), 0xF8);
+}
@@ -0,0 +1,171 @@
+// The Joypad
+// --- ------
+// The eight buttons are wired up as a 2x4 matrix. The game picks a row
+// (the directions, or the action buttons) with bits 4 and 5 of P1, and
+// reads that row's buttons from the bottom four bits. Everything is active
+// low: 0 means selected, or pressed.
+
+use io;
+
+// P1
+const SELECT_ACTIONS    : u8 = 0x20;
+const SELECT_DIRECTIONS : u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// The button's bit in `Joypad::set_state`: the directions in the low
+    /// nibble, and the action buttons in the high one, each in the order
+    /// they appear in P1.
+    pub fn bit(self) -> u8 {
+        match self {
+            Button::Right  => 0x01,
+            Button::Left   => 0x02,
+            Button::Up     => 0x04,
+            Button::Down   => 0x08,
+            Button::A      => 0x10,
+            Button::B      => 0x20,
+            Button::Select => 0x40,
+            Button::Start  => 0x80,
+        }
+    }
+}
+
+pub struct Joypad {
+    pressed: u8, // One bit per button, as in `Button::bit`. 1 is pressed.
+    select: u8,  // Bits 4 and 5 of P1
+    // Set when one of P1's input lines goes from high to low, until the
+    // MMU picks it up
+    interrupt: bool,
+}
+
+impl Default for Joypad {
+    fn default() -> Joypad {
+        Joypad::new()
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            pressed: 0x00,
+            select: SELECT_ACTIONS | SELECT_DIRECTIONS,
+            interrupt: false,
+        }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        let pressed = self.pressed | button.bit();
+        self.set_state(pressed);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        let pressed = self.pressed & !button.bit();
+        self.set_state(pressed);
+    }
+
+    /// Set every button at once, one bit each as in `Button::bit`, with 1
+    /// for pressed.
+    pub fn set_state(&mut self, pressed: u8) {
+        let before = self.lines();
+        self.pressed = pressed;
+        self.update_interrupt(before);
+    }
+
+    pub fn state(&self) -> u8 {
+        self.pressed
+    }
+
+    /// Whether the joypad interrupt should be requested. Clears it.
+    pub fn take_interrupt(&mut self) -> bool {
+        let interrupt = self.interrupt;
+        self.interrupt = false;
+        interrupt
+    }
+
+    /// The bottom four bits of P1: the buttons in the selected rows, with
+    /// 0 for pressed
+    fn lines(&self) -> u8 {
+        let mut pressed = 0x00;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pressed |= self.pressed & 0x0F;
+        }
+        if self.select & SELECT_ACTIONS == 0 {
+            pressed |= self.pressed >> 4;
+        }
+        !pressed & 0x0F
+    }
+
+    fn update_interrupt(&mut self, before: u8) {
+        if before & !self.lines() != 0 {
+            self.interrupt = true;
+        }
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            io::P1 => self.select | self.lines(),
+            _      => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, val: u8) {
+        if address == io::P1 {
+            // Selecting a row with a button held down counts, too
+            let before = self.lines();
+            self.select = val & (SELECT_ACTIONS | SELECT_DIRECTIONS);
+            self.update_interrupt(before);
+        }
+    }
+}
+
+#[test]
+fn test_reading_the_selected_buttons() {
+    let mut joypad = Joypad::new();
+    joypad.press(Button::Down);
+    joypad.press(Button::A);
+    assert_eq!(joypad.read_register(io::P1), 0x3F);
+    joypad.write_register(io::P1, SELECT_ACTIONS);
+    assert_eq!(joypad.read_register(io::P1), 0x27);
+    joypad.write_register(io::P1, SELECT_DIRECTIONS);
+    assert_eq!(joypad.read_register(io::P1), 0x1E);
+    joypad.write_register(io::P1, 0x00);
+    assert_eq!(joypad.read_register(io::P1), 0x06);
+    joypad.release(Button::Down);
+    assert_eq!(joypad.read_register(io::P1), 0x0E);
+}
+
+#[test]
+fn test_setting_the_whole_state() {
+    let mut joypad = Joypad::new();
+    joypad.set_state(Button::Start.bit() | Button::Left.bit());
+    joypad.write_register(io::P1, SELECT_DIRECTIONS);
+    assert_eq!(joypad.read_register(io::P1), 0x17);
+    assert_eq!(joypad.state(), 0x82);
+}
+
+#[test]
+fn test_pressing_a_selected_button_interrupts() {
+    let mut joypad = Joypad::new();
+    // Nothing's selected, so nothing happens
+    joypad.press(Button::B);
+    assert!(!joypad.take_interrupt());
+    joypad.write_register(io::P1, SELECT_DIRECTIONS);
+    assert!(joypad.take_interrupt());
+    assert!(!joypad.take_interrupt());
+    // Releasing doesn't count
+    joypad.release(Button::B);
+    assert!(!joypad.take_interrupt());
+    joypad.press(Button::Start);
+    assert!(joypad.take_interrupt());
+}
@@ -1,3 +1,13 @@
 pub mod cpu;
 pub mod mmu;
 pub mod gpu;
+pub mod interrupt;
+pub mod io;
+pub mod timer;
+pub mod joypad;
+pub mod apu;
+pub mod serial;
+pub mod png;
+pub mod boot;
+pub mod cartridge;
+pub mod mbc;
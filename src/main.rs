@@ -1,21 +1,91 @@
 extern crate gb;
 
+use std::env;
+use std::process;
+
+use gb::serial::{CaptureLink, FileLink, LoopbackLink, SerialLink};
+use gb::serial::printer::Printer;
+use gb::serial::tcp::TcpLink;
+
+fn usage() -> ! {
+    eprintln!("usage: gb [--boot-rom <boot.bin>] [--serial-stdout | --serial-file <path> | \
+               --serial-loopback | --printer <directory> | --link-listen <address> | \
+               --link-connect <address>] <rom.gb>");
+    process::exit(1);
+}
+
 fn main() {
-    // let mut cpu = gb::cpu::Z80::new();
-    /*
+    let mut args = env::args().skip(1);
+    let mut boot_rom_path = None;
+    let mut rom_path = None;
+    let mut link : Option<Box<dyn SerialLink>> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--boot-rom" => boot_rom_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--serial-stdout" => link = Some(Box::new(CaptureLink::stdout())),
+            "--serial-file" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                match FileLink::create(&path) {
+                    Ok(file) => link = Some(Box::new(file)),
+                    Err(e) => {
+                        eprintln!("{}: {}", path, e);
+                        process::exit(1);
+                    }
+                }
+            },
+            "--serial-loopback" => link = Some(Box::new(LoopbackLink)),
+            "--printer" => {
+                let directory = args.next().unwrap_or_else(|| usage());
+                link = Some(Box::new(Printer::new(directory)));
+            },
+            "--link-listen" | "--link-connect" => {
+                let address = args.next().unwrap_or_else(|| usage());
+                let tcp = if arg == "--link-listen" {
+                    eprintln!("waiting for another emulator on {}", address);
+                    TcpLink::listen(&address)
+                } else {
+                    TcpLink::connect(&address)
+                };
+                match tcp {
+                    Ok(tcp) => link = Some(Box::new(tcp)),
+                    Err(e) => {
+                        eprintln!("{}: {}", address, e);
+                        process::exit(1);
+                    }
+                }
+            },
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| usage());
+    let cartridge = match gb::cartridge::Cartridge::open(&rom_path) {
+        Ok(cartridge) => cartridge,
+        Err(e) => {
+            eprintln!("{}: {}", rom_path, e);
+            process::exit(1);
+        }
+    };
+
     // Construct a CPU
+    let mut cpu = gb::cpu::Z80::new();
+    cpu.load_cartridge(cartridge);
+    match boot_rom_path {
+        Some(path) => {
+            if let Err(e) = cpu.load_boot_rom(&path) {
+                eprintln!("{}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None if cpu.is_cgb() => cpu.skip_boot(gb::boot::Model::Cgb),
+        None => cpu.skip_boot(gb::boot::Model::Dmg),
+    }
+    if let Some(link) = link {
+        cpu.set_serial_link(link);
+    }
 
     loop {
-        // Read an instruction from memory
-        let op = CPU._mmu.rb(CPU._r.pc);
-        // Execute
-        CPU.call(op);
-        CPU._r.pc += 1;
-        // Keep track of how much time the instruction took
-        CPU._clock.m += CPU._r.m;
-        CPU._clock.t += CPU._r.t;
-        // Keep track of how long it took to redraw the screen
-        // GPU.step();
+        // Run instructions for as long as it takes to draw one screen
+        cpu.run_frame();
     }
-    */
 }
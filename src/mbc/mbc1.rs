@@ -0,0 +1,158 @@
+use mbc::{MemoryBankController, rom_byte, ram_offset};
+
+/// MBC1: up to 2 MiB of ROM and 32 KiB of RAM.
+pub struct Mbc1 {
+    ram_enabled: bool,
+    // The low five bits of the ROM bank number. Writing 0 here selects
+    // bank 1 instead, so 0x20, 0x40 and 0x60 can't be selected at
+    // 0x4000 ..= 0x7FFF either.
+    bank1: u8,
+    // Two more bits: either the top of the ROM bank number, or the RAM
+    // bank number, depending on the mode
+    bank2: u8,
+    // In mode 1, `bank2` also switches the bank at 0x0000 ..= 0x3FFF and
+    // the RAM bank. In mode 0, both are fixed at bank 0.
+    advanced_banking: bool,
+}
+
+impl Default for Mbc1 {
+    fn default() -> Mbc1 {
+        Mbc1::new()
+    }
+}
+
+impl Mbc1 {
+    pub fn new() -> Mbc1 {
+        Mbc1 {
+            ram_enabled: false,
+            bank1: 0x01,
+            bank2: 0x00,
+            advanced_banking: false,
+        }
+    }
+
+    fn low_rom_bank(&self) -> usize {
+        if self.advanced_banking { (self.bank2 as usize) << 5 } else { 0 }
+    }
+
+    fn high_rom_bank(&self) -> usize {
+        (self.bank2 as usize) << 5 | self.bank1 as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.advanced_banking { self.bank2 as usize } else { 0 }
+    }
+}
+
+impl MemoryBankController for Mbc1 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000 ..= 0x3FFF => rom_byte(rom, self.low_rom_bank(), address),
+            _                 => rom_byte(rom, self.high_rom_bank(), address),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, val: u8) {
+        match address {
+            // Any value with 0xA in the low nibble enables RAM
+            0x0000 ..= 0x1FFF => self.ram_enabled = val & 0x0F == 0x0A,
+            0x2000 ..= 0x3FFF => {
+                self.bank1 = val & 0x1F;
+                if self.bank1 == 0 { self.bank1 = 1 }
+            },
+            0x4000 ..= 0x5FFF => self.bank2 = val & 0x03,
+            _                 => self.advanced_banking = val & 0x01 == 0x01,
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match ram_offset(ram, self.ram_bank(), address) {
+            Some(offset) => ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, val: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        match ram_offset(ram, self.ram_bank(), address) {
+            Some(offset) => {
+                ram[offset] = val;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[test]
+fn test_mbc1_switches_rom_banks() {
+    let rom = ::mbc::banked_rom(128);
+    let mut mbc = Mbc1::new();
+    assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    mbc.write_rom(0x2000, 0x05);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 5);
+    // Only five bits are used
+    mbc.write_rom(0x2000, 0xE7);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 7);
+    // The upper two bits come from 0x4000 ..= 0x5FFF
+    mbc.write_rom(0x4000, 0x02);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 0x47);
+    // ...and only switch the low bank in mode 1
+    assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+    mbc.write_rom(0x6000, 0x01);
+    assert_eq!(mbc.read_rom(&rom, 0x0000), 0x40);
+}
+
+#[test]
+fn test_mbc1_maps_bank_0_to_bank_1() {
+    let rom = ::mbc::banked_rom(128);
+    let mut mbc = Mbc1::new();
+    mbc.write_rom(0x2000, 0x00);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    // The same goes for 0x20, 0x40 and 0x60
+    mbc.write_rom(0x4000, 0x01);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 0x21);
+}
+
+#[test]
+fn test_mbc1_wraps_banks_past_the_end_of_rom() {
+    let rom = ::mbc::banked_rom(4);
+    let mut mbc = Mbc1::new();
+    mbc.write_rom(0x2000, 0x06);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 2);
+}
+
+#[test]
+fn test_mbc1_ram_must_be_enabled() {
+    let mut ram = vec![0; 0x2000];
+    let mut mbc = Mbc1::new();
+    mbc.write_ram(&mut ram, 0xA000, 0x42);
+    assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+    mbc.write_rom(0x0000, 0x0A);
+    mbc.write_ram(&mut ram, 0xA000, 0x42);
+    assert_eq!(mbc.read_ram(&ram, 0xA000), 0x42);
+    mbc.write_rom(0x0000, 0x00);
+    assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+}
+
+#[test]
+fn test_mbc1_switches_ram_banks_in_mode_1() {
+    let mut ram = vec![0; 0x8000];
+    let mut mbc = Mbc1::new();
+    mbc.write_rom(0x0000, 0x0A);
+    mbc.write_ram(&mut ram, 0xA000, 0x01);
+    mbc.write_rom(0x4000, 0x02);
+    // Still bank 0 in mode 0
+    assert_eq!(mbc.read_ram(&ram, 0xA000), 0x01);
+    mbc.write_rom(0x6000, 0x01);
+    mbc.write_ram(&mut ram, 0xA000, 0x02);
+    assert_eq!(ram[0x4000], 0x02);
+    mbc.write_rom(0x6000, 0x00);
+    assert_eq!(mbc.read_ram(&ram, 0xA000), 0x01);
+}
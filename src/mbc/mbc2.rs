@@ -0,0 +1,97 @@
+use mbc::{MemoryBankController, rom_byte};
+
+/// The MBC2 has 512 half-bytes of RAM built in
+pub const RAM_SIZE : usize = 0x0200;
+
+/// MBC2: up to 256 KiB of ROM, and its own 512 x 4 bits of RAM.
+pub struct Mbc2 {
+    ram_enabled: bool,
+    rom_bank: u8, // 4 bits. 0 selects bank 1.
+}
+
+impl Default for Mbc2 {
+    fn default() -> Mbc2 {
+        Mbc2::new()
+    }
+}
+
+impl Mbc2 {
+    pub fn new() -> Mbc2 {
+        Mbc2 {
+            ram_enabled: false,
+            rom_bank: 0x01,
+        }
+    }
+}
+
+impl MemoryBankController for Mbc2 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000 ..= 0x3FFF => rom_byte(rom, 0, address),
+            _                 => rom_byte(rom, self.rom_bank as usize, address),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, val: u8) {
+        // Both registers live in 0x0000 ..= 0x3FFF. Bit 8 of the address
+        // picks which one is written.
+        match address {
+            0x0000 ..= 0x3FFF if address & 0x0100 == 0 => {
+                self.ram_enabled = val & 0x0F == 0x0A
+            },
+            0x0000 ..= 0x3FFF => {
+                self.rom_bank = val & 0x0F;
+                if self.rom_bank == 0 { self.rom_bank = 1 }
+            },
+            _ => {},
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled || ram.is_empty() {
+            return 0xFF;
+        }
+        // Only the low nibble is there; the top half of the data bus
+        // floats high. The 512 bytes repeat all the way up to 0xBFFF.
+        ram[address as usize & (RAM_SIZE - 1)] | 0xF0
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, val: u8) -> bool {
+        if !self.ram_enabled || ram.is_empty() {
+            return false;
+        }
+        ram[address as usize & (RAM_SIZE - 1)] = val & 0x0F;
+        true
+    }
+}
+
+#[test]
+fn test_mbc2_selects_registers_with_address_bit_8() {
+    let rom = ::mbc::banked_rom(16);
+    let mut ram = vec![0; RAM_SIZE];
+    let mut mbc = Mbc2::new();
+    // Bit 8 clear: RAM enable, which doesn't touch the ROM bank
+    mbc.write_rom(0x0000, 0x0A);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    mbc.write_ram(&mut ram, 0xA000, 0x05);
+    assert_eq!(mbc.read_ram(&ram, 0xA000), 0xF5);
+    // Bit 8 set: ROM bank
+    mbc.write_rom(0x2100, 0x03);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 3);
+    mbc.write_rom(0x0100, 0x00);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    assert_eq!(mbc.read_ram(&ram, 0xA000), 0xF5);
+}
+
+#[test]
+fn test_mbc2_ram_is_4_bits_wide_and_repeats() {
+    let mut ram = vec![0; RAM_SIZE];
+    let mut mbc = Mbc2::new();
+    mbc.write_rom(0x0000, 0x0A);
+    mbc.write_ram(&mut ram, 0xA1FF, 0xAB);
+    assert_eq!(ram[0x1FF], 0x0B);
+    assert_eq!(mbc.read_ram(&ram, 0xA1FF), 0xFB);
+    assert_eq!(mbc.read_ram(&ram, 0xBFFF), 0xFB);
+    mbc.write_rom(0x0000, 0x00);
+    assert_eq!(mbc.read_ram(&ram, 0xA1FF), 0xFF);
+}
@@ -0,0 +1,515 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use mbc::{MemoryBankController, rom_byte, ram_offset};
+
+/// The CPU runs at 4194304 Hz
+const CYCLES_PER_SECOND : u32 = 4_194_304;
+
+// RTC registers, selected by writing their number to 0x4000 ..= 0x5FFF
+const RTC_SECONDS   : u8 = 0x08;
+const RTC_MINUTES   : u8 = 0x09;
+const RTC_HOURS     : u8 = 0x0A;
+const RTC_DAYS_LOW  : u8 = 0x0B;
+const RTC_DAYS_HIGH : u8 = 0x0C;
+
+/// The size of the clock state other emulators put after the RAM in a
+/// .sav file: the live and latched registers as five 32-bit words each,
+/// and then a 64-bit UNIX timestamp, all little-endian.
+pub const RTC_FOOTER_SIZE : usize = 48;
+
+// Bits in the upper day counter register
+const DAY_HIGH  : u8 = 0x01; // Bit 8 of the day counter
+const HALT      : u8 = 0x40;
+const DAY_CARRY : u8 = 0x80; // The day counter went past 511
+
+/// Where the real-time clock gets its time from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RtcClock {
+    /// Counts CPU cycles, so it runs as fast as the emulator does (and
+    /// stops when it's paused).
+    Emulated,
+    /// Follows the host's wall clock, the way a battery-backed clock keeps
+    /// going while the Game Boy is switched off.
+    Host,
+}
+
+/// Count one of the clock's registers up `n` times, returning its new value
+/// and how many times it carried into the next one. It carries from
+/// `limit - 1` back to 0. A game can write something out of range, which
+/// then counts up to `mask` and wraps to 0 without carrying.
+fn count_up(value: u8, n: u64, limit: u8, mask: u8) -> (u8, u64) {
+    let mut value = value as u64;
+    let mut n = n;
+    if value >= limit as u64 {
+        let to_wrap = mask as u64 + 1 - value;
+        if n < to_wrap {
+            return ((value + n) as u8, 0);
+        }
+        n -= to_wrap;
+        value = 0;
+    }
+    let total = value + n;
+    ((total % limit as u64) as u8, total / limit as u64)
+}
+
+/// The MBC3's real-time clock. It keeps going on its own; the game reads
+/// a latched copy of it.
+pub struct Rtc {
+    pub clock: RtcClock,
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16, // 9 bits
+    halted: bool,
+    day_carry: bool,
+    // A snapshot of the registers, taken by writing 0x00 and then 0x01
+    // to 0x6000 ..= 0x7FFF. This is what the game actually reads.
+    latched: [u8; 5],
+    // Cycles since the last whole second, in emulated mode
+    cycles: u32,
+    // When the clock was last brought up to date, in host mode
+    last_sync: SystemTime,
+}
+
+impl Rtc {
+    pub fn new(clock: RtcClock) -> Rtc {
+        Rtc {
+            clock,
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halted: false,
+            day_carry: false,
+            latched: [0; 5],
+            cycles: 0,
+            last_sync: SystemTime::now(),
+        }
+    }
+
+    /// Let `cycles` of emulated time pass.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.clock != RtcClock::Emulated || self.halted {
+            return;
+        }
+        self.cycles += cycles;
+        let seconds = self.cycles / CYCLES_PER_SECOND;
+        self.cycles %= CYCLES_PER_SECOND;
+        self.advance(seconds as u64);
+    }
+
+    /// Catch up with the host's clock, as of `now`.
+    pub fn sync_to(&mut self, now: SystemTime) {
+        if self.clock != RtcClock::Host {
+            return;
+        }
+        // If the host clock goes backwards, wait for it to catch up
+        let elapsed = match now.duration_since(self.last_sync) {
+            Ok(elapsed) => elapsed.as_secs(),
+            Err(_) => return,
+        };
+        self.last_sync += Duration::from_secs(elapsed);
+        if !self.halted {
+            self.advance(elapsed);
+        }
+    }
+
+    fn sync(&mut self) {
+        self.sync_to(SystemTime::now());
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        let (seconds, minutes) = count_up(self.seconds, seconds, 60, 0x3F);
+        let (minutes, hours) = count_up(self.minutes, minutes, 60, 0x3F);
+        let (hours, days) = count_up(self.hours, hours, 24, 0x1F);
+        self.seconds = seconds;
+        self.minutes = minutes;
+        self.hours = hours;
+        let days = self.days as u64 + days;
+        if days > 0x1FF {
+            // The carry bit stays set until the game clears it
+            self.day_carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    fn days_high(&self) -> u8 {
+        let mut val = (self.days >> 8) as u8 & DAY_HIGH;
+        if self.halted { val |= HALT }
+        if self.day_carry { val |= DAY_CARRY }
+        val
+    }
+
+    /// Copy the live registers into the ones the game can read.
+    pub fn latch(&mut self) {
+        self.sync();
+        self.latched = self.registers();
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        [self.seconds, self.minutes, self.hours, self.days as u8, self.days_high()]
+    }
+
+    /// The clock's state, in the format of a .sav file's footer
+    pub fn to_footer(&mut self) -> [u8; RTC_FOOTER_SIZE] {
+        self.sync();
+        let mut footer = [0; RTC_FOOTER_SIZE];
+        for (i, &word) in self.registers().iter().chain(self.latched.iter()).enumerate() {
+            footer[i * 4] = word;
+        }
+        let timestamp = match self.last_sync.duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch.as_secs(),
+            Err(_) => 0,
+        };
+        for i in 0..8 {
+            footer[40 + i] = (timestamp >> (i * 8)) as u8;
+        }
+        footer
+    }
+
+    /// Restore the clock from a .sav file's footer. Following the host's
+    /// clock, it then catches up on the time that passed since the save.
+    /// Some emulators write a 32-bit timestamp, for a 44-byte footer;
+    /// that's fine too.
+    pub fn load_footer(&mut self, footer: &[u8]) {
+        if footer.len() < 44 {
+            return;
+        }
+        let word = |i: usize| footer[i * 4];
+        self.seconds = word(0) & 0x3F;
+        self.minutes = word(1) & 0x3F;
+        self.hours = word(2) & 0x1F;
+        self.days = word(3) as u16 | ((word(4) & DAY_HIGH) as u16) << 8;
+        self.halted = word(4) & HALT != 0;
+        self.day_carry = word(4) & DAY_CARRY != 0;
+        for i in 0..5 {
+            self.latched[i] = word(5 + i);
+        }
+        self.cycles = 0;
+        let timestamp = footer[40..].iter().take(8).enumerate()
+            .fold(0u64, |t, (i, &b)| t | (b as u64) << (i * 8));
+        if self.clock == RtcClock::Host {
+            self.last_sync = UNIX_EPOCH + Duration::from_secs(timestamp);
+            self.sync();
+        }
+    }
+
+    /// Read one of the latched registers, 0x08 ..= 0x0C
+    fn read(&self, register: u8) -> u8 {
+        let index = register.wrapping_sub(RTC_SECONDS) as usize;
+        self.latched.get(index).cloned().unwrap_or(0xFF)
+    }
+
+    /// Set one of the live registers, 0x08 ..= 0x0C
+    pub fn write(&mut self, register: u8, val: u8) {
+        self.sync();
+        match register {
+            RTC_SECONDS => {
+                self.seconds = val & 0x3F;
+                // Writing the seconds restarts the current second
+                self.cycles = 0;
+            },
+            RTC_MINUTES  => self.minutes = val & 0x3F,
+            RTC_HOURS    => self.hours = val & 0x1F,
+            RTC_DAYS_LOW => self.days = (self.days & 0x100) | val as u16,
+            _ => {
+                self.days = (self.days & 0xFF) | ((val & DAY_HIGH) as u16) << 8;
+                self.halted = val & HALT != 0;
+                self.day_carry = val & DAY_CARRY != 0;
+            },
+        }
+    }
+}
+
+/// MBC3: up to 2 MiB of ROM, 32 KiB of RAM, and sometimes a real-time
+/// clock.
+pub struct Mbc3 {
+    // Enables the RTC registers as well as RAM
+    ram_enabled: bool,
+    rom_bank: u8, // 7 bits. 0 selects bank 1, like on the MBC1.
+    // 0x00 ..= 0x03 select a RAM bank, 0x08 ..= 0x0C an RTC register
+    ram_select: u8,
+    // The last value written to 0x6000 ..= 0x7FFF. The clock latches on
+    // a 0 followed by a 1.
+    latch: u8,
+    rtc: Option<Rtc>,
+}
+
+impl Default for Mbc3 {
+    fn default() -> Mbc3 {
+        Mbc3::new()
+    }
+}
+
+impl Mbc3 {
+    /// An MBC3 without a clock
+    pub fn new() -> Mbc3 {
+        Mbc3 {
+            ram_enabled: false,
+            rom_bank: 0x01,
+            ram_select: 0x00,
+            latch: 0xFF,
+            rtc: None,
+        }
+    }
+
+    /// An MBC3 with a real-time clock, as on cartridge types 0x0F and 0x10
+    pub fn with_rtc(clock: RtcClock) -> Mbc3 {
+        Mbc3 { rtc: Some(Rtc::new(clock)), ..Mbc3::new() }
+    }
+}
+
+impl MemoryBankController for Mbc3 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000 ..= 0x3FFF => rom_byte(rom, 0, address),
+            _                 => rom_byte(rom, self.rom_bank as usize, address),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, val: u8) {
+        match address {
+            0x0000 ..= 0x1FFF => self.ram_enabled = val & 0x0F == 0x0A,
+            0x2000 ..= 0x3FFF => {
+                self.rom_bank = val & 0x7F;
+                if self.rom_bank == 0 { self.rom_bank = 1 }
+            },
+            0x4000 ..= 0x5FFF => self.ram_select = val,
+            _ => {
+                if self.latch == 0x00 && val == 0x01 {
+                    if let Some(ref mut rtc) = self.rtc {
+                        rtc.latch();
+                    }
+                }
+                self.latch = val;
+            },
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match self.ram_select {
+            0x00 ..= 0x03 => match ram_offset(ram, self.ram_select as usize, address) {
+                Some(offset) => ram[offset],
+                None => 0xFF,
+            },
+            RTC_SECONDS ..= RTC_DAYS_HIGH => match self.rtc {
+                Some(ref rtc) => rtc.read(self.ram_select),
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, val: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        match self.ram_select {
+            0x00 ..= 0x03 => match ram_offset(ram, self.ram_select as usize, address) {
+                Some(offset) => {
+                    ram[offset] = val;
+                    true
+                },
+                None => false,
+            },
+            // The clock is saved along with RAM
+            RTC_SECONDS ..= RTC_DAYS_HIGH => match self.rtc {
+                Some(ref mut rtc) => {
+                    rtc.write(self.ram_select, val);
+                    true
+                },
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if let Some(ref mut rtc) = self.rtc {
+            rtc.tick(cycles);
+        }
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+}
+
+#[cfg(test)]
+fn read_rtc(mbc: &mut Mbc3, register: u8) -> u8 {
+    mbc.write_rom(0x6000, 0x00);
+    mbc.write_rom(0x6000, 0x01);
+    mbc.write_rom(0x4000, register);
+    mbc.read_ram(&[], 0xA000)
+}
+
+#[test]
+fn test_mbc3_switches_rom_banks() {
+    let rom = ::mbc::banked_rom(128);
+    let mut mbc = Mbc3::new();
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    // All seven bits are used, so there's no gap at 0x20, 0x40 and 0x60
+    mbc.write_rom(0x2000, 0x20);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 0x20);
+    mbc.write_rom(0x2000, 0xFF);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 0x7F);
+    mbc.write_rom(0x2000, 0x00);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+}
+
+#[test]
+fn test_mbc3_switches_ram_banks() {
+    let mut ram = vec![0; 0x8000];
+    let mut mbc = Mbc3::new();
+    mbc.write_rom(0x0000, 0x0A);
+    mbc.write_rom(0x4000, 0x03);
+    mbc.write_ram(&mut ram, 0xA001, 0x42);
+    assert_eq!(ram[0x6001], 0x42);
+    mbc.write_rom(0x4000, 0x00);
+    assert_eq!(mbc.read_ram(&ram, 0xA001), 0x00);
+}
+
+#[test]
+fn test_mbc3_rtc_only_changes_when_latched() {
+    let mut mbc = Mbc3::with_rtc(RtcClock::Emulated);
+    mbc.write_rom(0x0000, 0x0A);
+    mbc.tick(CYCLES_PER_SECOND * 5);
+    mbc.write_rom(0x4000, RTC_SECONDS);
+    assert_eq!(mbc.read_ram(&[], 0xA000), 0);
+    // Writing 1 without a 0 first doesn't latch
+    mbc.write_rom(0x6000, 0x01);
+    assert_eq!(mbc.read_ram(&[], 0xA000), 0);
+    assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 5);
+}
+
+#[test]
+fn test_mbc3_rtc_counts_up_to_days() {
+    let mut mbc = Mbc3::with_rtc(RtcClock::Emulated);
+    mbc.write_rom(0x0000, 0x0A);
+    // 1 day, 2 hours, 3 minutes and 4 seconds
+    for _ in 0..(86400 + 2 * 3600 + 3 * 60 + 4) {
+        mbc.tick(CYCLES_PER_SECOND);
+    }
+    assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 4);
+    assert_eq!(read_rtc(&mut mbc, RTC_MINUTES), 3);
+    assert_eq!(read_rtc(&mut mbc, RTC_HOURS), 2);
+    assert_eq!(read_rtc(&mut mbc, RTC_DAYS_LOW), 1);
+    assert_eq!(read_rtc(&mut mbc, RTC_DAYS_HIGH), 0);
+}
+
+#[test]
+fn test_mbc3_rtc_sets_the_day_carry() {
+    let mut mbc = Mbc3::with_rtc(RtcClock::Emulated);
+    mbc.write_rom(0x0000, 0x0A);
+    // Day 511, 23:59:59
+    mbc.write_rom(0x4000, RTC_DAYS_HIGH);
+    mbc.write_ram(&mut [], 0xA000, DAY_HIGH);
+    mbc.write_rom(0x4000, RTC_DAYS_LOW);
+    mbc.write_ram(&mut [], 0xA000, 0xFF);
+    mbc.write_rom(0x4000, RTC_HOURS);
+    mbc.write_ram(&mut [], 0xA000, 23);
+    mbc.write_rom(0x4000, RTC_MINUTES);
+    mbc.write_ram(&mut [], 0xA000, 59);
+    mbc.write_rom(0x4000, RTC_SECONDS);
+    mbc.write_ram(&mut [], 0xA000, 59);
+    assert_eq!(read_rtc(&mut mbc, RTC_DAYS_HIGH), DAY_HIGH);
+    mbc.tick(CYCLES_PER_SECOND);
+    assert_eq!(read_rtc(&mut mbc, RTC_DAYS_LOW), 0);
+    assert_eq!(read_rtc(&mut mbc, RTC_DAYS_HIGH), DAY_CARRY);
+}
+
+#[test]
+fn test_mbc3_rtc_stops_when_halted() {
+    let mut mbc = Mbc3::with_rtc(RtcClock::Emulated);
+    mbc.write_rom(0x0000, 0x0A);
+    mbc.write_rom(0x4000, RTC_DAYS_HIGH);
+    mbc.write_ram(&mut [], 0xA000, HALT);
+    mbc.tick(CYCLES_PER_SECOND * 10);
+    assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 0);
+    assert_eq!(read_rtc(&mut mbc, RTC_DAYS_HIGH), HALT);
+}
+
+#[test]
+fn test_rtc_can_follow_the_host_clock() {
+    let mut rtc = Rtc::new(RtcClock::Host);
+    let start = rtc.last_sync;
+    // Emulated time doesn't count
+    rtc.tick(CYCLES_PER_SECOND * 10);
+    rtc.sync_to(start + Duration::from_millis(61_500));
+    rtc.latch();
+    assert_eq!(rtc.read(RTC_SECONDS), 1);
+    assert_eq!(rtc.read(RTC_MINUTES), 1);
+}
+
+#[test]
+fn test_rtc_round_trips_through_a_save_footer() {
+    let mut rtc = Rtc::new(RtcClock::Emulated);
+    rtc.write(RTC_HOURS, 13);
+    rtc.write(RTC_DAYS_HIGH, DAY_CARRY | DAY_HIGH);
+    rtc.latch();
+    rtc.write(RTC_MINUTES, 7);
+    let footer = rtc.to_footer();
+    assert_eq!(footer[4 * 4], DAY_CARRY | DAY_HIGH);
+
+    let mut loaded = Rtc::new(RtcClock::Emulated);
+    loaded.load_footer(&footer);
+    assert_eq!(loaded.registers(), [0, 7, 13, 0, DAY_CARRY | DAY_HIGH]);
+    assert_eq!(loaded.read(RTC_MINUTES), 0);
+    assert_eq!(loaded.read(RTC_HOURS), 13);
+}
+
+#[test]
+fn test_rtc_catches_up_on_time_spent_switched_off() {
+    let mut rtc = Rtc::new(RtcClock::Host);
+    let mut footer = rtc.to_footer();
+    // Saved an hour and a minute ago
+    let timestamp = footer[40..].iter().enumerate()
+        .fold(0u64, |t, (i, &b)| t | (b as u64) << (i * 8)) - 3660;
+    for i in 0..8 {
+        footer[40 + i] = (timestamp >> (i * 8)) as u8;
+    }
+    let mut loaded = Rtc::new(RtcClock::Host);
+    loaded.load_footer(&footer);
+    assert_eq!(loaded.hours, 1);
+    assert_eq!(loaded.minutes, 1);
+}
+
+#[test]
+fn test_rtc_catches_up_on_years_at_once() {
+    let mut rtc = Rtc::new(RtcClock::Host);
+    let start = rtc.last_sync;
+    // 1000 days, 1:02:03 later, going round the day counter once
+    rtc.sync_to(start + Duration::from_secs(1000 * 86400 + 3723));
+    assert_eq!(rtc.registers(), [3, 2, 1, (1000 - 512) as u8, DAY_CARRY | DAY_HIGH]);
+    // A save from 1970 takes no longer
+    let mut footer = rtc.to_footer();
+    for byte in footer[40..].iter_mut() {
+        *byte = 0;
+    }
+    rtc.load_footer(&footer);
+    assert!(rtc.day_carry);
+}
+
+#[test]
+fn test_rtc_registers_out_of_range_wrap_without_carrying() {
+    let mut rtc = Rtc::new(RtcClock::Emulated);
+    rtc.write(RTC_SECONDS, 62);
+    rtc.write(RTC_HOURS, 30);
+    rtc.tick(CYCLES_PER_SECOND * 3);
+    assert_eq!(rtc.registers(), [1, 0, 30, 0, 0]);
+    // An hour and a bit later, the hours wrap too
+    rtc.tick(CYCLES_PER_SECOND);
+    rtc.advance(3600 * 2);
+    assert_eq!(rtc.registers(), [2, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_rtc_reads_outside_its_registers_are_ff() {
+    let rtc = Rtc::new(RtcClock::Emulated);
+    assert_eq!(rtc.read(0x03), 0xFF);
+    assert_eq!(rtc.read(0x0D), 0xFF);
+}
@@ -0,0 +1,160 @@
+use mbc::{MemoryBankController, rom_byte, ram_offset};
+
+// On rumble cartridges, this bit of the RAM bank number drives the motor
+const RUMBLE_MOTOR : u8 = 0x08;
+
+/// MBC5: up to 8 MiB of ROM and 128 KiB of RAM. The only controller that
+/// works in the Game Boy Color's double speed mode.
+pub struct Mbc5 {
+    ram_enabled: bool,
+    rom_bank: u16, // 9 bits. Unlike the older controllers, 0 really is 0.
+    ram_bank: u8,  // 4 bits
+    // Called with the motor's new state whenever it turns on or off.
+    // Only rumble cartridges have one.
+    rumble: Option<Box<dyn FnMut(bool)>>,
+    motor_on: bool,
+    has_rumble: bool,
+}
+
+impl Default for Mbc5 {
+    fn default() -> Mbc5 {
+        Mbc5::new()
+    }
+}
+
+impl Mbc5 {
+    pub fn new() -> Mbc5 {
+        Mbc5 {
+            ram_enabled: false,
+            rom_bank: 0x01,
+            ram_bank: 0x00,
+            rumble: None,
+            motor_on: false,
+            has_rumble: false,
+        }
+    }
+
+    /// An MBC5 with a rumble motor, as on cartridge types 0x1C ..= 0x1E.
+    /// The motor takes over bit 3 of the RAM bank number.
+    pub fn with_rumble() -> Mbc5 {
+        Mbc5 { has_rumble: true, ..Mbc5::new() }
+    }
+
+    fn set_ram_bank(&mut self, val: u8) {
+        if !self.has_rumble {
+            self.ram_bank = val & 0x0F;
+            return;
+        }
+        self.ram_bank = val & 0x07;
+        let motor_on = val & RUMBLE_MOTOR != 0;
+        if motor_on != self.motor_on {
+            self.motor_on = motor_on;
+            if let Some(ref mut rumble) = self.rumble {
+                rumble(motor_on);
+            }
+        }
+    }
+}
+
+impl MemoryBankController for Mbc5 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000 ..= 0x3FFF => rom_byte(rom, 0, address),
+            _                 => rom_byte(rom, self.rom_bank as usize, address),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, val: u8) {
+        match address {
+            // Only exactly 0x0A enables RAM on the MBC5
+            0x0000 ..= 0x1FFF => self.ram_enabled = val == 0x0A,
+            // The low eight bits of the ROM bank...
+            0x2000 ..= 0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | val as u16,
+            // ...and the ninth
+            0x3000 ..= 0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((val & 0x01) as u16) << 8
+            },
+            0x4000 ..= 0x5FFF => self.set_ram_bank(val),
+            _                 => {},
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match ram_offset(ram, self.ram_bank as usize, address) {
+            Some(offset) => ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, val: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        match ram_offset(ram, self.ram_bank as usize, address) {
+            Some(offset) => {
+                ram[offset] = val;
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn set_rumble_callback(&mut self, callback: Box<dyn FnMut(bool)>) {
+        if self.has_rumble {
+            self.rumble = Some(callback);
+        }
+    }
+}
+
+#[test]
+fn test_mbc5_switches_rom_banks() {
+    let rom = ::mbc::banked_rom(512);
+    let mut mbc = Mbc5::new();
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    // Bank 0 can be mapped in at 0x4000 too
+    mbc.write_rom(0x2000, 0x00);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 0);
+    mbc.write_rom(0x2000, 0xFF);
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 0xFF);
+    // The ninth bit is written separately
+    mbc.write_rom(0x3000, 0x01);
+    mbc.write_rom(0x2000, 0x05);
+    assert_eq!(mbc.rom_bank, 0x105);
+    // (`banked_rom` only has room for the low byte of the bank number)
+    assert_eq!(mbc.read_rom(&rom, 0x4000), 0x05);
+}
+
+#[test]
+fn test_mbc5_switches_between_16_ram_banks() {
+    let mut ram = vec![0; 0x20000];
+    let mut mbc = Mbc5::new();
+    mbc.write_rom(0x0000, 0x0A);
+    mbc.write_rom(0x4000, 0x0F);
+    mbc.write_ram(&mut ram, 0xA000, 0x42);
+    assert_eq!(ram[0x1E000], 0x42);
+    mbc.write_rom(0x4000, 0x00);
+    assert_eq!(mbc.read_ram(&ram, 0xA000), 0x00);
+}
+
+#[test]
+fn test_mbc5_reports_the_rumble_motor() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let changes = Rc::new(RefCell::new(Vec::new()));
+    let log = changes.clone();
+    let mut ram = vec![0; 0x8000];
+    let mut mbc = Mbc5::with_rumble();
+    mbc.set_rumble_callback(Box::new(move |on| log.borrow_mut().push(on)));
+    mbc.write_rom(0x0000, 0x0A);
+    mbc.write_rom(0x4000, 0x09);
+    mbc.write_rom(0x4000, 0x09);
+    // The motor bit isn't part of the bank number
+    mbc.write_ram(&mut ram, 0xA000, 0x42);
+    assert_eq!(ram[0x2000], 0x42);
+    mbc.write_rom(0x4000, 0x01);
+    assert_eq!(*changes.borrow(), vec![true, false]);
+}
@@ -0,0 +1,100 @@
+// Memory Bank Controllers
+// ------ ---- -----------
+// Cartridges bigger than 32 KiB (or with their own RAM) have a chip that
+// swaps `banks` of ROM and RAM in and out of the address space. Games talk
+// to it by writing to addresses in ROM, which can't actually be written.
+
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+
+use self::mbc3::Rtc;
+
+pub const ROM_BANK_SIZE : usize = 0x4000;
+pub const RAM_BANK_SIZE : usize = 0x2000;
+
+pub trait MemoryBankController {
+    /// Read from 0x0000 ..= 0x7FFF
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8;
+    /// Write to 0x0000 ..= 0x7FFF, which sets the controller's registers
+    fn write_rom(&mut self, address: u16, val: u8);
+    /// Read from 0xA000 ..= 0xBFFF
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8;
+    /// Write to 0xA000 ..= 0xBFFF. Returns whether anything was stored,
+    /// which it isn't with RAM disabled, or no RAM at all.
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, val: u8) -> bool;
+
+    /// Let `cycles` T-cycles pass, for controllers with a clock
+    fn tick(&mut self, _cycles: u32) {}
+
+    /// The controller's real-time clock, if it has one
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+
+    /// Have `callback` called whenever a rumble cartridge's motor turns on
+    /// or off. Cartridges without a motor ignore it.
+    fn set_rumble_callback(&mut self, _callback: Box<dyn FnMut(bool)>) {}
+}
+
+/// The byte at `address` within ROM bank `bank`. Bank numbers past the end
+/// of the ROM wrap around, the same way the unconnected pins on the real
+/// chip do.
+pub fn rom_byte(rom: &[u8], bank: usize, address: u16) -> u8 {
+    if rom.is_empty() {
+        return 0xFF;
+    }
+    let offset = bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1));
+    rom[offset % rom.len()]
+}
+
+/// Where `address` is within the cartridge's RAM, in RAM bank `bank`,
+/// if the cartridge has any RAM.
+pub fn ram_offset(ram: &[u8], bank: usize, address: u16) -> Option<usize> {
+    if ram.is_empty() {
+        return None;
+    }
+    let offset = bank * RAM_BANK_SIZE + (address as usize & (RAM_BANK_SIZE - 1));
+    Some(offset % ram.len())
+}
+
+/// A ROM where every bank starts with its own number
+#[cfg(test)]
+pub fn banked_rom(banks: usize) -> Vec<u8> {
+    let mut rom = vec![0; banks * ROM_BANK_SIZE];
+    for bank in 0..banks {
+        rom[bank * ROM_BANK_SIZE] = bank as u8;
+    }
+    rom
+}
+
+/// A cartridge with no controller at all: 32 KiB of ROM, and maybe
+/// 8 KiB of RAM.
+pub struct RomOnly;
+
+impl MemoryBankController for RomOnly {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        // Reading past the end of a short image gives an open bus
+        *rom.get(address as usize).unwrap_or(&0xFF)
+    }
+
+    fn write_rom(&mut self, _address: u16, _val: u8) {}
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        match ram_offset(ram, 0, address) {
+            Some(offset) => ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, val: u8) -> bool {
+        match ram_offset(ram, 0, address) {
+            Some(offset) => {
+                ram[offset] = val;
+                true
+            },
+            None => false,
+        }
+    }
+}
@@ -1,103 +1,423 @@
+use std::io;
+
+use cartridge::{Cartridge, CgbFlag};
+
+/// OAM DMA copies this many bytes into OAM, one every M-cycle
+const DMA_LENGTH : u16 = 0xA0;
+
+/// An OAM DMA transfer, copying 160 bytes from 0xXX00 into OAM
+struct Dma {
+    source: u16,
+    index: u16,
+    // The transfer starts an M-cycle after it's set off
+    delay: u8,
+}
+
+/// Memory is split across two buses. While DMA is using one of them, the
+/// CPU can't.
+#[derive(PartialEq)]
+enum Bus {
+    External, // The cartridge and working memory
+    Video,    // VRAM
+    Internal, // OAM, IO and zero-page RAM, which DMA doesn't get in the way of
+}
+
+fn bus(address: u16) -> Bus {
+    match address {
+        0x8000 ..= 0x9FFF => Bus::Video,
+        0xFE00 ..= 0xFFFF => Bus::Internal,
+        _                 => Bus::External,
+    }
+}
+
 pub struct MMU {
     gpu: ::gpu::GPU,
-    inbios: bool,
-    bios: [u8; 0x0100],
-    rom:  [u8; (1024*8)],
-    wram: [u8; (1024*8)],
-    eram: [u8; (1024*8)],
-    zram: [u8; (1024*8)],
+    timer: ::timer::Timer,
+    joypad: ::joypad::Joypad,
+    apu: ::apu::Apu,
+    serial: ::serial::Serial,
+    // Mapped over the start of the cartridge until 0xFF50 is written
+    boot_rom: Vec<u8>,
+    boot_rom_mapped: bool,
+    cartridge: Cartridge,
+    wram: [u8; 1024*32], // Eight 4 KiB banks, though only the CGB has 2 ..= 7
+    wram_bank: u8, // SVBK
+    zram: [u8; 1024*8],
+    // IO registers that nothing has taken ownership of yet
+    io: [u8; 0x80],
+    // Whether the Game Boy Color's registers are there
+    cgb: bool,
+    // The CGB can run the CPU (and the timer, serial and DMA) at twice the
+    // speed. KEY1 arms the switch, and STOP makes it.
+    double_speed: bool,
+    speed_switch_armed: bool,
+    interrupt_enable: u8, // IE, at 0xFFFF
+    interrupt_flag: u8,   // IF, at 0xFF0F
+    dma: Option<Dma>,
+    dma_register: u8, // The last value written to 0xFF46
+    // What DMA last read. A CPU that reads from the bus DMA is using gets
+    // this instead.
+    dma_byte: u8,
+}
+
+impl Default for MMU {
+    fn default() -> MMU {
+        MMU::new()
+    }
 }
 
 impl MMU {
     pub fn new() -> MMU {
         MMU {
             gpu: ::gpu::GPU::new(),
-            inbios: true,
-            bios: [0; 0x100],
-            rom : [0; (1024*8)],
-            wram: [0; (1024*8)],
-            eram: [0; (1024*8)],
-            zram: [0; (1024*8)],
+            timer: ::timer::Timer::new(),
+            joypad: ::joypad::Joypad::new(),
+            apu: ::apu::Apu::new(),
+            serial: ::serial::Serial::new(),
+            boot_rom: Vec::new(),
+            boot_rom_mapped: false,
+            cartridge: Cartridge::empty(),
+            wram: [0; 1024*32],
+            wram_bank: 0,
+            zram: [0; 1024*8],
+            io: [0; 0x80],
+            cgb: false,
+            double_speed: false,
+            speed_switch_armed: false,
+            interrupt_enable: 0x00,
+            interrupt_flag: 0x00,
+            dma: None,
+            dma_register: 0xFF,
+            dma_byte: 0xFF,
         }
     }
 
+    /// Plug a cartridge in, replacing whatever was there before. Games made
+    /// for the Game Boy Color switch it into CGB mode.
+    pub fn load_cartridge(&mut self, cartridge: Cartridge) {
+        self.set_cgb(cartridge.header.cgb_flag != CgbFlag::DmgOnly);
+        self.cartridge = cartridge;
+    }
+
+    /// Turn the Game Boy Color's hardware on or off.
+    pub fn set_cgb(&mut self, cgb: bool) {
+        self.cgb = cgb;
+        self.gpu.set_cgb(cgb);
+    }
+
+    pub fn cgb(&self) -> bool {
+        self.cgb
+    }
+
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// Switch speeds, if KEY1 has asked for it. STOP does this instead of
+    /// stopping. Returns whether it happened.
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.cgb || !self.speed_switch_armed {
+            return false;
+        }
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+        self.timer.write_register(::io::DIV, 0x00);
+        true
+    }
+
+    /// Where `address` (in 0xC000 ..= 0xFDFF) is in working memory. The
+    /// second 4 KiB is switchable on the CGB; SVBK's bank 0 means bank 1.
+    fn wram_index(&self, address: u16) -> usize {
+        let address = address as usize & 0x1FFF;
+        if address < 0x1000 {
+            address
+        } else {
+            self.wram_bank.max(1) as usize * 0x1000 + (address & 0x0FFF)
+        }
+    }
+
+    /// Map a boot ROM over the start of the cartridge: 256 bytes for the
+    /// DMG and MGB, or 2304 for the CGB.
+    pub fn load_boot_rom(&mut self, rom: Vec<u8>) -> io::Result<()> {
+        if rom.len() != ::boot::DMG_BOOT_ROM_SIZE && rom.len() != ::boot::CGB_BOOT_ROM_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("a boot ROM should be 256 or 2304 bytes, not {}",
+                                              rom.len())));
+        }
+        self.boot_rom = rom;
+        self.boot_rom_mapped = true;
+        Ok(())
+    }
+
+    /// Hand the start of memory back to the cartridge. Once this happens,
+    /// there's no way to map the boot ROM back in.
+    pub fn unmap_boot_rom(&mut self) {
+        self.boot_rom_mapped = false;
+    }
+
+    fn in_boot_rom(&self, address: u16) -> bool {
+        self.boot_rom_mapped && match address {
+            0x0000 ..= 0x00FF => true,
+            // The CGB's boot ROM skips over the cartridge header
+            0x0200 ..= 0x08FF => self.boot_rom.len() == ::boot::CGB_BOOT_ROM_SIZE,
+            _ => false,
+        }
+    }
+
+    pub fn gpu(&self) -> &::gpu::GPU {
+        &self.gpu
+    }
+
+    /// The buttons, for pressing
+    pub fn joypad_mut(&mut self) -> &mut ::joypad::Joypad {
+        &mut self.joypad
+    }
+
+    /// The sound, for setting a sample rate and taking samples
+    pub fn apu_mut(&mut self) -> &mut ::apu::Apu {
+        &mut self.apu
+    }
+
+    /// The link port, for plugging things into
+    pub fn serial_mut(&mut self) -> &mut ::serial::Serial {
+        &mut self.serial
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    /// Let `cycles` T-cycles pass for everything outside the CPU.
+    pub fn tick(&mut self, cycles: u32) {
+        // At double speed, the timer, serial port and DMA keep up with the
+        // CPU, but everything else carries on at the usual speed
+        let normal_cycles = if self.double_speed { cycles / 2 } else { cycles };
+        self.cartridge.tick(normal_cycles);
+        if self.timer.tick(cycles) {
+            self.request_interrupt(::interrupt::TIMER);
+        }
+        if self.serial.tick(cycles) {
+            self.request_interrupt(::interrupt::SERIAL);
+        }
+        self.apu.tick(normal_cycles);
+        // ...which the frame sequencer does by watching the next bit up
+        let div = self.timer.read_register(::io::DIV);
+        self.apu.div_changed(if self.double_speed { div >> 1 } else { div });
+        let interrupts = self.gpu.tick(normal_cycles);
+        self.request_interrupt(interrupts);
+        if self.joypad.take_interrupt() {
+            self.request_interrupt(::interrupt::JOYPAD);
+        }
+        for _ in 0..cycles / 4 {
+            self.step_dma();
+        }
+    }
+
+    /// Start copying 160 bytes from `page` * 0x100 into OAM
+    fn start_dma(&mut self, page: u8) {
+        self.dma_register = page;
+        let mut source = (page as u16) << 8;
+        // There's nothing to copy from above working memory: these read
+        // from its echo instead
+        if source >= 0xE000 {
+            source -= 0x2000;
+        }
+        self.dma = Some(Dma { source, index: 0, delay: 1 });
+    }
+
+    /// Copy the next byte of an OAM DMA, if one is running
+    fn step_dma(&mut self) {
+        let (source, index) = match self.dma {
+            Some(ref mut dma) if dma.delay > 0 => {
+                dma.delay -= 1;
+                return;
+            },
+            Some(ref dma) => (dma.source, dma.index),
+            None => return,
+        };
+        let val = self.read_bus(source + index);
+        self.gpu.oam[index as usize] = val;
+        self.dma_byte = val;
+        if index + 1 == DMA_LENGTH {
+            self.dma = None;
+        } else if let Some(ref mut dma) = self.dma {
+            dma.index += 1;
+        }
+    }
+
+    /// Whether the GPU is letting the CPU get at `address`, if it's in
+    /// VRAM or OAM
+    fn gpu_accessible(&self, address: u16) -> bool {
+        match address {
+            0x8000 ..= 0x9FFF => self.gpu.vram_accessible(),
+            0xFE00 ..= 0xFE9F => self.gpu.oam_accessible(),
+            _ => true,
+        }
+    }
+
+    /// Keep the CPU out of VRAM and OAM while the GPU is using them, as on
+    /// the real hardware (the default), or not
+    pub fn set_memory_locking(&mut self, enabled: bool) {
+        self.gpu.set_memory_locking(enabled);
+    }
+
+    /// Whether DMA is using the memory at `address`, so the CPU can't
+    fn dma_conflict(&self, address: u16) -> bool {
+        match self.dma {
+            Some(ref dma) if dma.delay == 0 => {
+                (0xFE00 ..= 0xFEFF).contains(&address) || bus(address) == bus(dma.source)
+            },
+            _ => false,
+        }
+    }
+
+    /// Raise one of the interrupts in `::interrupt` by setting its bit in IF.
+    pub fn request_interrupt(&mut self, interrupt: u8) {
+        self.interrupt_flag |= interrupt;
+    }
+
+    /// Clear an interrupt's bit in IF, once the CPU has started servicing it.
+    pub fn acknowledge_interrupt(&mut self, interrupt: u8) {
+        self.interrupt_flag &= !interrupt;
+    }
+
+    /// The interrupts that have been requested, enabled or not.
+    pub fn requested_interrupts(&self) -> u8 {
+        self.interrupt_flag
+    }
+
+    /// The interrupts that are both requested and enabled.
+    pub fn pending_interrupts(&self) -> u8 {
+        self.interrupt_enable & self.interrupt_flag & ::interrupt::ALL
+    }
+
     pub fn read(&mut self, address: u16) -> u8 {
+        if self.dma_conflict(address) {
+            // OAM is busy being written. Anywhere else on the same bus
+            // reads whatever DMA is reading.
+            return if bus(address) == Bus::Internal { 0xFF } else { self.dma_byte };
+        }
+        if !self.gpu_accessible(address) {
+            return 0xFF;
+        }
+        self.read_bus(address)
+    }
+
+    fn read_bus(&mut self, address: u16) -> u8 {
         let addr = address as usize;
         match addr {
-            // When the gameboy starts up, all reads from 0x000 ... 0x0100
-            // are redirected to the BIOS, which boots up the gameboy
-            // and draws the 'Nintendo' logo on screen. After that,
-            // the gameboy will read from 0x0100, which is a signal that
-            // startup is over, and this area of memory can be used by
+            // When the gameboy starts up, reads from the start of memory
+            // are redirected to the boot ROM, which boots up the gameboy
+            // and draws the 'Nintendo' logo on screen. Once it's done, it
+            // writes to 0xFF50, and this area of memory can be used by
             // the cartridge.
-            0x0000 ... 0x00FF => {
-                if self.inbios {self.bios[addr]} else {self.rom[addr]}
-            },
-            0x0100 => { self.inbios = false; self.rom[addr] },
+            _ if self.in_boot_rom(address) => self.boot_rom[addr],
             // ROM
-            0x0101 ... 0x7FFF => self.rom[addr],
+            0x0000 ..= 0x7FFF => self.cartridge.read_rom(address),
             // Graphics VRAM
-            0x8000 ... 0x9FFF => self.gpu.vram[addr],
+            0x8000 ..= 0x9FFF => self.gpu.read_vram(address),
             // External memory
-            0xA000 ... 0xBFFF => self.eram[addr & 0x1FFF],
+            0xA000 ..= 0xBFFF => self.cartridge.read_ram(address),
             // Working memory
-            0xC000 ... 0xDFFF => self.wram[addr & 0x1FFF],
+            0xC000 ..= 0xDFFF => self.wram[self.wram_index(address)],
             // Shadowed memory - redirects to the working memory
-            0xE000 ... 0xFDFF => self.wram[addr & 0x1FFF],
+            0xE000 ..= 0xFDFF => self.wram[self.wram_index(address)],
             // OAM is only 160 bytes
-            0xFE00 ... 0xFEA0 => self.gpu.oam[addr & 0x00FF],
+            0xFE00 ..= 0xFE9F => self.gpu.oam[addr & 0x00FF],
             // The rest is all 0's
             // (We use 0x0 as 0 because it is a cute cat face)
-            0xFEA1 ... 0xFEFF => 0x0,
-            0xFF00 ... 0xFF7F => 0x0, // TODO: Input/Output
-            0xFF80 ... 0xFFFF => self.zram[addr & 0x007F], // zero-page RAM
+            0xFEA0 ..= 0xFEFF => 0x0,
+            // Input/Output
+            0xFF00 ..= 0xFF7F => self.read_io(address),
+            0xFF80 ..= 0xFFFE => self.zram[addr & 0x007F], // zero-page RAM
+            0xFFFF => self.interrupt_enable,
             _ => { println!("Memory access out of bounds"); 0x0 }
         }
     }
 
     pub fn read_word(&mut self, address: u16) -> u16 { // write 16 bits
-        let byte_one = self.read(address+1);
+        let byte_one = self.read(address.wrapping_add(1));
         let byte_two = self.read(address);
         byte_two as u16 | ((byte_one as u16) << 8)
     }
 
     pub fn write_byte(&mut self, address: u16, val: u8) { // write 8 bits
-        //TODO(Lito): There is more to this
+        if self.dma_conflict(address) || !self.gpu_accessible(address) {
+            return;
+        }
         let addr = address as usize;
         match addr {
             // ROM
-            0x0000 ... 0x7FFF => self.rom[addr] = val,
+            0x0000 ..= 0x7FFF => self.cartridge.write_rom(address, val),
             // Graphics VRAM
-            0x8000 ... 0x9FFF => self.gpu.write_byte(address, val),
+            0x8000 ..= 0x9FFF => self.gpu.write_vram(address, val),
             // External memory
-            0xA000 ... 0xBFFF => self.eram[addr & 0x1FFF] = val,
+            0xA000 ..= 0xBFFF => self.cartridge.write_ram(address, val),
             // Working memory
-            0xC000 ... 0xDFFF => self.wram[addr & 0x1FFF] = val,
+            0xC000 ..= 0xDFFF => self.wram[self.wram_index(address)] = val,
             // Shadowed memory - redirects to the working memory
-            0xE000 ... 0xFDFF => self.wram[addr & 0x1FFF] = val,
-            // TODO(Lito): This is WAY more complicated
-            // (I'm ignoring IO, OAM, and lots of other stuff)
-            0xFE00 ... 0xFF7F => self.gpu.write_byte(address, val),
+            0xE000 ..= 0xFDFF => self.wram[self.wram_index(address)] = val,
+            // OAM
+            0xFE00 ..= 0xFE9F => self.gpu.oam[addr & 0x00FF] = val,
+            // Unusable
+            0xFEA0 ..= 0xFEFF => {},
+            // Input/Output
+            0xFF00 ..= 0xFF7F => self.write_io(address, val),
             // zero-page RAM
-            0xFF80 ... 0xFFFF => self.zram[addr & 0x007F] = val,
+            0xFF80 ..= 0xFFFE => self.zram[addr & 0x007F] = val,
+            0xFFFF => self.interrupt_enable = val,
             _ => { println!("Memory write out of bounds"); }
         }
     }
 
+    /// Read an IO register from whichever component it belongs to
+    fn read_io(&mut self, address: u16) -> u8 {
+        let val = match address {
+            ::io::P1 => self.joypad.read_register(address),
+            ::io::SB | ::io::SC => self.serial.read_register(address),
+            ::io::DIV ..= ::io::TAC => self.timer.read_register(address),
+            ::io::IF => self.interrupt_flag,
+            ::io::NR10 ..= ::io::WAVE_END => self.apu.read_register(address),
+            ::io::LCDC ..= ::io::LYC | ::io::BGP ..= ::io::WX => self.gpu.read_register(address),
+            // Whether the boot ROM is unmapped
+            ::io::BOOT => !self.boot_rom_mapped as u8,
+            ::io::DMA => self.dma_register,
+            ::io::KEY1 if self.cgb => (self.double_speed as u8) << 7 | self.speed_switch_armed as u8,
+            ::io::VBK | ::io::BCPS ..= ::io::OPRI if self.cgb => self.gpu.read_register(address),
+            ::io::SVBK if self.cgb => self.wram_bank,
+            _ => self.io[address as usize & 0x7F],
+        };
+        val | ::io::unused_bits(address, self.cgb)
+    }
+
+    /// Write an IO register, for whichever component it belongs to
+    fn write_io(&mut self, address: u16, val: u8) {
+        match address {
+            ::io::P1 => self.joypad.write_register(address, val),
+            ::io::SB | ::io::SC => self.serial.write_register(address, val),
+            ::io::DIV ..= ::io::TAC => self.timer.write_register(address, val),
+            ::io::IF => self.interrupt_flag = val & ::interrupt::ALL,
+            ::io::NR10 ..= ::io::WAVE_END => self.apu.write_register(address, val),
+            ::io::LCDC ..= ::io::LYC | ::io::BGP ..= ::io::WX => self.gpu.write_register(address, val),
+            ::io::BOOT => if val != 0 { self.unmap_boot_rom() },
+            ::io::DMA => self.start_dma(val),
+            ::io::KEY1 if self.cgb => self.speed_switch_armed = val & 0x01 != 0,
+            ::io::VBK | ::io::BCPS ..= ::io::OPRI if self.cgb => self.gpu.write_register(address, val),
+            ::io::SVBK if self.cgb => self.wram_bank = val & 0x07,
+            _ => self.io[address as usize & 0x7F] = val,
+        }
+    }
+
     pub fn write_word(&mut self, address: u16, val: u16) { // write 16 bits
         let byte_one : u8 = (val & 0x00FF) as u8;
         let byte_two : u8 = (val >> 8) as u8;
         self.write_byte(address, byte_one);
-        self.write_byte(address+1, byte_two);
-    }
-
-    /*
-    // TODO(Lito):
-    pub fn open() {
-        let rom_path = &Path::new("pokemon.gb");
-        File::open(rom_path).read_to_end();
+        self.write_byte(address.wrapping_add(1), byte_two);
     }
-    */
 }
 
 #[test]
@@ -123,3 +443,229 @@ fn test_reading_a_word() {
     mmu.write_word(0xC001, 0x0605);
     assert_eq!(mmu.read_word(0xC001), 0x0605);
 }
+
+#[test]
+fn test_interrupt_registers() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xFFFF, ::interrupt::VBLANK | ::interrupt::TIMER);
+    mmu.write_byte(0xFF0F, ::interrupt::TIMER | ::interrupt::SERIAL);
+    // The unused bits of IF read back as 1
+    assert_eq!(mmu.read(0xFF0F), 0xE0 | ::interrupt::TIMER | ::interrupt::SERIAL);
+    assert_eq!(mmu.pending_interrupts(), ::interrupt::TIMER);
+    mmu.request_interrupt(::interrupt::VBLANK);
+    assert_eq!(mmu.pending_interrupts(), ::interrupt::VBLANK | ::interrupt::TIMER);
+    mmu.acknowledge_interrupt(::interrupt::VBLANK);
+    assert_eq!(mmu.pending_interrupts(), ::interrupt::TIMER);
+}
+
+#[test]
+fn test_reading_from_a_cartridge() {
+    let mut rom = ::cartridge::test_rom(0x00, 0x00, 0x00);
+    rom[0x0150] = 0x42;
+    rom[0x7FFF] = 0x24;
+    let mut mmu = MMU::new();
+    mmu.load_cartridge(Cartridge::from_bytes(&rom).unwrap());
+    assert_eq!(mmu.read(0x0150), 0x42);
+    assert_eq!(mmu.read(0x7FFF), 0x24);
+    assert_eq!(mmu.cartridge().header.title, "TEST");
+    // ROM can't be written to
+    mmu.write_byte(0x0150, 0x00);
+    assert_eq!(mmu.read(0x0150), 0x42);
+}
+
+#[test]
+fn test_the_boot_rom_is_unmapped_by_writing_to_ff50() {
+    let mut rom = ::cartridge::test_rom(0x00, 0x00, 0x00);
+    rom[0x0000] = 0xAA;
+    rom[0x0100] = 0xBB;
+    let mut mmu = MMU::new();
+    mmu.load_cartridge(Cartridge::from_bytes(&rom).unwrap());
+    mmu.load_boot_rom(vec![0x31; 0x100]).unwrap();
+    assert_eq!(mmu.read(0x0000), 0x31);
+    assert_eq!(mmu.read(0xFF50), 0xFE);
+    // Reading past the boot ROM doesn't unmap it
+    assert_eq!(mmu.read(0x0100), 0xBB);
+    assert_eq!(mmu.read(0x0000), 0x31);
+    mmu.write_byte(0xFF50, 0x01);
+    assert_eq!(mmu.read(0x0000), 0xAA);
+    assert_eq!(mmu.read(0xFF50), 0xFF);
+}
+
+#[test]
+fn test_the_cgb_boot_rom_leaves_a_gap_for_the_header() {
+    let mut rom = ::cartridge::test_rom(0x00, 0x00, 0x00);
+    rom[0x0150] = 0xBB;
+    let mut mmu = MMU::new();
+    mmu.load_cartridge(Cartridge::from_bytes(&rom).unwrap());
+    mmu.load_boot_rom(vec![0x31; 0x900]).unwrap();
+    assert_eq!(mmu.read(0x0150), 0xBB);
+    assert_eq!(mmu.read(0x0200), 0x31);
+    assert_eq!(mmu.read(0x08FF), 0x31);
+    assert!(mmu.load_boot_rom(vec![0; 0x200]).is_err());
+}
+
+#[test]
+fn test_io_registers_read_back_unused_bits_as_1() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(::io::TAC, 0x00);
+    assert_eq!(mmu.read(::io::TAC), 0xF8);
+    mmu.write_byte(::io::SCX, 0x12);
+    assert_eq!(mmu.read(::io::SCX), 0x12);
+    // LY is read-only
+    mmu.write_byte(::io::LY, 0x12);
+    assert_eq!(mmu.read(::io::LY), 0x00);
+    // Nothing lives at 0xFF03
+    mmu.write_byte(0xFF03, 0x00);
+    assert_eq!(mmu.read(0xFF03), 0xFF);
+}
+
+#[test]
+fn test_writing_vram_and_oam() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0x8123, 0x42);
+    mmu.write_byte(0xFE9F, 0x43);
+    assert_eq!(mmu.read(0x8123), 0x42);
+    assert_eq!(mmu.read(0xFE9F), 0x43);
+}
+
+#[test]
+fn test_the_timer_requests_an_interrupt() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(::io::TIMA, 0xFF);
+    mmu.write_byte(::io::TAC, 0x05);
+    mmu.tick(16);
+    assert_eq!(mmu.requested_interrupts(), 0x00);
+    mmu.tick(4);
+    assert_eq!(mmu.requested_interrupts(), ::interrupt::TIMER);
+}
+
+#[test]
+fn test_the_gpu_requests_vblank() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(::io::LCDC, 0x80);
+    mmu.tick(::gpu::CYCLES_PER_LINE * 144);
+    assert_eq!(mmu.read(::io::LY), 144);
+    assert_eq!(mmu.requested_interrupts(), ::interrupt::VBLANK);
+}
+
+#[test]
+fn test_oam_dma_copies_160_bytes() {
+    let mut mmu = MMU::new();
+    for i in 0..0xA0 {
+        mmu.write_byte(0xC100 + i, i as u8);
+    }
+    mmu.write_byte(::io::DMA, 0xC1);
+    assert_eq!(mmu.read(::io::DMA), 0xC1);
+    // A cycle to start, and then one a byte
+    mmu.tick(4 * 0xA0);
+    assert_eq!(mmu.read(0xFE9F), 0xFF);
+    mmu.tick(4);
+    assert_eq!(mmu.read(0xFE00), 0x00);
+    assert_eq!(mmu.read(0xFE9F), 0x9F);
+}
+
+#[test]
+fn test_oam_dma_takes_over_the_bus() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xC000, 0x42);
+    mmu.write_byte(0xD000, 0x43);
+    mmu.write_byte(0x8000, 0x44);
+    mmu.write_byte(0xFF80, 0x45);
+    mmu.write_byte(::io::DMA, 0xC0);
+    mmu.tick(8);
+    // Working memory is on the same bus, so reads what DMA is reading
+    assert_eq!(mmu.read(0xD000), 0x42);
+    // ...and OAM is busy
+    assert_eq!(mmu.read(0xFE00), 0xFF);
+    // But VRAM and zero-page RAM are fine
+    assert_eq!(mmu.read(0x8000), 0x44);
+    assert_eq!(mmu.read(0xFF80), 0x45);
+    // Writes on the busy bus go nowhere
+    mmu.write_byte(0xD000, 0x00);
+    mmu.tick(4 * 0xA0);
+    assert_eq!(mmu.read(0xD000), 0x43);
+}
+
+#[test]
+fn test_vram_is_locked_while_drawing() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0x8000, 0x42);
+    mmu.write_byte(::io::LCDC, 0x80);
+    mmu.tick(80);
+    assert_eq!(mmu.read(0x8000), 0xFF);
+    mmu.write_byte(0x8000, 0x00);
+    mmu.set_memory_locking(false);
+    assert_eq!(mmu.read(0x8000), 0x42);
+}
+
+#[test]
+fn test_sound_registers_go_to_the_apu() {
+    let mut mmu = MMU::new();
+    assert_eq!(mmu.read(::io::NR52), 0x70);
+    mmu.write_byte(::io::NR52, 0x80);
+    mmu.write_byte(0xFF11, 0xBF); // NR11: one length clock left
+    mmu.write_byte(0xFF12, 0xF0);
+    mmu.write_byte(0xFF14, 0xC0); // Trigger, with the length enabled
+    assert_eq!(mmu.read(::io::NR52), 0xF1);
+    assert_eq!(mmu.read(0xFF11), 0xBF);
+    // DIV bit 4 falls every 8192 cycles, which clocks the length
+    for _ in 0..8192 / 4 {
+        mmu.tick(4);
+    }
+    assert_eq!(mmu.read(::io::NR52), 0xF0);
+}
+
+#[test]
+fn test_a_finished_serial_transfer_interrupts() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(::io::SB, 0x42);
+    mmu.write_byte(::io::SC, 0x81);
+    assert_eq!(mmu.read(::io::SC), 0xFF);
+    for _ in 0..8 * 512 / 4 {
+        mmu.tick(4);
+    }
+    assert_eq!(mmu.read(::io::SB), 0xFF);
+    assert_eq!(mmu.read(::io::SC), 0x7F);
+    assert_eq!(mmu.requested_interrupts(), ::interrupt::SERIAL);
+}
+
+#[test]
+fn test_cgb_vram_and_wram_banks() {
+    let mut mmu = MMU::new();
+    // Nothing happens on the DMG
+    mmu.write_byte(::io::SVBK, 0x03);
+    assert_eq!(mmu.read(::io::SVBK), 0xFF);
+    mmu.set_cgb(true);
+    mmu.write_byte(0xD000, 0x11);
+    mmu.write_byte(::io::SVBK, 0x03);
+    assert_eq!(mmu.read(::io::SVBK), 0xFB);
+    assert_eq!(mmu.read(0xD000), 0x00);
+    mmu.write_byte(0xD000, 0x33);
+    assert_eq!(mmu.read(0xF000), 0x33);
+    // Bank 0 is bank 1
+    mmu.write_byte(::io::SVBK, 0x00);
+    assert_eq!(mmu.read(0xD000), 0x11);
+    mmu.write_byte(0x8000, 0x44);
+    mmu.write_byte(::io::VBK, 0x01);
+    assert_eq!(mmu.read(::io::VBK), 0xFF);
+    assert_eq!(mmu.read(0x8000), 0x00);
+    mmu.write_byte(0x8000, 0x55);
+    assert_eq!(mmu.gpu.vram[0x2000], 0x55);
+}
+
+#[test]
+fn test_cgb_games_turn_on_cgb_mode() {
+    let mut rom = ::cartridge::test_rom(0x00, 0x00, 0x00);
+    rom[0x0143] = 0x80;
+    // Keep the header checksum right
+    rom[0x014D] = rom[0x014D].wrapping_sub(0x80);
+    let mut mmu = MMU::new();
+    mmu.load_cartridge(Cartridge::from_bytes(&rom).unwrap());
+    assert!(mmu.cgb());
+    assert_eq!(mmu.read(::io::KEY1), 0x7E);
+    mmu.write_byte(::io::KEY1, 0x01);
+    assert_eq!(mmu.read(::io::KEY1), 0x7F);
+    assert!(mmu.switch_speed());
+    assert_eq!(mmu.read(::io::KEY1), 0xFE);
+    assert!(!mmu.switch_speed());
+}
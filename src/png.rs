@@ -0,0 +1,102 @@
+// PNG Files
+// --- -----
+// Just enough of PNG to save greyscale images: the pixel data goes in
+// uncompressed ("stored") deflate blocks, so nothing here needs zlib.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// The most a stored deflate block can hold
+const MAX_BLOCK_SIZE : usize = 0xFFFF;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut crc_input = kind.to_vec();
+    crc_input.extend_from_slice(data);
+    writer.write_all(&crc_input)?;
+    writer.write_all(&crc32(&crc_input).to_be_bytes())
+}
+
+/// Write an 8-bit greyscale image, one byte a pixel, row by row.
+pub fn write_greyscale<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[u8])
+                                 -> io::Result<()> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+    writer.write_all(b"\x89PNG\r\n\x1A\n")?;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]); // 8 bits, greyscale, no interlacing
+    write_chunk(writer, b"IHDR", &header)?;
+
+    // Every row starts with its filter type: 0, for none
+    let mut raw = Vec::with_capacity(pixels.len() + height as usize);
+    for row in pixels.chunks(width.max(1) as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(MAX_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        zlib.push(last as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+    write_chunk(writer, b"IDAT", &zlib)?;
+
+    write_chunk(writer, b"IEND", &[])
+}
+
+pub fn save_greyscale<P: AsRef<Path>>(path: P, width: u32, height: u32, pixels: &[u8])
+                                      -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_greyscale(&mut writer, width, height, pixels)?;
+    writer.flush()
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+}
+
+#[test]
+fn test_writing_a_png() {
+    let mut png = Vec::new();
+    write_greyscale(&mut png, 2, 2, &[0x00, 0xFF, 0x80, 0x40]).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1A\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    // IDAT: the zlib header, one last stored block of two 3-byte rows
+    assert_eq!(&png[33..37], 17u32.to_be_bytes());
+    assert_eq!(&png[37..41], b"IDAT");
+    assert_eq!(png[41..48], [0x78, 0x01, 0x01, 0x06, 0x00, 0xF9, 0xFF]);
+    assert_eq!(png[48..54], [0x00, 0x00, 0xFF, 0x00, 0x80, 0x40]);
+    assert_eq!(&png[png.len() - 12..], b"\x00\x00\x00\x00IEND\xAE\x42\x60\x82");
+}
@@ -0,0 +1,362 @@
+// The Serial Port
+// --- ------ ----
+// The link cable swaps a byte at a time between two Game Boys. Whichever
+// one uses its internal clock (bit 0 of SC) drives the transfer: eight
+// bits, shifted out of the top of SB while the other side's come in at the
+// bottom, at 8192 Hz. Setting bit 7 of SC starts a transfer, and it's
+// cleared again (with a serial interrupt) once all eight bits are across.
+//
+// What's on the other end of the cable is a `SerialLink`.
+
+pub mod printer;
+pub mod tcp;
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+use io;
+
+// SC
+const TRANSFER       : u8 = 0x80;
+const INTERNAL_CLOCK : u8 = 0x01;
+
+/// How long each bit takes with the internal clock: 8192 Hz
+const CYCLES_PER_BIT : u32 = 512;
+
+/// Whatever's plugged into the link port. Transfers happen a byte at a
+/// time: the timing of the individual bits is up to `Serial`.
+pub trait SerialLink {
+    /// We're driving the clock: send `byte`, and return what came back.
+    fn transfer(&mut self, byte: u8) -> u8;
+    /// The other side drives the clock. If it's started a transfer, send it
+    /// `byte` and return what it sent. Only called while we're waiting for
+    /// one.
+    fn poll(&mut self, byte: u8) -> Option<u8>;
+}
+
+/// Nothing plugged in. Transfers we drive read all 1s, and nobody else
+/// ever starts one.
+pub struct Disconnected;
+
+impl SerialLink for Disconnected {
+    fn transfer(&mut self, _byte: u8) -> u8 {
+        0xFF
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Keeps whatever the game sends, like the output of test ROMs that print
+/// over serial. Reads back like nothing's plugged in.
+pub struct CaptureLink {
+    output: Rc<RefCell<Vec<u8>>>,
+    stdout: bool,
+}
+
+impl Default for CaptureLink {
+    fn default() -> CaptureLink {
+        CaptureLink::new()
+    }
+}
+
+impl CaptureLink {
+    pub fn new() -> CaptureLink {
+        CaptureLink { output: Rc::new(RefCell::new(Vec::new())), stdout: false }
+    }
+
+    /// Print everything to stdout, as well as keeping it.
+    pub fn stdout() -> CaptureLink {
+        CaptureLink { stdout: true, ..CaptureLink::new() }
+    }
+
+    /// Everything sent so far, which carries on filling up after the link is
+    /// handed over to the emulator.
+    pub fn output(&self) -> Rc<RefCell<Vec<u8>>> {
+        self.output.clone()
+    }
+}
+
+impl SerialLink for CaptureLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.output.borrow_mut().push(byte);
+        if self.stdout {
+            let mut stdout = ::std::io::stdout();
+            let _ = stdout.write_all(&[byte]);
+            let _ = stdout.flush();
+        }
+        0xFF
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// A cable plugged back into the same Game Boy: every byte sent comes
+/// straight back.
+pub struct LoopbackLink;
+
+impl SerialLink for LoopbackLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        byte
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        // Nothing on the other end to drive the clock
+        None
+    }
+}
+
+/// Like `CaptureLink`, but written straight to a file as it comes.
+pub struct FileLink {
+    file: File,
+}
+
+impl FileLink {
+    pub fn create<P: AsRef<Path>>(path: P) -> ::std::io::Result<FileLink> {
+        Ok(FileLink { file: File::create(path)? })
+    }
+}
+
+impl SerialLink for FileLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        // There's no one to tell if this fails, and the game can't tell
+        let _ = self.file.write_all(&[byte]);
+        0xFF
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+pub struct Serial {
+    data: u8,    // SB
+    control: u8, // SC
+    link: Box<dyn SerialLink>,
+    // The byte on its way in, and how much of it is left
+    incoming: u8,
+    bits_left: u8,
+    timer: u32,
+    // Until the link is next asked if the other side's started a transfer
+    poll_timer: u32,
+}
+
+impl Default for Serial {
+    fn default() -> Serial {
+        Serial::new()
+    }
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            data: 0x00,
+            control: 0x00,
+            link: Box::new(Disconnected),
+            incoming: 0xFF,
+            bits_left: 0,
+            timer: 0,
+            poll_timer: 0,
+        }
+    }
+
+    /// Plug something into the link port.
+    pub fn set_link(&mut self, link: Box<dyn SerialLink>) {
+        self.link = link;
+    }
+
+    fn start(&mut self, incoming: u8) {
+        self.incoming = incoming;
+        self.bits_left = 8;
+        self.timer = CYCLES_PER_BIT;
+    }
+
+    /// Let `cycles` T-cycles pass. Returns whether the serial interrupt
+    /// should be requested.
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        if self.control & TRANSFER == 0 {
+            return false;
+        }
+        if self.bits_left == 0 {
+            // Waiting for the other side to clock a byte over. Asking the
+            // link can mean a syscall, so only do it once a bit's worth of
+            // time: the other side can't have got further than that.
+            self.poll_timer = self.poll_timer.saturating_sub(cycles);
+            if self.poll_timer > 0 {
+                return false;
+            }
+            self.poll_timer = CYCLES_PER_BIT;
+            match self.link.poll(self.data) {
+                Some(incoming) => self.start(incoming),
+                None => return false,
+            }
+        }
+        let mut remaining = cycles;
+        while remaining >= self.timer {
+            remaining -= self.timer;
+            self.timer = CYCLES_PER_BIT;
+            // Shift out the top bit, and the other side's top bit in
+            self.data = (self.data << 1) | (self.incoming >> 7);
+            self.incoming <<= 1;
+            self.bits_left -= 1;
+            if self.bits_left == 0 {
+                self.control &= !TRANSFER;
+                return true;
+            }
+        }
+        self.timer -= remaining;
+        false
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            io::SB => self.data,
+            io::SC => self.control,
+            _      => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, val: u8) {
+        match address {
+            io::SB => self.data = val,
+            io::SC => {
+                self.control = val & (TRANSFER | INTERNAL_CLOCK);
+                if val & (TRANSFER | INTERNAL_CLOCK) == TRANSFER | INTERNAL_CLOCK {
+                    let incoming = self.link.transfer(self.data);
+                    self.start(incoming);
+                } else {
+                    self.bits_left = 0;
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// The other end of a cable, for tests: sends back `reply`, and starts
+/// one transfer of its own with `send`.
+#[cfg(test)]
+struct TestLink {
+    reply: u8,
+    send: Option<u8>,
+    received: Rc<RefCell<Vec<u8>>>,
+    polls: Rc<::std::cell::Cell<u32>>,
+}
+
+#[cfg(test)]
+impl SerialLink for TestLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.received.borrow_mut().push(byte);
+        self.reply
+    }
+
+    fn poll(&mut self, byte: u8) -> Option<u8> {
+        self.polls.set(self.polls.get() + 1);
+        let send = self.send.take();
+        if send.is_some() {
+            self.received.borrow_mut().push(byte);
+        }
+        send
+    }
+}
+
+#[test]
+fn test_a_transfer_takes_eight_bits() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let mut serial = Serial::new();
+    serial.set_link(Box::new(TestLink {
+        reply: 0x5A, send: None, received: received.clone(), polls: Rc::new(::std::cell::Cell::new(0)),
+    }));
+    serial.write_register(io::SB, 0x81);
+    serial.write_register(io::SC, 0x81);
+    assert_eq!(*received.borrow(), vec![0x81]);
+    assert!(!serial.tick(CYCLES_PER_BIT * 4));
+    // Half of each byte is across
+    assert_eq!(serial.read_register(io::SB), 0x15);
+    assert_eq!(serial.read_register(io::SC), 0x81);
+    assert!(!serial.tick(CYCLES_PER_BIT * 4 - 4));
+    assert!(serial.tick(4));
+    assert_eq!(serial.read_register(io::SB), 0x5A);
+    assert_eq!(serial.read_register(io::SC), 0x01);
+}
+
+#[test]
+fn test_the_other_side_can_drive_the_clock() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let mut serial = Serial::new();
+    serial.set_link(Box::new(TestLink {
+        reply: 0x00, send: Some(0x42), received: received.clone(), polls: Rc::new(::std::cell::Cell::new(0)),
+    }));
+    serial.write_register(io::SB, 0x24);
+    // Nothing happens until the transfer's enabled
+    assert!(!serial.tick(CYCLES_PER_BIT * 8));
+    serial.write_register(io::SC, 0x80);
+    assert!(serial.tick(CYCLES_PER_BIT * 8));
+    assert_eq!(serial.read_register(io::SB), 0x42);
+    assert_eq!(*received.borrow(), vec![0x24]);
+}
+
+#[test]
+fn test_nothing_plugged_in_reads_ff() {
+    let mut serial = Serial::new();
+    serial.write_register(io::SB, 0x00);
+    serial.write_register(io::SC, 0x81);
+    assert!(serial.tick(CYCLES_PER_BIT * 8));
+    assert_eq!(serial.read_register(io::SB), 0xFF);
+    // With the external clock, it waits forever
+    serial.write_register(io::SC, 0x80);
+    assert!(!serial.tick(CYCLES_PER_BIT * 100));
+    assert_eq!(serial.read_register(io::SC), 0x80);
+}
+
+#[test]
+fn test_capturing_what_the_game_sends() {
+    let mut link = CaptureLink::new();
+    let output = link.output();
+    assert_eq!(link.transfer(b'o'), 0xFF);
+    link.transfer(b'k');
+    assert_eq!(*output.borrow(), b"ok".to_vec());
+}
+
+#[test]
+fn test_waiting_for_the_other_side_polls_once_a_bit() {
+    let polls = Rc::new(::std::cell::Cell::new(0));
+    let mut serial = Serial::new();
+    serial.set_link(Box::new(TestLink {
+        reply: 0x00, send: None, received: Rc::new(RefCell::new(Vec::new())), polls: polls.clone(),
+    }));
+    serial.write_register(io::SC, 0x80);
+    // Four bits' worth of time, a few cycles at a time
+    for _ in 0..CYCLES_PER_BIT {
+        serial.tick(4);
+    }
+    assert_eq!(polls.get(), 4);
+}
+
+#[test]
+fn test_a_loopback_cable_reads_back_what_was_sent() {
+    let mut serial = Serial::new();
+    serial.set_link(Box::new(LoopbackLink));
+    serial.write_register(io::SB, 0xA5);
+    serial.write_register(io::SC, 0x81);
+    assert!(serial.tick(CYCLES_PER_BIT * 8));
+    assert_eq!(serial.read_register(io::SB), 0xA5);
+}
+
+#[test]
+fn test_capturing_to_a_file() {
+    let path = ::std::env::temp_dir().join(format!("gb-{}-serial.txt", ::std::process::id()));
+    {
+        let mut link = FileLink::create(&path).unwrap();
+        assert_eq!(link.transfer(b'o'), 0xFF);
+        link.transfer(b'k');
+    }
+    assert_eq!(::std::fs::read(&path).unwrap(), b"ok".to_vec());
+    ::std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,337 @@
+// The Game Boy Printer
+// --- --- ---- -------
+// The game drives the clock and sends packets:
+//
+//   0x88 0x33 command compression length(2) data... checksum(2) 0x00 0x00
+//
+// The checksum is the sum of everything from the command to the end of
+// the data. The printer sends 0x00 back for all of that, then 0x81 to say
+// it's there, and finally its status. Image data comes as 2bpp tiles, 20
+// to a row of the strip; printing it saves the strip as a PNG.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serial::SerialLink;
+
+// Commands
+const INITIALIZE : u8 = 0x01;
+const PRINT      : u8 = 0x02;
+const DATA       : u8 = 0x04;
+const STATUS     : u8 = 0x0F;
+
+// Status bits
+const CHECKSUM_ERROR   : u8 = 0x01;
+const PRINTING         : u8 = 0x02;
+const UNPROCESSED_DATA : u8 = 0x08;
+const OTHER_ERROR      : u8 = 0x40;
+
+/// The printer's buffer holds nine rows of tiles
+const MAX_DATA : usize = 0x280 * 9;
+
+const STRIP_WIDTH : usize = 160;
+
+/// How many status checks the printer stays busy for after printing
+const PRINT_POLLS : u8 = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+pub struct Printer {
+    output_dir: PathBuf,
+    printed: Rc<RefCell<Vec<PathBuf>>>,
+    state: State,
+    // The packet coming in
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    // Decompressed tiles waiting to be printed
+    buffer: Vec<u8>,
+    status: u8,
+    busy_polls: u8,
+}
+
+/// Undo the printer's run-length encoding. A control byte with bit 7 set
+/// repeats the next byte (control & 0x7F) + 2 times; otherwise, the next
+/// control + 1 bytes are copied as they are.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 != 0 {
+            if let Some(&byte) = data.get(i) {
+                out.extend(::std::iter::repeat_n(byte, (control & 0x7F) as usize + 2));
+            }
+            i += 1;
+        } else {
+            let end = (i + control as usize + 1).min(data.len());
+            out.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    out
+}
+
+impl Printer {
+    /// Printed strips go in `output_dir`, as print-001.png, print-002.png...
+    pub fn new<P: AsRef<Path>>(output_dir: P) -> Printer {
+        Printer {
+            output_dir: output_dir.as_ref().to_path_buf(),
+            printed: Rc::new(RefCell::new(Vec::new())),
+            state: State::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            buffer: Vec::new(),
+            status: 0x00,
+            busy_polls: 0,
+        }
+    }
+
+    /// The files printed so far, which carries on filling up after the
+    /// printer is plugged in.
+    pub fn printed(&self) -> Rc<RefCell<Vec<PathBuf>>> {
+        self.printed.clone()
+    }
+
+    fn run_command(&mut self) {
+        match self.command {
+            INITIALIZE => {
+                self.buffer.clear();
+                self.status = 0x00;
+                self.busy_polls = 0;
+            },
+            DATA => {
+                // An empty data packet just marks the end of the image
+                let data = if self.compressed { decompress(&self.data) } else { self.data.clone() };
+                let room = MAX_DATA - self.buffer.len();
+                self.buffer.extend_from_slice(&data[..data.len().min(room)]);
+                if !self.buffer.is_empty() {
+                    self.status |= UNPROCESSED_DATA;
+                }
+            },
+            PRINT => {
+                // Sheets, margins, palette, exposure
+                let palette = match self.data.get(2) {
+                    Some(&0x00) | None => 0xE4,
+                    Some(&palette) => palette,
+                };
+                if !self.buffer.is_empty() {
+                    if self.print(palette).is_err() {
+                        self.status |= OTHER_ERROR;
+                    }
+                    self.buffer.clear();
+                }
+                self.status = (self.status & !UNPROCESSED_DATA) | PRINTING;
+                self.busy_polls = PRINT_POLLS;
+            },
+            STATUS if self.busy_polls > 0 => {
+                self.busy_polls -= 1;
+                if self.busy_polls == 0 {
+                    self.status &= !PRINTING;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// The buffer as shades from 0 (white) to 3 (black), a byte a pixel
+    fn strip(&self, palette: u8) -> Vec<u8> {
+        let tile_rows = self.buffer.len() / (20 * 16);
+        let mut pixels = vec![0; STRIP_WIDTH * tile_rows * 8];
+        for (tile, bytes) in self.buffer.chunks(16).take(tile_rows * 20).enumerate() {
+            let (tile_x, tile_y) = (tile % 20, tile / 20);
+            for row in 0..8 {
+                let (low, high) = (bytes[row * 2], bytes[row * 2 + 1]);
+                for column in 0..8 {
+                    let bit = 7 - column;
+                    let color = ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+                    let shade = (palette >> (color * 2)) & 0x03;
+                    let y = tile_y * 8 + row;
+                    pixels[y * STRIP_WIDTH + tile_x * 8 + column] = shade;
+                }
+            }
+        }
+        pixels
+    }
+
+    fn print(&mut self, palette: u8) -> ::std::io::Result<()> {
+        let pixels: Vec<u8> = self.strip(palette).iter().map(|&shade| 0xFF - shade * 0x55).collect();
+        let height = (pixels.len() / STRIP_WIDTH) as u32;
+        let number = self.printed.borrow().len() + 1;
+        let path = self.output_dir.join(format!("print-{:03}.png", number));
+        ::png::save_greyscale(&path, STRIP_WIDTH as u32, height, &pixels)?;
+        self.printed.borrow_mut().push(path);
+        Ok(())
+    }
+}
+
+impl SerialLink for Printer {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+        self.state = match self.state {
+            State::Magic1 => if byte == 0x88 { State::Magic2 } else { State::Magic1 },
+            State::Magic2 => if byte == 0x33 { State::Command } else { State::Magic1 },
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                State::Compression
+            },
+            State::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthLow
+            },
+            State::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthHigh
+            },
+            State::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.clear();
+                if self.length == 0 { State::ChecksumLow } else { State::Data }
+            },
+            State::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() == self.length as usize { State::ChecksumLow } else { State::Data }
+            },
+            State::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                State::ChecksumHigh
+            },
+            State::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                State::Alive
+            },
+            State::Alive => {
+                reply = 0x81;
+                State::Status
+            },
+            State::Status => {
+                if self.received_checksum == self.checksum {
+                    self.status &= !CHECKSUM_ERROR;
+                    self.run_command();
+                } else {
+                    self.status |= CHECKSUM_ERROR;
+                }
+                reply = self.status;
+                State::Magic1
+            },
+        };
+        reply
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        // The printer never drives the clock
+        None
+    }
+}
+
+/// A whole packet, as the game would send it
+#[cfg(test)]
+fn packet(command: u8, compressed: bool, data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x88, 0x33, command, compressed as u8,
+                          data.len() as u8, (data.len() >> 8) as u8];
+    packet.extend_from_slice(data);
+    let checksum = packet[2..].iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    packet.extend_from_slice(&[checksum as u8, (checksum >> 8) as u8, 0x00, 0x00]);
+    packet
+}
+
+#[cfg(test)]
+fn send(printer: &mut Printer, packet: &[u8]) -> Vec<u8> {
+    packet.iter().map(|&byte| printer.transfer(byte)).collect()
+}
+
+#[test]
+fn test_decompressing() {
+    assert_eq!(decompress(&[0x81, 0xAA, 0x01, 0x12, 0x34]), vec![0xAA, 0xAA, 0xAA, 0x12, 0x34]);
+}
+
+#[test]
+fn test_the_printer_answers_packets() {
+    let mut printer = Printer::new(::std::env::temp_dir());
+    let reply = send(&mut printer, &packet(INITIALIZE, false, &[]));
+    assert_eq!(reply[..8], [0x00; 8]);
+    assert_eq!(reply[8..], [0x81, 0x00]);
+    let mut bad = packet(STATUS, false, &[]);
+    bad[6] ^= 0xFF;
+    assert_eq!(send(&mut printer, &bad)[9], CHECKSUM_ERROR);
+    assert_eq!(send(&mut printer, &packet(STATUS, false, &[]))[9], 0x00);
+}
+
+#[test]
+fn test_printing_a_strip() {
+    let dir = ::std::env::temp_dir().join(format!("gb-{}-printer", ::std::process::id()));
+    ::std::fs::create_dir_all(&dir).unwrap();
+    let mut printer = Printer::new(&dir);
+    let printed = printer.printed();
+    send(&mut printer, &packet(INITIALIZE, false, &[]));
+    // Two rows of tiles, all color 3 but the very first row of pixels
+    let mut compressed = vec![0x01, 0x00, 0x00];
+    let mut left = 0x280 - 2;
+    while left > 0 {
+        let run = left.min(0x7F + 2);
+        compressed.extend_from_slice(&[0x80 | (run - 2) as u8, 0xFF]);
+        left -= run;
+    }
+    let reply = send(&mut printer, &packet(DATA, true, &compressed));
+    assert_eq!(reply[reply.len() - 1], UNPROCESSED_DATA);
+    assert_eq!(printer.buffer.len(), 0x280);
+    let strip = printer.strip(0xE4);
+    assert_eq!(strip.len(), 160 * 16);
+    assert_eq!(strip[..9], [0, 0, 0, 0, 0, 0, 0, 0, 3]);
+    assert_eq!(strip[160], 3);
+    send(&mut printer, &packet(DATA, false, &[]));
+    let reply = send(&mut printer, &packet(PRINT, false, &[0x01, 0x13, 0xE4, 0x40]));
+    assert_eq!(reply[reply.len() - 1], PRINTING);
+    assert_eq!(*printed.borrow(), vec![dir.join("print-001.png")]);
+    let png = ::std::fs::read(dir.join("print-001.png")).unwrap();
+    assert_eq!(png[16..24], [0, 0, 0, 160, 0, 0, 0, 16]);
+    // Busy for a few status checks, then done
+    for _ in 1..PRINT_POLLS {
+        assert_eq!(send(&mut printer, &packet(STATUS, false, &[]))[9], PRINTING);
+    }
+    assert_eq!(send(&mut printer, &packet(STATUS, false, &[]))[9], 0x00);
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_printing_through_the_serial_port() {
+    let mut mmu = ::mmu::MMU::new();
+    mmu.serial_mut().set_link(Box::new(Printer::new(::std::env::temp_dir())));
+    let mut replies = Vec::new();
+    for byte in packet(INITIALIZE, false, &[]) {
+        mmu.write_byte(::io::SB, byte);
+        mmu.write_byte(::io::SC, 0x81);
+        while mmu.read(::io::SC) & 0x80 != 0 {
+            mmu.tick(4);
+        }
+        replies.push(mmu.read(::io::SB));
+    }
+    assert_eq!(replies[8..], [0x81, 0x00]);
+}
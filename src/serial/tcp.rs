@@ -0,0 +1,100 @@
+// A link cable over TCP, so two emulators can talk over localhost. Each
+// transfer is one byte each way: the side driving the clock sends its byte
+// and waits for the reply, which the other side sends once it's ready for
+// a transfer. There's no giving up on a slow reply: it would still turn up
+// later, and be taken for the reply to the next byte.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use serial::SerialLink;
+
+pub struct TcpLink {
+    stream: TcpStream,
+}
+
+impl TcpLink {
+    /// Wait for the other emulator to connect.
+    pub fn listen<A: ToSocketAddrs>(address: A) -> io::Result<TcpLink> {
+        let (stream, _) = TcpListener::bind(address)?.accept()?;
+        TcpLink::from_stream(stream)
+    }
+
+    /// Connect to an emulator that's listening.
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<TcpLink> {
+        TcpLink::from_stream(TcpStream::connect(address)?)
+    }
+
+    fn from_stream(stream: TcpStream) -> io::Result<TcpLink> {
+        stream.set_nodelay(true)?;
+        Ok(TcpLink { stream })
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+impl SerialLink for TcpLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        // Only a closed connection reads like a loose cable
+        let reply = self.stream.set_nonblocking(false)
+            .and_then(|_| self.stream.write_all(&[byte]))
+            .and_then(|_| self.read_byte());
+        reply.unwrap_or(0xFF)
+    }
+
+    fn poll(&mut self, byte: u8) -> Option<u8> {
+        self.stream.set_nonblocking(true).ok()?;
+        let incoming = self.read_byte();
+        self.stream.set_nonblocking(false).ok()?;
+        let incoming = incoming.ok()?;
+        self.stream.write_all(&[byte]).ok()?;
+        Some(incoming)
+    }
+}
+
+#[test]
+fn test_two_links_trade_bytes() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let other = ::std::thread::spawn(move || {
+        let mut link = TcpLink::from_stream(listener.accept().unwrap().0).unwrap();
+        loop {
+            if let Some(byte) = link.poll(0x99) {
+                return byte;
+            }
+        }
+    });
+    let mut link = TcpLink::connect(address).unwrap();
+    assert_eq!(link.transfer(0x42), 0x99);
+    assert_eq!(other.join().unwrap(), 0x42);
+}
+
+#[test]
+fn test_a_slow_reply_is_waited_for() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let other = ::std::thread::spawn(move || {
+        let mut link = TcpLink::from_stream(listener.accept().unwrap().0).unwrap();
+        // Longer than anyone would want to time out after
+        ::std::thread::sleep(::std::time::Duration::from_millis(600));
+        let mut received = Vec::new();
+        for reply in [0x99, 0x98].iter() {
+            loop {
+                if let Some(byte) = link.poll(*reply) {
+                    received.push(byte);
+                    break;
+                }
+            }
+        }
+        received
+    });
+    let mut link = TcpLink::connect(address).unwrap();
+    assert_eq!(link.transfer(0x42), 0x99);
+    // ...and the two sides are still in step
+    assert_eq!(link.transfer(0x43), 0x98);
+    assert_eq!(other.join().unwrap(), vec![0x42, 0x43]);
+}
@@ -0,0 +1,273 @@
+// The Timer
+// --- -----
+// DIV is the top half of a 16-bit counter that goes up every clock cycle.
+// TIMA counts up whenever one of that counter's bits (picked by TAC) goes
+// from 1 to 0, and when it overflows it's reloaded from TMA and the timer
+// interrupt is requested. Since it's the falling edge that counts, anything
+// that knocks that bit from 1 to 0 counts too: resetting DIV, disabling
+// the timer, or switching to another bit.
+
+use io;
+
+// TAC
+const TIMER_ENABLE : u8 = 0x04;
+const CLOCK_SELECT : u8 = 0x03;
+
+pub struct Timer {
+    counter: u16, // DIV is the top eight bits
+    tima: u8,     // Timer counter
+    tma: u8,      // Timer modulo, reloaded into TIMA when it overflows
+    tac: u8,      // Timer control
+    // TIMA reads 0 for one M-cycle after it overflows, and only then is
+    // reloaded. Writing to TIMA in the meantime cancels the reload.
+    overflowed: bool,
+    // During the M-cycle TIMA is reloaded, writes to TIMA are ignored,
+    // and writes to TMA go straight through to TIMA as well.
+    reloading: bool,
+    // T-cycles that haven't made up a whole M-cycle yet
+    leftover: u32,
+}
+
+impl Default for Timer {
+    fn default() -> Timer {
+        Timer::new()
+    }
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            counter: 0x0000,
+            tima: 0x00,
+            tma: 0x00,
+            tac: 0x00,
+            overflowed: false,
+            reloading: false,
+            leftover: 0,
+        }
+    }
+
+    /// Let `cycles` T-cycles pass. Returns whether TIMA overflowed, and
+    /// so the timer interrupt should be requested.
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        let mut interrupt = false;
+        // Everything here happens once an M-cycle
+        self.leftover += cycles;
+        let m_cycles = self.leftover / 4;
+        self.leftover %= 4;
+        for _ in 0..m_cycles {
+            self.reloading = false;
+            if self.overflowed {
+                self.overflowed = false;
+                self.reloading = true;
+                self.tima = self.tma;
+                interrupt = true;
+            }
+            let before = self.signal();
+            self.counter = self.counter.wrapping_add(4);
+            self.falling_edge(before);
+        }
+        interrupt
+    }
+
+    /// The bit TIMA is watching, and-ed with whether the timer is on
+    fn signal(&self) -> bool {
+        let bit = match self.tac & CLOCK_SELECT {
+            0x00 => 9, // 4096 Hz
+            0x01 => 3, // 262144 Hz
+            0x02 => 5, // 65536 Hz
+            _    => 7, // 16384 Hz
+        };
+        self.tac & TIMER_ENABLE != 0 && self.counter & (1 << bit) != 0
+    }
+
+    fn falling_edge(&mut self, before: bool) {
+        if before && !self.signal() {
+            let (tima, overflowed) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            if overflowed {
+                self.overflowed = true;
+            }
+        }
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            io::DIV  => (self.counter >> 8) as u8,
+            io::TIMA => self.tima,
+            io::TMA  => self.tma,
+            io::TAC  => self.tac,
+            _        => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, val: u8) {
+        let before = self.signal();
+        match address {
+            // Any write resets the whole counter
+            io::DIV => self.counter = 0,
+            io::TIMA if !self.reloading => {
+                self.tima = val;
+                self.overflowed = false;
+            },
+            io::TMA => {
+                self.tma = val;
+                if self.reloading {
+                    self.tima = val;
+                }
+            },
+            io::TAC => self.tac = val & 0x07,
+            _ => {},
+        }
+        self.falling_edge(before);
+    }
+}
+
+#[test]
+fn test_div_counts_every_256_cycles() {
+    let mut timer = Timer::new();
+    timer.tick(252);
+    assert_eq!(timer.read_register(io::DIV), 0);
+    timer.tick(4);
+    assert_eq!(timer.read_register(io::DIV), 1);
+    timer.write_register(io::DIV, 0x42);
+    assert_eq!(timer.read_register(io::DIV), 0);
+}
+
+#[test]
+fn test_odd_cycles_add_up() {
+    let mut timer = Timer::new();
+    for _ in 0..128 {
+        timer.tick(2);
+    }
+    assert_eq!(timer.read_register(io::DIV), 1);
+}
+
+#[test]
+fn test_tima_counts_at_the_selected_rate() {
+    let mut timer = Timer::new();
+    timer.write_register(io::TAC, TIMER_ENABLE | 0x01);
+    timer.tick(16 * 10);
+    assert_eq!(timer.read_register(io::TIMA), 10);
+    // Nothing happens while the timer is off
+    timer.write_register(io::TAC, 0x01);
+    timer.tick(16 * 10);
+    assert_eq!(timer.read_register(io::TIMA), 10);
+    timer.write_register(io::DIV, 0x00);
+    timer.write_register(io::TAC, TIMER_ENABLE);
+    timer.tick(1024 - 4);
+    assert_eq!(timer.read_register(io::TIMA), 10);
+    timer.tick(4);
+    assert_eq!(timer.read_register(io::TIMA), 11);
+}
+
+#[test]
+fn test_tima_reloads_from_tma_a_cycle_after_overflowing() {
+    let mut timer = Timer::new();
+    timer.write_register(io::TMA, 0x80);
+    timer.write_register(io::TIMA, 0xFF);
+    timer.write_register(io::TAC, TIMER_ENABLE | 0x01);
+    assert!(!timer.tick(16));
+    // It's 0 for a cycle...
+    assert_eq!(timer.read_register(io::TIMA), 0x00);
+    // ...and then reloaded, along with the interrupt
+    assert!(timer.tick(4));
+    assert_eq!(timer.read_register(io::TIMA), 0x80);
+}
+
+#[cfg(test)]
+fn overflowing_timer() -> Timer {
+    let mut timer = Timer::new();
+    timer.write_register(io::TMA, 0x80);
+    timer.write_register(io::TIMA, 0xFF);
+    timer.write_register(io::TAC, TIMER_ENABLE | 0x01);
+    timer.tick(16);
+    timer
+}
+
+#[test]
+fn test_writing_tima_before_it_reloads() {
+    let mut timer = overflowing_timer();
+    // Writing before the reload cancels it
+    timer.write_register(io::TIMA, 0x42);
+    assert!(!timer.tick(4));
+    assert_eq!(timer.read_register(io::TIMA), 0x42);
+}
+
+#[test]
+fn test_writing_tima_while_it_reloads() {
+    let mut timer = overflowing_timer();
+    timer.tick(4);
+    // Writing during the reload is ignored, but writing TMA isn't
+    timer.write_register(io::TIMA, 0x42);
+    assert_eq!(timer.read_register(io::TIMA), 0x80);
+    timer.write_register(io::TMA, 0x90);
+    assert_eq!(timer.read_register(io::TIMA), 0x90);
+}
+
+#[test]
+fn test_resetting_div_can_tick_tima() {
+    let mut timer = Timer::new();
+    timer.write_register(io::TAC, TIMER_ENABLE | 0x01);
+    timer.tick(8);
+    // Bit 3 is set, so knocking it back to 0 counts as a tick
+    timer.write_register(io::DIV, 0x00);
+    assert_eq!(timer.read_register(io::TIMA), 1);
+}
+
+#[test]
+fn test_changing_tac_can_tick_tima() {
+    let mut timer = Timer::new();
+    timer.write_register(io::TAC, TIMER_ENABLE | 0x01);
+    timer.tick(8);
+    // Turning the timer off while the bit is set...
+    timer.write_register(io::TAC, 0x01);
+    assert_eq!(timer.read_register(io::TIMA), 1);
+    // ...or switching to a bit that isn't
+    timer.write_register(io::TAC, TIMER_ENABLE | 0x01);
+    timer.write_register(io::TAC, TIMER_ENABLE | 0x02);
+    assert_eq!(timer.read_register(io::TIMA), 2);
+}
+
+/// Mooneye's timer tests, from acceptance/timer in
+/// https://github.com/Gekkio/mooneye-test-suite. They aren't in the repo, so
+/// this only runs with `cargo test -- --ignored`, and fails if it can't
+/// find them. Point MOONEYE_TIMER_ROMS at wherever they're built.
+#[test]
+#[ignore]
+fn test_mooneye_timer_roms() {
+    let dir = ::std::env::var("MOONEYE_TIMER_ROMS")
+        .unwrap_or_else(|_| "test-roms/mooneye/acceptance/timer".to_string());
+    let entries = match ::std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) => panic!("can't find the Mooneye timer tests, {}: {}", dir, e),
+    };
+    let mut roms: Vec<_> = entries.map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "gb"))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty(), "no .gb files in {}", dir);
+    let mut failed = Vec::new();
+    for path in &roms {
+        let mut cpu = ::cpu::Z80::new();
+        cpu.load_cartridge(::cartridge::Cartridge::open(path).unwrap());
+        cpu.skip_boot(::boot::Model::Dmg);
+        let link = ::serial::CaptureLink::new();
+        let output = link.output();
+        cpu.set_serial_link(Box::new(link));
+        // They send the Fibonacci numbers when they pass, and six 0x42s
+        // when they don't. Give them 20 seconds.
+        for _ in 0..60 * 20 {
+            cpu.run_frame();
+            if output.borrow().len() >= 6 {
+                break;
+            }
+        }
+        let passed = output.borrow().starts_with(&[3, 5, 8, 13, 21, 34]);
+        println!("{}: {}", path.display(), if passed { "ok" } else { "FAILED" });
+        if !passed {
+            failed.push(path);
+        }
+    }
+    assert!(failed.is_empty(), "{} of {} failed: {:?}", failed.len(), roms.len(), failed);
+}